@@ -32,7 +32,7 @@ fn main() -> Result<()> {
     let (mut backend, mut input) = winit::init(None)?;
     // create an `EguiState`. Usually this would be part of your global smithay state
     let mut wallpaper = WallpaperState::new();
-    wallpaper.set("bg.png");
+    wallpaper.set("bg.png")?;
     // this is likely already part of your ui-state for `send_frames` and similar
     let start_time = std::time::Instant::now();
     // We need to track the current set of modifiers, because egui expects them to be passed for many events
@@ -69,20 +69,27 @@ fn main() -> Result<()> {
             // Just render it over the whole window, but you may limit the area
             Rectangle::from_loc_and_size((0, 0), size.to_logical(1)),
             size,
+            1.0,
+            Transform::Flipped180,
         );
 
         // Lastly put the rendered frame on the screen
         backend.bind()?;
         let renderer = backend.renderer();
-        renderer
-            .render(size, Transform::Flipped180, |renderer, frame| {
-                frame.clear(
-                    [1.0, 1.0, 1.0, 1.0],
-                    &[Rectangle::from_loc_and_size((0, 0), size)],
-                )?;
-                unsafe { wallpaper_frame.draw(renderer, frame) }
-            })?
-            .map_err(|err| anyhow::format_err!("{}", err))?;
+        renderer.render(
+            size,
+            Transform::Flipped180,
+            |renderer, frame| -> Result<()> {
+                frame
+                    .clear(
+                        [1.0, 1.0, 1.0, 1.0],
+                        &[Rectangle::from_loc_and_size((0, 0), size)],
+                    )
+                    .map_err(|err| anyhow::format_err!("{}", err))?;
+                wallpaper_frame.draw(renderer, frame)?;
+                Ok(())
+            },
+        )??;
         backend.submit(None, 1.0)?;
     }
 }
@@ -0,0 +1,146 @@
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+use smithay::utils::{Buffer, Rectangle};
+
+/// Frames of an animated wallpaper and the playback position
+#[derive(Debug)]
+pub(crate) struct Animation {
+    frames: Vec<(Rc<Option<DynamicImage>>, Duration)>,
+    /// Region each frame differs from the one before in, found once it is first shown
+    changes: Vec<Option<Option<Rectangle<i32, Buffer>>>>,
+    index: usize,
+    /// The frame shown before, if the animation advanced by a single frame
+    previous: Option<usize>,
+    shown: Instant,
+    /// When the animation was paused, it stays at the current frame until resumed
+    paused: Option<Instant>,
+}
+
+impl Animation {
+    /// Starts showing the first frame at `now`
+    pub fn new(frames: Vec<(DynamicImage, Duration)>, now: Instant) -> Self {
+        Self {
+            changes: vec![None; frames.len()],
+            frames: frames
+                .into_iter()
+                .map(|(image, delay)| (Rc::new(Some(image)), delay))
+                .collect(),
+            index: 0,
+            previous: None,
+            shown: now,
+            paused: None,
+        }
+    }
+
+    /// Returns the index of the currently displayed frame
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the currently displayed frame
+    pub fn current(&self) -> Rc<Option<DynamicImage>> {
+        self.frames[self.index].0.clone()
+    }
+
+    /// Returns when the next frame is due
+    pub fn deadline(&self) -> Instant {
+        self.shown + self.frames[self.index].1
+    }
+
+    /// Stops at the current frame until [`Animation::resume`] is called
+    pub fn pause(&mut self, now: Instant) {
+        if self.paused.is_none() {
+            self.paused = Some(now);
+        }
+    }
+
+    /// Continues where the animation was paused, showing the current frame for the rest of its delay
+    pub fn resume(&mut self, now: Instant) {
+        if let Some(paused) = self.paused.take() {
+            self.shown += now.saturating_duration_since(paused);
+        }
+    }
+
+    /// Advances the animation to `now`, returns the new frame if it changed
+    pub fn advance(&mut self, now: Instant) -> Option<Rc<Option<DynamicImage>>> {
+        if self.paused.is_some() {
+            return None;
+        }
+        let total = self
+            .frames
+            .iter()
+            .map(|(_, delay)| *delay)
+            .sum::<Duration>();
+        let elapsed = now.saturating_duration_since(self.shown);
+        if elapsed > total && !total.is_zero() {
+            // skip whole loops, e.g. after the compositor was suspended
+            let loops = (elapsed.as_secs_f64() / total.as_secs_f64()).floor();
+            self.shown += total.mul_f64(loops);
+        }
+
+        let start = self.index;
+        while now.saturating_duration_since(self.shown) >= self.frames[self.index].1 {
+            self.shown += self.frames[self.index].1;
+            self.index = (self.index + 1) % self.frames.len();
+        }
+
+        if self.index != start {
+            self.previous =
+                Some(start).filter(|start| (start + 1) % self.frames.len() == self.index);
+            Some(self.current())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the frame shown before and the region the current frame differs from it in,
+    /// `None` if frames were skipped or the whole frame changed
+    pub fn changed(&mut self) -> Option<(usize, Rectangle<i32, Buffer>)> {
+        let previous = self.previous?;
+        let index = self.index;
+        let frames = &self.frames;
+        let region = *self.changes[index].get_or_insert_with(|| {
+            match (&*frames[previous].0, &*frames[index].0) {
+                (Some(previous), Some(current)) => changed_region(previous, current),
+                _ => None,
+            }
+        });
+        region.map(|region| (previous, region))
+    }
+}
+
+/// Returns the bounding box of the pixels that differ between `a` and `b`, empty if none do.
+///
+/// The decoders composite every frame onto the whole canvas, so their own regions are gone.
+fn changed_region(a: &DynamicImage, b: &DynamicImage) -> Option<Rectangle<i32, Buffer>> {
+    if a.color() != b.color() || a.width() != b.width() || a.height() != b.height() {
+        return None;
+    }
+    let pixel = a.color().bytes_per_pixel() as usize;
+    let row = a.width() as usize * pixel;
+    let rows = || {
+        a.as_bytes()
+            .chunks_exact(row)
+            .zip(b.as_bytes().chunks_exact(row))
+    };
+    let top = match rows().position(|(a, b)| a != b) {
+        Some(top) => top,
+        None => return Some(Rectangle::from_loc_and_size((0, 0), (0, 0))),
+    };
+    let bottom = a.height() as usize - rows().rev().position(|(a, b)| a != b).unwrap_or(0);
+    let (mut left, mut right) = (a.width() as usize, 0);
+    for (a, b) in rows().take(bottom).skip(top) {
+        let columns = || a.chunks_exact(pixel).zip(b.chunks_exact(pixel));
+        if let Some(first) = columns().position(|(a, b)| a != b) {
+            left = left.min(first);
+            let last = columns().rev().position(|(a, b)| a != b).unwrap_or(0);
+            right = right.max(a.len() / pixel - last);
+        }
+    }
+    Some(Rectangle::from_loc_and_size(
+        (left as i32, top as i32),
+        ((right - left) as i32, (bottom - top) as i32),
+    ))
+}
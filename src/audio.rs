@@ -0,0 +1,225 @@
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use pipewire as pw;
+use pw::spa;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::shader::AUDIO_BANDS as BANDS;
+
+/// Samples per FFT, about 20ms at 48kHz
+const FFT_SIZE: usize = 1024;
+const MIN_FREQUENCY: f32 = 30.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+/// Loudness in dB shown as an empty band
+const FLOOR: f32 = -70.0;
+/// Factor a band keeps per processed buffer when the sound gets quieter, so bars fall smoothly
+const DECAY: f32 = 0.85;
+
+/// Spectrum of the captured audio, each band from `0.0` (silent) to `1.0`
+pub(crate) type Spectrum = [f32; BANDS];
+
+struct Terminate;
+
+/// Captures the audio played on the default output with PipeWire and analyzes it on its own thread
+pub(crate) struct AudioCapture {
+    spectrum: Arc<Mutex<Spectrum>>,
+    terminate: pw::channel::Sender<Terminate>,
+}
+
+impl fmt::Debug for AudioCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioCapture")
+            .field("spectrum", &self.spectrum)
+            .finish()
+    }
+}
+
+impl AudioCapture {
+    pub fn new() -> Result<Self, pw::Error> {
+        let spectrum = Arc::new(Mutex::new([0.0; BANDS]));
+        let (terminate, receiver) = pw::channel::channel();
+        let (started_tx, started) = std::sync::mpsc::channel();
+
+        let shared = spectrum.clone();
+        thread::spawn(move || {
+            let result = run(shared, receiver, || {
+                let _ = started_tx.send(Ok(()));
+            });
+            if let Err(err) = result {
+                let _ = started_tx.send(Err(err));
+            }
+        });
+        started.recv().map_err(|_| pw::Error::CreationFailed)??;
+        Ok(Self {
+            spectrum,
+            terminate,
+        })
+    }
+
+    /// Returns the current spectrum
+    pub fn spectrum(&self) -> Spectrum {
+        *self.spectrum.lock().unwrap()
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        let _ = self.terminate.send(Terminate);
+    }
+}
+
+/// State of the capture stream on the PipeWire thread
+struct Analyzer {
+    format: spa::param::audio::AudioInfoRaw,
+    fft: Arc<dyn Fft<f32>>,
+    /// The last `FFT_SIZE` samples, mixed down to mono
+    samples: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    spectrum: Arc<Mutex<Spectrum>>,
+}
+
+impl Analyzer {
+    fn push(&mut self, samples: impl Iterator<Item = f32>) {
+        self.samples.extend(samples);
+        if self.samples.len() > FFT_SIZE {
+            self.samples.drain(..self.samples.len() - FFT_SIZE);
+        }
+        if self.samples.len() < FFT_SIZE {
+            return;
+        }
+
+        // Hann window against the leakage of the cut off edges
+        self.buffer.clear();
+        self.buffer
+            .extend(self.samples.iter().enumerate().map(|(i, sample)| {
+                let window =
+                    0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos();
+                Complex::new(sample * window, 0.0)
+            }));
+        self.fft.process(&mut self.buffer);
+
+        let rate = self.format.rate().max(1) as f32;
+        let bin = |frequency: f32| {
+            ((frequency / rate * FFT_SIZE as f32) as usize).clamp(1, FFT_SIZE / 2 - 1)
+        };
+        // logarithmic bands, like hearing
+        let frequency = |band: usize| {
+            MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(band as f32 / BANDS as f32)
+        };
+        let mut spectrum = self.spectrum.lock().unwrap();
+        for (band, value) in spectrum.iter_mut().enumerate() {
+            let (start, end) = (bin(frequency(band)), bin(frequency(band + 1)));
+            let magnitude = self.buffer[start..=end.max(start)]
+                .iter()
+                .map(|c| c.norm())
+                .fold(0.0, f32::max)
+                / FFT_SIZE as f32;
+            let level = ((20.0 * magnitude.max(1e-9).log10() - FLOOR) / -FLOOR).clamp(0.0, 1.0);
+            *value = level.max(*value * DECAY);
+        }
+    }
+}
+
+/// Runs the capture stream until `terminate` receives a message, calling `started` once connected
+fn run<F: FnOnce()>(
+    spectrum: Arc<Mutex<Spectrum>>,
+    terminate: pw::channel::Receiver<Terminate>,
+    started: F,
+) -> Result<(), pw::Error> {
+    pw::init();
+    let mainloop = pw::MainLoop::new()?;
+    let context = pw::Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+    let _terminate = terminate.attach(&mainloop, {
+        let mainloop = mainloop.clone();
+        move |_| mainloop.quit()
+    });
+
+    let analyzer = Analyzer {
+        format: Default::default(),
+        fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+        samples: Vec::with_capacity(FFT_SIZE * 2),
+        buffer: Vec::with_capacity(FFT_SIZE),
+        spectrum,
+    };
+    let stream = pw::stream::Stream::new(
+        &core,
+        "smithay-wallpaper",
+        pw::properties! {
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Capture",
+            *pw::keys::MEDIA_ROLE => "Music",
+            // records what is played instead of the microphone
+            *pw::keys::STREAM_CAPTURE_SINK => "true",
+        },
+    )?;
+    let _listener = stream
+        .add_local_listener_with_user_data(analyzer)
+        .param_changed(|_, analyzer, id, param| {
+            let param = match param {
+                Some(param) if id == spa::param::ParamType::Format.as_raw() => param,
+                _ => return,
+            };
+            let (media_type, media_subtype) = match spa::param::format_utils::parse_format(param) {
+                Ok(format) => format,
+                Err(_) => return,
+            };
+            if media_type == spa::param::format::MediaType::Audio
+                && media_subtype == spa::param::format::MediaSubtype::Raw
+            {
+                let _ = analyzer.format.parse(param);
+            }
+        })
+        .process(|stream, analyzer| {
+            let mut buffer = match stream.dequeue_buffer() {
+                Some(buffer) => buffer,
+                None => return,
+            };
+            let data = match buffer.datas_mut().first_mut() {
+                Some(data) => data,
+                None => return,
+            };
+            let channels = analyzer.format.channels().max(1) as usize;
+            let size = data.chunk().size() as usize;
+            if let Some(bytes) = data.data() {
+                let bytes = &bytes[..size.min(bytes.len())];
+                let frames = bytes.chunks_exact(mem::size_of::<f32>() * channels);
+                analyzer.push(frames.map(|frame| {
+                    frame
+                        .chunks_exact(mem::size_of::<f32>())
+                        .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+                        .sum::<f32>()
+                        / channels as f32
+                }));
+            }
+        })
+        .register()?;
+
+    let mut format = spa::param::audio::AudioInfoRaw::new();
+    format.set_format(spa::param::audio::AudioFormat::F32LE);
+    let values = spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &spa::pod::Value::Object(spa::pod::Object {
+            type_: spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
+            id: spa::param::ParamType::EnumFormat.as_raw(),
+            properties: format.into(),
+        }),
+    )
+    .map_err(|_| pw::Error::CreationFailed)?
+    .0
+    .into_inner();
+    let mut params = [spa::pod::Pod::from_bytes(&values).ok_or(pw::Error::CreationFailed)?];
+    stream.connect(
+        spa::Direction::Input,
+        None,
+        pw::stream::StreamFlags::AUTOCONNECT | pw::stream::StreamFlags::MAP_BUFFERS,
+        &mut params,
+    )?;
+
+    started();
+    mainloop.run();
+    Ok(())
+}
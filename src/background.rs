@@ -0,0 +1,154 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use smithay::utils::{Physical, Size};
+
+/// Generated gradients are rendered at most this large and stretched onto the output
+const MAX_GRADIENT_SIZE: i32 = 512;
+
+/// A single color of a gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    /// Position of the color along the gradient, from `0.0` to `1.0`
+    pub offset: f32,
+    /// RGBA color in the range of `0.0` to `1.0`
+    pub color: [f32; 4],
+}
+
+/// The shape of a gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientKind {
+    /// A gradient along a line through the center of the output.
+    ///
+    /// The angle is given in degrees, `0.0` runs left to right and `90.0` top to bottom.
+    Linear { angle: f32 },
+    /// A gradient from the center of the output to its corners
+    Radial,
+}
+
+/// Description of a gradient background
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientSpec {
+    pub kind: GradientKind,
+    pub stops: Vec<ColorStop>,
+}
+
+impl GradientSpec {
+    /// Creates a linear gradient at `angle` degrees
+    pub fn linear(angle: f32, stops: Vec<ColorStop>) -> Self {
+        Self {
+            kind: GradientKind::Linear { angle },
+            stops,
+        }
+    }
+
+    /// Creates a radial gradient
+    pub fn radial(stops: Vec<ColorStop>) -> Self {
+        Self {
+            kind: GradientKind::Radial,
+            stops,
+        }
+    }
+
+    /// Returns the color at position `t` along the gradient
+    fn sample(&self, t: f32) -> [f32; 4] {
+        let t = t.clamp(0.0, 1.0);
+        let mut previous = match self.stops.first() {
+            Some(stop) => stop,
+            None => return [0.0, 0.0, 0.0, 1.0],
+        };
+        if t <= previous.offset {
+            return previous.color;
+        }
+
+        for stop in &self.stops[1..] {
+            if t <= stop.offset {
+                let span = stop.offset - previous.offset;
+                let f = if span > 0.0 {
+                    (t - previous.offset) / span
+                } else {
+                    1.0
+                };
+                let mut color = [0.0; 4];
+                for (i, c) in color.iter_mut().enumerate() {
+                    *c = previous.color[i] + (stop.color[i] - previous.color[i]) * f;
+                }
+                return color;
+            }
+            previous = stop;
+        }
+        previous.color
+    }
+}
+
+/// A generated background that is not backed by a file
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Background {
+    Color([f32; 4]),
+    Gradient {
+        spec: GradientSpec,
+        size: Option<Size<i32, Physical>>,
+    },
+}
+
+fn to_rgba(color: [f32; 4]) -> Rgba<u8> {
+    Rgba(color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+/// Renders a single pixel image of `color`
+pub(crate) fn color(color: [f32; 4]) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, to_rgba(color)))
+}
+
+/// Shown for wallpapers that failed to load: a red cross on dark gray, framed by warning stripes
+pub(crate) fn error_placeholder() -> DynamicImage {
+    const WIDTH: u32 = 480;
+    const HEIGHT: u32 = 270;
+    const BORDER: u32 = 16;
+    const CROSS: i32 = 60;
+    let background = Rgba([40, 40, 40, 255]);
+    let red = Rgba([200, 40, 40, 255]);
+
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        if x < BORDER || y < BORDER || x >= WIDTH - BORDER || y >= HEIGHT - BORDER {
+            return if (x + y) / BORDER % 2 == 0 {
+                red
+            } else {
+                background
+            };
+        }
+        let (dx, dy) = (x as i32 - WIDTH as i32 / 2, y as i32 - HEIGHT as i32 / 2);
+        let on_cross = dx.abs() <= CROSS
+            && dy.abs() <= CROSS
+            && ((dx - dy).abs() <= 10 || (dx + dy).abs() <= 10);
+        if on_cross {
+            red
+        } else {
+            background
+        }
+    }))
+}
+
+/// Renders `spec` for an output of `size`
+pub(crate) fn gradient(spec: &GradientSpec, size: Size<i32, Physical>) -> DynamicImage {
+    let (w, h) = (size.w.max(1) as f32, size.h.max(1) as f32);
+    let factor = (MAX_GRADIENT_SIZE as f32 / w.max(h)).min(1.0);
+    let (width, height) = (
+        ((w * factor).round() as u32).max(1),
+        ((h * factor).round() as u32).max(1),
+    );
+
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        // position relative to the center, in output pixels
+        let px = ((x as f32 + 0.5) / width as f32 - 0.5) * w;
+        let py = ((y as f32 + 0.5) / height as f32 - 0.5) * h;
+        let t = match spec.kind {
+            GradientKind::Linear { angle } => {
+                let (dy, dx) = angle.to_radians().sin_cos();
+                let extent = dx.abs() * w + dy.abs() * h;
+                0.5 + (px * dx + py * dy) / extent
+            }
+            GradientKind::Radial => (px * px + py * py).sqrt() / ((w * w + h * h).sqrt() / 2.0),
+        };
+        to_rgba(spec.sample(t))
+    });
+    DynamicImage::ImageRgba8(image)
+}
@@ -0,0 +1,397 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::ptr;
+
+use smithay::{
+    backend::renderer::{
+        gles2::{ffi, Gles2Renderer, Gles2Texture},
+        Renderer, Texture,
+    },
+    utils::{Buffer, Size},
+};
+
+use crate::{
+    cache,
+    context::{self, PerContext},
+    shader, tools, WallpaperError,
+};
+
+const VERTEX_SHADER: &str = r#"
+attribute vec2 position;
+varying vec2 v_coords;
+void main() {
+    v_coords = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+const DOWNSAMPLE_SHADER: &str = r#"
+precision mediump float;
+uniform sampler2D tex;
+uniform vec2 halfpixel;
+uniform float offset;
+varying vec2 v_coords;
+void main() {
+    vec2 d = halfpixel * offset;
+    vec4 sum = texture2D(tex, v_coords) * 4.0;
+    sum += texture2D(tex, v_coords - d);
+    sum += texture2D(tex, v_coords + d);
+    sum += texture2D(tex, v_coords + vec2(d.x, -d.y));
+    sum += texture2D(tex, v_coords - vec2(d.x, -d.y));
+    gl_FragColor = sum / 8.0;
+}
+"#;
+
+const UPSAMPLE_SHADER: &str = r#"
+precision mediump float;
+uniform sampler2D tex;
+uniform vec2 halfpixel;
+uniform float offset;
+varying vec2 v_coords;
+void main() {
+    vec2 d = halfpixel * offset;
+    vec4 sum = texture2D(tex, v_coords + vec2(-d.x * 2.0, 0.0));
+    sum += texture2D(tex, v_coords + vec2(-d.x, d.y)) * 2.0;
+    sum += texture2D(tex, v_coords + vec2(0.0, d.y * 2.0));
+    sum += texture2D(tex, v_coords + vec2(d.x, d.y)) * 2.0;
+    sum += texture2D(tex, v_coords + vec2(d.x * 2.0, 0.0));
+    sum += texture2D(tex, v_coords + vec2(d.x, -d.y)) * 2.0;
+    sum += texture2D(tex, v_coords + vec2(0.0, -d.y * 2.0));
+    sum += texture2D(tex, v_coords + vec2(-d.x, -d.y)) * 2.0;
+    gl_FragColor = sum / 12.0;
+}
+"#;
+
+/// More halvings of the image barely add to the blur but cost time
+const MAX_ITERATIONS: u32 = 6;
+/// Blurred textures kept around, two so a transition between blurred images does not blur every frame
+const KEEP: usize = 2;
+
+/// A linked blur program and its uniform locations
+#[derive(Debug)]
+struct Pass {
+    program: ffi::types::GLuint,
+    position: ffi::types::GLint,
+    tex: ffi::types::GLint,
+    halfpixel: ffi::types::GLint,
+    offset: ffi::types::GLint,
+}
+
+impl Pass {
+    unsafe fn compile(gl: &ffi::Gles2, fragment: &str) -> Result<Self, String> {
+        let program = shader::link(gl, VERTEX_SHADER, fragment)?;
+        Ok(Self {
+            program,
+            position: shader::attribute(gl, program, "position"),
+            tex: shader::uniform(gl, program, "tex"),
+            halfpixel: shader::uniform(gl, program, "halfpixel"),
+            offset: shader::uniform(gl, program, "offset"),
+        })
+    }
+
+    /// Renders `input` into `target` of `size` through the framebuffer `fbo`
+    unsafe fn draw(
+        &self,
+        gl: &ffi::Gles2,
+        fbo: ffi::types::GLuint,
+        input: ffi::types::GLuint,
+        target: ffi::types::GLuint,
+        size: Size<i32, Buffer>,
+        offset: f32,
+    ) {
+        let quad: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+
+        gl.BindFramebuffer(ffi::FRAMEBUFFER, fbo);
+        gl.FramebufferTexture2D(
+            ffi::FRAMEBUFFER,
+            ffi::COLOR_ATTACHMENT0,
+            ffi::TEXTURE_2D,
+            target,
+            0,
+        );
+        gl.Viewport(0, 0, size.w, size.h);
+
+        gl.UseProgram(self.program);
+        gl.ActiveTexture(ffi::TEXTURE0);
+        gl.BindTexture(ffi::TEXTURE_2D, input);
+        gl.Uniform1i(self.tex, 0);
+        gl.Uniform2f(self.halfpixel, 0.5 / size.w as f32, 0.5 / size.h as f32);
+        gl.Uniform1f(self.offset, offset);
+
+        gl.BindBuffer(ffi::ARRAY_BUFFER, 0);
+        gl.EnableVertexAttribArray(self.position as u32);
+        gl.VertexAttribPointer(
+            self.position as u32,
+            2,
+            ffi::FLOAT,
+            ffi::FALSE,
+            0,
+            quad.as_ptr() as *const _,
+        );
+        gl.DrawArrays(ffi::TRIANGLE_STRIP, 0, 4);
+        gl.DisableVertexAttribArray(self.position as u32);
+    }
+}
+
+#[derive(Debug)]
+enum Programs {
+    Pending,
+    Ready { down: Pass, up: Pass },
+    Failed,
+}
+
+impl Default for Programs {
+    fn default() -> Self {
+        Programs::Pending
+    }
+}
+
+/// A blurred texture and the texture and frame it was blurred from
+#[derive(Debug)]
+struct Blurred {
+    // held so its id is not reused for another texture
+    source: Gles2Texture,
+    frame: usize,
+    /// The source may be refilled with another image of the same size, see [`cache::generation`]
+    generation: u64,
+    texture: Gles2Texture,
+}
+
+/// A dual Kawase blur of the wallpaper image, rendered to an offscreen texture
+#[derive(Debug)]
+pub(crate) struct Blur {
+    radius: Cell<f32>,
+    objects: PerContext<Objects>,
+}
+
+/// The programs and blurred textures of a blur in one context
+#[derive(Debug, Default)]
+struct Objects {
+    programs: Programs,
+    blurred: Vec<Blurred>,
+}
+
+unsafe fn create_texture(gl: &ffi::Gles2, size: Size<i32, Buffer>) -> ffi::types::GLuint {
+    let mut tex = 0;
+    gl.GenTextures(1, &mut tex);
+    gl.BindTexture(ffi::TEXTURE_2D, tex);
+    gl.TexImage2D(
+        ffi::TEXTURE_2D,
+        0,
+        ffi::RGBA as i32,
+        size.w,
+        size.h,
+        0,
+        ffi::RGBA,
+        ffi::UNSIGNED_BYTE,
+        ptr::null(),
+    );
+    set_sampling(gl);
+    tex
+}
+
+/// Samples the bound texture linearly and clamps at its edges, so they do not bleed into each other
+unsafe fn set_sampling(gl: &ffi::Gles2) {
+    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+    gl.TexParameteri(
+        ffi::TEXTURE_2D,
+        ffi::TEXTURE_WRAP_S,
+        ffi::CLAMP_TO_EDGE as i32,
+    );
+    gl.TexParameteri(
+        ffi::TEXTURE_2D,
+        ffi::TEXTURE_WRAP_T,
+        ffi::CLAMP_TO_EDGE as i32,
+    );
+}
+
+impl Blur {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius: Cell::new(radius),
+            objects: PerContext::default(),
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius.get()
+    }
+
+    /// Changes the radius, blurring the image again on the next draw
+    pub fn set_radius(&self, radius: f32) {
+        if self.radius.get() != radius {
+            self.radius.set(radius);
+            self.objects.for_each(|objects| objects.blurred.clear());
+        }
+    }
+
+    /// Deletes the programs and blurred textures of `r`, they are created again on the next draw
+    pub fn destroy(&self, r: &mut Gles2Renderer) {
+        let objects = match self.objects.take(context::id(&*r)) {
+            Some(objects) => objects,
+            None => return,
+        };
+        if let Programs::Ready { down, up } = &objects.programs {
+            let ids = (down.program, up.program);
+            let _ = r.with_context(|_renderer, gl| unsafe {
+                gl.DeleteProgram(ids.0);
+                gl.DeleteProgram(ids.1);
+            });
+        }
+    }
+
+    /// Returns the bytes of the blurred textures kept in all contexts
+    pub fn texture_bytes(&self) -> usize {
+        let mut bytes = 0;
+        self.objects.for_each(|objects| {
+            bytes += objects
+                .blurred
+                .iter()
+                .map(|blurred| tools::texture_bytes(blurred.texture.size(), 4, false))
+                .sum::<usize>();
+        });
+        bytes
+    }
+
+    /// Forgets the programs and blurred textures without deleting them, after the GL contexts were lost
+    pub fn forget(&self) {
+        self.objects.clear();
+    }
+
+    /// Returns `source` of `size` showing `frame` blurred, `None` if the blur shaders failed to compile,
+    /// the compile error is returned once instead
+    pub fn blur(
+        &self,
+        r: &mut Gles2Renderer,
+        source: &Gles2Texture,
+        size: Size<i32, Buffer>,
+        frame: usize,
+    ) -> Result<Option<Gles2Texture>, WallpaperError> {
+        let generation = cache::generation();
+        let mut objects = self.objects.get(context::id(&*r));
+        if let Some(blurred) = objects.blurred.iter().find(|b| {
+            b.source.tex_id() == source.tex_id() && b.frame == frame && b.generation == generation
+        }) {
+            return Ok(Some(blurred.texture.clone()));
+        }
+
+        let radius = self.radius.get().max(1.0);
+        let iterations = (radius.log2().ceil() as u32).clamp(1, MAX_ITERATIONS);
+        let offset = (radius / (1u32 << iterations) as f32).max(1.0);
+        let programs = &mut objects.programs;
+
+        let texture = r
+            .with_context(|renderer, gl| unsafe {
+                if let Programs::Pending = *programs {
+                    let compiled = Pass::compile(gl, DOWNSAMPLE_SHADER).and_then(|down| {
+                        match Pass::compile(gl, UPSAMPLE_SHADER) {
+                            Ok(up) => Ok((down, up)),
+                            Err(err) => {
+                                gl.DeleteProgram(down.program);
+                                Err(err)
+                            }
+                        }
+                    });
+                    match compiled {
+                        Ok((down, up)) => *programs = Programs::Ready { down, up },
+                        Err(err) => {
+                            *programs = Programs::Failed;
+                            return Err(WallpaperError::Shader(err));
+                        }
+                    }
+                }
+                let (down, up) = match &*programs {
+                    Programs::Ready { down, up } => (down, up),
+                    _ => return Ok(None),
+                };
+
+                // the blur runs in the middle of a frame, so restore what it changes afterwards
+                let mut framebuffer = 0;
+                gl.GetIntegerv(ffi::FRAMEBUFFER_BINDING, &mut framebuffer);
+                let mut viewport = [0; 4];
+                gl.GetIntegerv(ffi::VIEWPORT, viewport.as_mut_ptr());
+                let blend = gl.IsEnabled(ffi::BLEND) == ffi::TRUE;
+                let scissor = gl.IsEnabled(ffi::SCISSOR_TEST) == ffi::TRUE;
+                gl.Disable(ffi::BLEND);
+                gl.Disable(ffi::SCISSOR_TEST);
+
+                let mut sizes = vec![size];
+                for _ in 0..iterations {
+                    let last = sizes[sizes.len() - 1];
+                    sizes.push(Size::from(((last.w / 2).max(1), (last.h / 2).max(1))));
+                }
+                let textures = sizes
+                    .iter()
+                    .map(|size| create_texture(gl, *size))
+                    .collect::<Vec<_>>();
+                gl.BindTexture(ffi::TEXTURE_2D, source.tex_id());
+                set_sampling(gl);
+
+                let mut fbo = 0;
+                gl.GenFramebuffers(1, &mut fbo);
+                let mut input = source.tex_id();
+                for level in 1..sizes.len() {
+                    down.draw(gl, fbo, input, textures[level], sizes[level], offset);
+                    input = textures[level];
+                }
+                for level in (0..sizes.len() - 1).rev() {
+                    up.draw(gl, fbo, input, textures[level], sizes[level], offset);
+                    input = textures[level];
+                }
+                gl.DeleteFramebuffers(1, &fbo);
+                gl.DeleteTextures(textures.len() as i32 - 1, textures[1..].as_ptr());
+
+                gl.BindFramebuffer(ffi::FRAMEBUFFER, framebuffer as u32);
+                gl.Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+                if blend {
+                    gl.Enable(ffi::BLEND);
+                }
+                if scissor {
+                    gl.Enable(ffi::SCISSOR_TEST);
+                }
+                gl.BindTexture(ffi::TEXTURE_2D, 0);
+                gl.UseProgram(0);
+
+                Ok(Some(Gles2Texture::from_raw(renderer, textures[0], size)))
+            })
+            .map_err(|err| WallpaperError::Render(Box::new(err)))??;
+
+        if let Some(texture) = &texture {
+            let blurred = &mut objects.blurred;
+            if blurred.len() >= KEEP {
+                blurred.remove(0);
+            }
+            blurred.push(Blurred {
+                source: source.clone(),
+                frame,
+                generation,
+                texture: texture.clone(),
+            });
+        }
+        Ok(texture)
+    }
+}
+
+/// Blurs `texture` of `size` if `r` is a `Gles2Renderer`, `None` for other renderers
+pub(crate) fn apply<R>(
+    r: &mut R,
+    blur: &Blur,
+    texture: &<R as Renderer>::TextureId,
+    size: Size<i32, Buffer>,
+    frame: usize,
+) -> Result<Option<Gles2Texture>, WallpaperError>
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let r = match (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let texture = match (texture as &dyn Any).downcast_ref::<Gles2Texture>() {
+        Some(texture) => texture,
+        None => return Ok(None),
+    };
+    blur.blur(r, texture, size, frame)
+}
@@ -0,0 +1,124 @@
+use std::f32::consts::PI;
+
+use image::{Rgb, RgbImage};
+
+const CHARACTERS: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+/// Size of the decoded image, the hash holds at most 9x9 components so more adds nothing
+const SIZE: u32 = 32;
+
+fn decode83(chars: &[u8]) -> Result<u32, String> {
+    chars.iter().try_fold(0, |value, c| {
+        let digit = CHARACTERS
+            .iter()
+            .position(|d| d == c)
+            .ok_or_else(|| format!("invalid character {:?}", *c as char))?;
+        Ok(value * 83 + digit as u32)
+    })
+}
+
+fn srgb_to_linear(value: u32) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0 + 0.5) as u8
+}
+
+fn sign_pow(value: f32, exp: f32) -> f32 {
+    value.abs().powf(exp).copysign(value)
+}
+
+/// Decodes the blurhash `hash` into a small image, to be scaled up to the output
+pub(crate) fn decode(hash: &str) -> Result<RgbImage, String> {
+    let hash = hash.as_bytes();
+    if hash.len() < 6 {
+        return Err(String::from("too short"));
+    }
+    let components = decode83(&hash[..1])?;
+    let (nx, ny) = (components % 9 + 1, components / 9 + 1);
+    if hash.len() != (4 + 2 * nx * ny) as usize {
+        return Err(format!(
+            "expected {} characters for {}x{} components, got {}",
+            4 + 2 * nx * ny,
+            nx,
+            ny,
+            hash.len()
+        ));
+    }
+    let max = (decode83(&hash[1..2])? + 1) as f32 / 166.0;
+
+    let mut colors = Vec::with_capacity((nx * ny) as usize);
+    let dc = decode83(&hash[2..6])?;
+    colors.push([dc >> 16, (dc >> 8) & 255, dc & 255].map(srgb_to_linear));
+    for i in 1..(nx * ny) as usize {
+        let ac = decode83(&hash[4 + i * 2..6 + i * 2])?;
+        colors.push(
+            [ac / (19 * 19), (ac / 19) % 19, ac % 19]
+                .map(|q| sign_pow((q as f32 - 9.0) / 9.0, 2.0) * max),
+        );
+    }
+
+    Ok(RgbImage::from_fn(SIZE, SIZE, |x, y| {
+        let mut pixel = [0.0; 3];
+        for j in 0..ny {
+            for i in 0..nx {
+                let basis = (PI * x as f32 * i as f32 / SIZE as f32).cos()
+                    * (PI * y as f32 * j as f32 / SIZE as f32).cos();
+                for (p, c) in pixel.iter_mut().zip(colors[(i + j * nx) as usize]) {
+                    *p += c * basis;
+                }
+            }
+        }
+        Rgb(pixel.map(linear_to_srgb))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_component_is_a_solid_color() {
+        let image = decode("00TI:j").unwrap();
+        assert_eq!(image.dimensions(), (SIZE, SIZE));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0]));
+        let image = decode("005?}k").unwrap();
+        assert!(image.pixels().all(|pixel| pixel.0 == [0x33, 0x66, 0x99]));
+    }
+
+    #[test]
+    fn horizontal_component_varies_along_x() {
+        // gray with red increasing towards the left
+        let image = decode("1~Eyb[|c").unwrap();
+        let (left, right) = (image.get_pixel(0, 0).0, image.get_pixel(SIZE - 1, 0).0);
+        assert!(left[0] > right[0], "{:?} {:?}", left, right);
+        assert_eq!(left[1], right[1]);
+        assert_eq!(image.get_pixel(0, 0), image.get_pixel(0, SIZE - 1));
+    }
+
+    #[test]
+    fn decodes_hashes_with_many_components() {
+        assert!(decode("LEHV6nWB2yk8pyo0adR*.7kCMdnj").is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_hashes() {
+        assert!(decode("").is_err());
+        assert!(decode("00TI:").is_err());
+        assert!(decode("00TI:j0").is_err());
+        assert!(decode("LEHV6nWB2yk8pyo0adR*.7kCMdn").is_err());
+        assert!(decode("00TI:\"").is_err());
+    }
+}
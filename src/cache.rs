@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use image::DynamicImage;
+
+use crate::CachedTexture;
+
+/// Budget used until [`set_texture_budget`] is called, 256 MiB
+const DEFAULT_BUDGET: usize = 256 * 1024 * 1024;
+/// Freed textures kept to be filled with the next image of the same size, on top of the budget
+const SPARES: usize = 2;
+
+/// What happens to the decoded image once its texture is uploaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Keeps the image, to import it again for another renderer or after the texture was dropped
+    KeepForReupload,
+    /// Frees the image, halving the memory used by still wallpapers.
+    ///
+    /// Only the first renderer drawing the image gets a texture, other GPUs draw nothing.
+    ///
+    /// Animations and videos keep their frames, they are uploaded again and again.
+    DropAfterUpload,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy::KeepForReupload
+    }
+}
+
+/// Identifies an image by the hash of its pixels and its size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    hash: u64,
+    width: u32,
+    height: u32,
+}
+
+impl Key {
+    /// Hashes all pixels of `image`, this is slow for large images and should run on a loader thread
+    pub fn of(image: &DynamicImage) -> Self {
+        let mut hasher = DefaultHasher::new();
+        image.color().hash(&mut hasher);
+        image.as_bytes().hash(&mut hasher);
+        Self {
+            hash: hasher.finish(),
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    texture: Rc<RefCell<Option<CachedTexture>>>,
+    last_used: u64,
+}
+
+impl Entry {
+    /// Only the cache holds the texture, no wallpaper displays it
+    fn unused(&self) -> bool {
+        Rc::strong_count(&self.texture) == 1
+    }
+
+    fn uploaded(&self) -> bool {
+        self.texture.borrow().is_some()
+    }
+
+    /// GPU memory of the texture, nothing until it is uploaded
+    fn bytes(&self) -> usize {
+        self.texture
+            .borrow()
+            .as_ref()
+            .map_or(0, |cached| cached.bytes)
+    }
+}
+
+#[derive(Debug)]
+struct TextureCache {
+    budget: usize,
+    clock: u64,
+    entries: HashMap<Key, Entry>,
+    spares: Vec<CachedTexture>,
+    /// Incremented whenever a spare is refilled, textures of older generations may show other images
+    generation: u64,
+}
+
+thread_local! {
+    static CACHE: RefCell<TextureCache> = RefCell::new(TextureCache {
+        budget: DEFAULT_BUDGET,
+        clock: 0,
+        entries: HashMap::new(),
+        spares: Vec::new(),
+        generation: 0,
+    });
+}
+
+impl TextureCache {
+    /// Drops the least recently used textures no wallpaper displays until the budget is met
+    fn trim(&mut self) {
+        self.entries
+            .retain(|_, entry| entry.uploaded() || !entry.unused());
+
+        let mut used: usize = self.entries.values().map(Entry::bytes).sum();
+        if used <= self.budget {
+            return;
+        }
+
+        let mut unused = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.unused())
+            .map(|(key, entry)| (entry.last_used, *key, entry.bytes()))
+            .collect::<Vec<_>>();
+        unused.sort_unstable_by_key(|(last_used, _, _)| *last_used);
+        for (_, key, bytes) in unused {
+            if used <= self.budget {
+                break;
+            }
+            if let Some(entry) = self.entries.remove(&key) {
+                self.keep_spare(entry.texture);
+            }
+            used -= bytes;
+        }
+    }
+
+    /// Keeps the texture of `texture` as a spare if nothing else holds it
+    fn keep_spare(&mut self, texture: Rc<RefCell<Option<CachedTexture>>>) {
+        if let Some(cached) = Rc::try_unwrap(texture)
+            .ok()
+            .and_then(|texture| texture.into_inner())
+        {
+            if self.spares.len() >= SPARES {
+                self.spares.remove(0);
+            }
+            self.spares.push(cached);
+        }
+    }
+}
+
+/// Sets how many bytes of textures are kept around for reuse after no wallpaper displays them anymore.
+///
+/// Textures in use are never dropped, even if they exceed the budget.
+/// The cache is per thread, like the renderers it holds textures of.
+pub fn set_texture_budget(bytes: usize) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.budget = bytes;
+        cache.trim();
+    });
+}
+
+/// Returns the texture budget set by [`set_texture_budget`]
+pub fn texture_budget() -> usize {
+    CACHE.with(|cache| cache.borrow().budget)
+}
+
+/// Returns the shared texture of the image identified by `key`, which is empty until first drawn
+pub(crate) fn texture(key: Key) -> Rc<RefCell<Option<CachedTexture>>> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.clock += 1;
+        let clock = cache.clock;
+        let entry = cache.entries.entry(key).or_insert_with(|| Entry {
+            texture: Rc::new(RefCell::new(None)),
+            last_used: clock,
+        });
+        entry.last_used = clock;
+        let texture = entry.texture.clone();
+        cache.trim();
+        texture
+    })
+}
+
+/// Drops unused textures exceeding the budget, called after uploading a texture
+pub(crate) fn trim() {
+    CACHE.with(|cache| cache.borrow_mut().trim());
+}
+
+/// Keeps the texture of an image no longer displayed, so the next image of the same size
+/// is written into it instead of allocating a new one.
+///
+/// Textures still shown by another wallpaper or held by the cache are left alone.
+pub(crate) fn recycle(texture: Rc<RefCell<Option<CachedTexture>>>) {
+    CACHE.with(|cache| cache.borrow_mut().keep_spare(texture));
+}
+
+/// Takes a spare texture `matches` accepts, the most recently freed first
+pub(crate) fn take_spare<F: Fn(&CachedTexture) -> bool>(matches: F) -> Option<CachedTexture> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let index = cache.spares.iter().rposition(matches)?;
+        cache.generation += 1;
+        Some(cache.spares.remove(index))
+    })
+}
+
+/// Returns how often spares were refilled, anything derived from a texture
+/// of an older generation may be stale
+pub(crate) fn generation() -> u64 {
+    CACHE.with(|cache| cache.borrow().generation)
+}
+
+/// Drops all textures and spares, after the GL context they belong to was lost
+pub(crate) fn clear() {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        for entry in cache.entries.values() {
+            *entry.texture.borrow_mut() = None;
+        }
+        cache.spares.clear();
+        cache.trim();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(hash: u64) -> Key {
+        Key {
+            hash,
+            width: 1,
+            height: 1,
+        }
+    }
+
+    fn cached(width: i32, bytes: usize) -> CachedTexture {
+        CachedTexture {
+            texture: Box::new(()),
+            frame: 0,
+            size: (width, 1).into(),
+            mipmaps: false,
+            hdr: false,
+            srgb: false,
+            linear: false,
+            external: false,
+            compressed: false,
+            context: 0,
+            bytes,
+        }
+    }
+
+    /// Uploads a texture of `bytes` for `key` and returns the slot
+    fn upload(key: Key, bytes: usize) -> Rc<RefCell<Option<CachedTexture>>> {
+        let slot = texture(key);
+        *slot.borrow_mut() = Some(cached(1, bytes));
+        slot
+    }
+
+    fn cached_keys() -> Vec<u64> {
+        CACHE.with(|cache| {
+            let mut keys = cache
+                .borrow()
+                .entries
+                .keys()
+                .map(|key| key.hash)
+                .collect::<Vec<_>>();
+            keys.sort_unstable();
+            keys
+        })
+    }
+
+    #[test]
+    fn shares_textures_of_the_same_image() {
+        let a = texture(key(1));
+        let b = texture(key(1));
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &texture(key(2))));
+    }
+
+    #[test]
+    fn trims_least_recently_used() {
+        set_texture_budget(250);
+        drop(upload(key(1), 100));
+        drop(upload(key(2), 100));
+        drop(upload(key(3), 100));
+        // used again, so the second image is the oldest now
+        drop(texture(key(1)));
+        trim();
+        assert_eq!(cached_keys(), vec![1, 3]);
+    }
+
+    #[test]
+    fn keeps_textures_in_use_over_budget() {
+        set_texture_budget(0);
+        let a = upload(key(1), 100);
+        let b = upload(key(2), 100);
+        trim();
+        assert_eq!(cached_keys(), vec![1, 2]);
+
+        drop(a);
+        trim();
+        assert_eq!(cached_keys(), vec![2]);
+        drop(b);
+    }
+
+    #[test]
+    fn reuses_spares() {
+        set_texture_budget(0);
+        let generation = generation();
+        recycle(Rc::new(RefCell::new(Some(cached(4, 16)))));
+        recycle(Rc::new(RefCell::new(Some(cached(8, 32)))));
+
+        assert!(take_spare(|cached| cached.size.w == 2).is_none());
+        assert_eq!(super::generation(), generation);
+
+        let spare = take_spare(|cached| cached.size.w == 4).unwrap();
+        assert_eq!(spare.bytes, 16);
+        assert!(take_spare(|cached| cached.size.w == 4).is_none());
+        // textures derived from the refilled spare are stale now
+        assert_eq!(super::generation(), generation + 1);
+    }
+
+    #[test]
+    fn keeps_shown_textures_from_becoming_spares() {
+        let shown = Rc::new(RefCell::new(Some(cached(4, 16))));
+        recycle(shown.clone());
+        assert!(take_spare(|_| true).is_none());
+        assert!(shown.borrow().is_some());
+    }
+
+    #[test]
+    fn trimmed_textures_become_spares() {
+        set_texture_budget(0);
+        drop(upload(key(1), 100));
+        trim();
+        assert!(cached_keys().is_empty());
+        assert_eq!(take_spare(|_| true).map(|spare| spare.bytes), Some(100));
+    }
+
+    #[test]
+    fn clear_drops_textures_and_spares() {
+        let shown = upload(key(1), 100);
+        recycle(Rc::new(RefCell::new(Some(cached(4, 16)))));
+        clear();
+        assert!(shown.borrow().is_none());
+        assert!(take_spare(|_| true).is_none());
+    }
+}
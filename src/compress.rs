@@ -0,0 +1,180 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use image::{imageops, DynamicImage, RgbaImage};
+use intel_tex_2::{etc1, RgbaSurface};
+use smithay::{
+    backend::renderer::{
+        gles2::{ffi, Gles2Renderer, Gles2Texture},
+        Renderer,
+    },
+    utils::{Buffer, Size},
+};
+
+use crate::{cache, context, tools, CachedTexture, WallpaperError};
+
+const COMPRESSED_RGB8_ETC2: ffi::types::GLenum = 0x9274;
+const COMPRESSED_SRGB8_ETC2: ffi::types::GLenum = 0x9275;
+const ETC1_RGB8_OES: ffi::types::GLenum = 0x8D64;
+
+/// An opaque image transcoded to ETC blocks, an eighth of the size of its pixels.
+///
+/// ETC1 blocks are valid ETC2 blocks, so they are uploaded as ETC2 on GLES 3
+/// and as ETC1 on GLES 2 with `GL_OES_compressed_ETC1_RGB8_texture`.
+#[derive(Debug)]
+pub(crate) struct Compressed {
+    size: Size<i32, Buffer>,
+    blocks: Vec<u8>,
+}
+
+impl Compressed {
+    /// Transcodes `image`, `None` if it is not opaque, which ETC1 blocks cannot hold
+    fn new(image: &RgbaImage) -> Option<Self> {
+        if image.pixels().any(|pixel| pixel[3] != 255) {
+            return None;
+        }
+        // blocks are 4 by 4 pixels, the edges are repeated into the last ones
+        let (width, height) = image.dimensions();
+        let padded = (width + 3) / 4 * 4;
+        let padded = (padded, (height + 3) / 4 * 4);
+        let mut surface = RgbaImage::new(padded.0, padded.1);
+        imageops::replace(&mut surface, image, 0, 0);
+        for y in 0..padded.1 {
+            for x in 0..padded.0 {
+                if x >= width || y >= height {
+                    let edge = *image.get_pixel(x.min(width - 1), y.min(height - 1));
+                    surface.put_pixel(x, y, edge);
+                }
+            }
+        }
+        let blocks = etc1::compress_blocks(
+            &etc1::slow_settings(),
+            &RgbaSurface {
+                data: &surface,
+                width: padded.0,
+                height: padded.1,
+                stride: padded.0 * 4,
+            },
+        );
+        Some(Self {
+            size: Size::from((width as i32, height as i32)),
+            blocks,
+        })
+    }
+}
+
+/// Compression of an image running on a separate thread
+#[derive(Debug)]
+pub(crate) struct Compression(Receiver<Option<Compressed>>);
+
+impl Compression {
+    /// Transcodes `image` on a new thread and calls `wake` once it is done
+    pub fn spawn<F: FnOnce() + Send + 'static>(image: &DynamicImage, wake: F) -> Self {
+        let rgba = tools::rgba8(image).into_owned();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(Compressed::new(&rgba));
+            wake();
+        });
+        Self(rx)
+    }
+
+    /// Returns the blocks once they are computed, `Some(None)` if the image cannot be compressed
+    pub fn poll(&self) -> Option<Option<Compressed>> {
+        self.0.try_recv().ok()
+    }
+}
+
+/// Replaces the texture in `texture` with one of the `compressed` blocks, unless it holds them already.
+///
+/// Renderers without ETC support keep the uncompressed texture.
+pub(crate) fn upload<R>(
+    r: &mut R,
+    compressed: &Compressed,
+    texture: &RefCell<Option<CachedTexture>>,
+    linear: bool,
+) -> Result<(), WallpaperError>
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let r = match (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let mut cached_texture = texture.borrow_mut();
+    if let Some(cached) = &*cached_texture {
+        if cached.compressed && cached.linear == linear && cached.is::<Gles2Texture>() {
+            return Ok(());
+        }
+    }
+    let size = compressed.size;
+    if tools::max_texture_size(r).map_or(false, |max| size.w as u32 > max || size.h as u32 > max) {
+        return Ok(());
+    }
+    let imported = r
+        .with_context(|renderer, gl| unsafe {
+            let gles2 = tools::gl_string(gl, ffi::VERSION).starts_with("OpenGL ES 2");
+            let format = match (gles2, linear) {
+                (false, false) => COMPRESSED_RGB8_ETC2,
+                (false, true) => COMPRESSED_SRGB8_ETC2,
+                (true, false)
+                    if tools::has_extension(gl, "GL_OES_compressed_ETC1_RGB8_texture") =>
+                {
+                    ETC1_RGB8_OES
+                }
+                _ => return None,
+            };
+            let mut tex = 0;
+            gl.GenTextures(1, &mut tex);
+            gl.BindTexture(ffi::TEXTURE_2D, tex);
+            gl.CompressedTexImage2D(
+                ffi::TEXTURE_2D,
+                0,
+                format,
+                size.w,
+                size.h,
+                0,
+                compressed.blocks.len() as i32,
+                compressed.blocks.as_ptr() as *const _,
+            );
+            gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+            gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+            gl.TexParameteri(
+                ffi::TEXTURE_2D,
+                ffi::TEXTURE_WRAP_S,
+                ffi::CLAMP_TO_EDGE as i32,
+            );
+            gl.TexParameteri(
+                ffi::TEXTURE_2D,
+                ffi::TEXTURE_WRAP_T,
+                ffi::CLAMP_TO_EDGE as i32,
+            );
+            gl.BindTexture(ffi::TEXTURE_2D, 0);
+            Some((Gles2Texture::from_raw(renderer, tex, size), format))
+        })
+        .map_err(|err| WallpaperError::Render(Box::new(err)))?;
+    let (imported, format) = match imported {
+        Some(imported) => imported,
+        None => return Ok(()),
+    };
+    // mipmaps of compressed textures cannot be generated, they would have to be compressed as well
+    *cached_texture = Some(CachedTexture {
+        texture: Box::new(imported),
+        frame: 0,
+        size,
+        mipmaps: false,
+        hdr: false,
+        srgb: format == COMPRESSED_SRGB8_ETC2,
+        linear,
+        external: false,
+        compressed: true,
+        context: context::id(&*r),
+        bytes: compressed.blocks.len(),
+    });
+    drop(cached_texture);
+    cache::trim();
+    Ok(())
+}
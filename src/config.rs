@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+#[cfg(feature = "timeline")]
+use crate::solar::Coordinates;
+use crate::{Anchor, ScalingMode, WallpaperError};
+
+/// Interval of playlists without an explicit `interval`
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Wallpaper settings of a single output, or the defaults of all outputs
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Image to show
+    pub path: Option<PathBuf>,
+    /// Images to cycle through, takes precedence over `path`
+    pub playlist: Vec<PathBuf>,
+    /// Seconds each image of the playlist is shown
+    pub interval: Option<u64>,
+    /// How the image is fitted into the output
+    pub scaling: Option<ScalingMode>,
+    /// Where the image is aligned, like `"top"` to keep the top when cropping
+    pub anchor: Option<Anchor>,
+    /// RGBA color shown when no image is set or while it is loading
+    pub color: Option<[f32; 4]>,
+    /// Blurhash of the image, shown instead of `color` while it is loading
+    pub blurhash: Option<String>,
+}
+
+impl OutputConfig {
+    /// Time each image of the playlist is shown
+    pub fn interval(&self) -> Duration {
+        self.interval.map_or(DEFAULT_INTERVAL, Duration::from_secs)
+    }
+
+    /// Fills every unset value from `default`
+    fn or(self, default: &OutputConfig) -> OutputConfig {
+        OutputConfig {
+            path: self.path.or_else(|| default.path.clone()),
+            playlist: if self.playlist.is_empty() {
+                default.playlist.clone()
+            } else {
+                self.playlist
+            },
+            interval: self.interval.or(default.interval),
+            scaling: self.scaling.or(default.scaling),
+            anchor: self.anchor.or(default.anchor),
+            color: self.color.or(default.color),
+            blurhash: self.blurhash.or_else(|| default.blurhash.clone()),
+        }
+    }
+}
+
+/// Wallpaper configuration of a `WallpaperManager`
+///
+/// ```toml
+/// path = "/usr/share/backgrounds/default.png"
+/// scaling = "fill"
+///
+/// [location]
+/// latitude = 52.5
+/// longitude = 13.4
+///
+/// [outputs.DP-1]
+/// playlist = ["/home/user/a.png", "/home/user/b.png"]
+/// interval = 600
+///
+/// [outputs.HDMI-A-1]
+/// color = [0.1, 0.1, 0.1, 1.0]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WallpaperConfig {
+    /// Settings used for every output without its own value
+    #[serde(flatten)]
+    pub default: OutputConfig,
+    /// Settings of single outputs by output name
+    pub outputs: HashMap<String, OutputConfig>,
+    /// Location dynamic wallpapers follow the sun from
+    #[cfg(feature = "timeline")]
+    pub location: Option<Coordinates>,
+}
+
+impl WallpaperConfig {
+    /// Reads and parses the TOML file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, WallpaperError> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| WallpaperError::Io(path.to_path_buf(), err))?;
+        toml::from_str(&data).map_err(|err| WallpaperError::Config(path.to_path_buf(), err))
+    }
+
+    /// Returns the settings of the output named `name`, falling back to the defaults
+    pub fn output(&self, name: &str) -> OutputConfig {
+        match self.outputs.get(name) {
+            Some(config) => config.clone().or(&self.default),
+            None => self.default.clone(),
+        }
+    }
+}
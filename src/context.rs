@@ -0,0 +1,134 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell, RefMut};
+use std::ptr;
+use std::rc::{Rc, Weak};
+
+use smithay::backend::renderer::gles2::Gles2Renderer;
+
+use crate::CachedTexture;
+
+/// Returns the EGL context of `r` if it is a `Gles2Renderer`, `0` for other renderers.
+///
+/// Renderers of different GPUs share no textures or programs, so everything created
+/// with a renderer is kept per context.
+pub(crate) fn id<R: 'static>(r: &R) -> usize {
+    match (r as &dyn Any).downcast_ref::<Gles2Renderer>() {
+        Some(r) => r.egl_context().get_context_handle() as usize,
+        None => 0,
+    }
+}
+
+/// Remembers that the textures and programs of a wallpaper were lost
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    invalidated: Cell<bool>,
+}
+
+impl Tracker {
+    /// Makes the next draw start over, as if the renderer was recreated
+    pub fn invalidate(&self) {
+        self.invalidated.set(true);
+    }
+
+    /// Returns `true` once after [`Tracker::invalidate`] was called
+    pub fn invalidated(&self) -> bool {
+        self.invalidated.take()
+    }
+}
+
+/// GL objects created separately in every context drawn with
+#[derive(Debug)]
+pub(crate) struct PerContext<T>(RefCell<Vec<(usize, T)>>);
+
+impl<T> Default for PerContext<T> {
+    fn default() -> Self {
+        Self(RefCell::new(Vec::new()))
+    }
+}
+
+impl<T: Default> PerContext<T> {
+    /// Returns the objects of `context`, created empty on first use
+    pub fn get(&self, context: usize) -> RefMut<'_, T> {
+        RefMut::map(self.0.borrow_mut(), |objects| {
+            let index = match objects.iter().position(|(id, _)| *id == context) {
+                Some(index) => index,
+                None => {
+                    objects.push((context, T::default()));
+                    objects.len() - 1
+                }
+            };
+            &mut objects[index].1
+        })
+    }
+}
+
+impl<T> PerContext<T> {
+    /// Removes the objects of `context`, to delete them
+    pub fn take(&self, context: usize) -> Option<T> {
+        let mut objects = self.0.borrow_mut();
+        let index = objects.iter().position(|(id, _)| *id == context)?;
+        Some(objects.swap_remove(index).1)
+    }
+
+    /// Calls `f` with the objects of every context
+    pub fn for_each<F: FnMut(&mut T)>(&self, mut f: F) {
+        for (_, objects) in self.0.borrow_mut().iter_mut() {
+            f(objects);
+        }
+    }
+
+    /// Forgets the objects of all contexts without deleting them
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+/// A texture of a renderer not drawing right now, and the slot it is swapped back into
+#[derive(Debug)]
+struct Stashed {
+    slot: Weak<RefCell<Option<CachedTexture>>>,
+    texture: CachedTexture,
+}
+
+thread_local! {
+    static STASH: RefCell<Vec<Stashed>> = RefCell::new(Vec::new());
+}
+
+/// Puts the texture `context` imported for `slot` into it, stashing the texture of another renderer it holds.
+///
+/// Leaves `slot` empty if `context` did not import it yet, so it is imported again for this renderer.
+pub(crate) fn select(slot: &Rc<RefCell<Option<CachedTexture>>>, context: usize) {
+    let mut texture = slot.borrow_mut();
+    if texture
+        .as_ref()
+        .map_or(false, |texture| texture.context == context)
+    {
+        return;
+    }
+    STASH.with(|stash| {
+        let mut stash = stash.borrow_mut();
+        if stash.is_empty() && texture.is_none() {
+            return;
+        }
+        // textures of slots no wallpaper holds anymore are not shown again
+        stash.retain(|stashed| stashed.slot.strong_count() > 0);
+        let selected = stash
+            .iter()
+            .position(|stashed| {
+                stashed.texture.context == context
+                    && ptr::eq(stashed.slot.as_ptr(), Rc::as_ptr(slot))
+            })
+            .map(|index| stash.swap_remove(index).texture);
+        if let Some(other) = std::mem::replace(&mut *texture, selected) {
+            stash.push(Stashed {
+                slot: Rc::downgrade(slot),
+                texture: other,
+            });
+        }
+    });
+}
+
+/// Forgets the stashed textures of all renderers, after their contexts were lost
+pub(crate) fn clear() {
+    STASH.with(|stash| stash.borrow_mut().clear());
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use zbus::{blocking::Connection, dbus_interface, fdo, SignalContext};
+
+use crate::{IpcReply, IpcRequest};
+
+/// Bus name and interface name of the service
+pub const DBUS_NAME: &str = "org.smithay.Wallpaper1";
+/// Object path the interface is served at
+pub const DBUS_PATH: &str = "/org/smithay/Wallpaper1";
+
+/// Calls fail if the compositor does not dispatch them within this time
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) type Call = (IpcRequest, SyncSender<IpcReply>);
+
+/// Forwards `request` to the thread calling [`DbusServer::dispatch`] and waits for the reply
+pub(crate) fn call(calls: &Mutex<Sender<Call>>, request: IpcRequest) -> fdo::Result<Vec<String>> {
+    let (tx, rx) = mpsc::sync_channel(1);
+    calls
+        .lock()
+        .unwrap()
+        .send((request, tx))
+        .map_err(|_| fdo::Error::Failed("wallpaper service stopped".into()))?;
+    rx.recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| fdo::Error::Failed("wallpaper service did not reply".into()))?
+        .map_err(fdo::Error::Failed)
+}
+
+/// The exported interface
+struct Wallpaper {
+    calls: Mutex<Sender<Call>>,
+}
+
+impl Wallpaper {
+    fn call(&self, request: IpcRequest) -> fdo::Result<Vec<String>> {
+        call(&self.calls, request)
+    }
+}
+
+/// An empty output name addresses all outputs
+fn output(name: &str) -> Option<String> {
+    match name {
+        "" | "*" => None,
+        name => Some(name.to_string()),
+    }
+}
+
+#[dbus_interface(name = "org.smithay.Wallpaper1")]
+impl Wallpaper {
+    /// Sets the wallpaper of `output`, or of all outputs if `output` is empty
+    fn set_wallpaper(&self, output: &str, path: &str) -> fdo::Result<()> {
+        self.call(IpcRequest::Set {
+            output: self::output(output),
+            path: PathBuf::from(path),
+        })
+        .map(|_| ())
+    }
+
+    /// Skips to the next playlist image of `output`, or of all outputs if `output` is empty
+    fn next(&self, output: &str) -> fdo::Result<()> {
+        self.call(IpcRequest::Next {
+            output: self::output(output),
+        })
+        .map(|_| ())
+    }
+
+    /// Returns every output with its wallpaper, an empty path if it has none
+    fn get_current(&self) -> fdo::Result<Vec<(String, String)>> {
+        Ok(self
+            .call(IpcRequest::Query)?
+            .into_iter()
+            .map(|line| {
+                let (output, path) = line.split_once(' ').unwrap_or((&line, "-"));
+                let path = if path == "-" { "" } else { path };
+                (output.to_string(), path.to_string())
+            })
+            .collect())
+    }
+
+    /// Emitted after the wallpaper of `output` changed, `output` is empty if all outputs changed
+    #[dbus_interface(signal)]
+    async fn wallpaper_changed(
+        ctxt: &SignalContext<'_>,
+        output: &str,
+        path: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Exports the `org.smithay.Wallpaper1` interface on the session bus
+///
+/// Calls are answered by [`DbusServer::dispatch`], which has to be called regularly from the compositor,
+/// for example once per frame.
+pub struct DbusServer {
+    connection: Connection,
+    calls: Receiver<Call>,
+}
+
+impl fmt::Debug for DbusServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DbusServer").finish()
+    }
+}
+
+impl DbusServer {
+    /// Connects to the session bus and requests the `org.smithay.Wallpaper1` name
+    ///
+    /// With the `portal` feature the `org.freedesktop.impl.portal.Wallpaper` backend is served as well.
+    pub fn new() -> zbus::Result<Self> {
+        let (tx, calls) = mpsc::channel();
+        let builder = zbus::blocking::ConnectionBuilder::session()?
+            .name(DBUS_NAME)?
+            .serve_at(
+                DBUS_PATH,
+                Wallpaper {
+                    calls: Mutex::new(tx.clone()),
+                },
+            )?;
+        #[cfg(feature = "portal")]
+        let builder = builder.name(crate::portal::PORTAL_NAME)?.serve_at(
+            crate::portal::PORTAL_PATH,
+            crate::portal::Portal {
+                calls: Mutex::new(tx),
+            },
+        )?;
+        let connection = builder.build()?;
+        Ok(Self { connection, calls })
+    }
+
+    /// Answers all pending calls using `handle`, usually [`WallpaperManager::handle_ipc`](crate::WallpaperManager::handle_ipc)
+    ///
+    /// Returns the first error emitting `WallpaperChanged`, the remaining calls are answered anyway.
+    pub fn dispatch<F>(&self, mut handle: F) -> zbus::Result<()>
+    where
+        F: FnMut(IpcRequest) -> IpcReply,
+    {
+        let mut emitted = Ok(());
+        for (request, reply) in self.calls.try_iter() {
+            let changed = match &request {
+                IpcRequest::Set { output, path } => Some((output.clone(), path.clone())),
+                _ => None,
+            };
+            let result = handle(request);
+            if let (Ok(_), Some((output, path))) = (&result, changed) {
+                let signal = self.emit_changed(output.as_deref(), &path);
+                emitted = emitted.and(signal);
+            }
+            let _ = reply.send(result);
+        }
+        emitted
+    }
+
+    /// Emits the `WallpaperChanged` signal, for changes not made through D-Bus
+    pub fn emit_changed(&self, output: Option<&str>, path: &Path) -> zbus::Result<()> {
+        self.connection.emit_signal(
+            None::<&str>,
+            DBUS_PATH,
+            DBUS_NAME,
+            "WallpaperChanged",
+            &(output.unwrap_or(""), &*path.to_string_lossy()),
+        )
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use image::ImageFormat;
+use smithay::utils::{Physical, Size};
+
+use crate::WallpaperError;
+
+/// Order in which the images of a directory are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionOrder {
+    /// Sorted by path
+    Sequential,
+    /// Shuffled, every image is shown once before the order repeats
+    Random,
+}
+
+/// Which images of a directory are shown by [`WallpaperState::set_directory`](crate::WallpaperState::set_directory)
+#[derive(Debug, Clone)]
+pub struct SelectionPolicy {
+    pub order: SelectionOrder,
+    /// Include images in subdirectories
+    pub recursive: bool,
+    /// Skip images smaller than this in both dimensions
+    pub min_size: Option<(u32, u32)>,
+    /// Skip images whose aspect ratio differs from the output by more than this fraction, e.g. `0.1`
+    pub aspect_tolerance: Option<f64>,
+    /// How long each image is shown
+    pub interval: Duration,
+}
+
+impl Default for SelectionPolicy {
+    fn default() -> Self {
+        Self {
+            order: SelectionOrder::Sequential,
+            recursive: false,
+            min_size: None,
+            aspect_tolerance: None,
+            interval: Duration::from_secs(300),
+        }
+    }
+}
+
+impl SelectionPolicy {
+    /// Checks the resolution and aspect ratio of the image at `path` against an output of `size`
+    fn accepts(&self, path: &Path, size: Size<i32, Physical>) -> bool {
+        if self.min_size.is_none() && self.aspect_tolerance.is_none() {
+            return true;
+        }
+        let (width, height) = match image::image_dimensions(path) {
+            Ok(dimensions) => dimensions,
+            // vector images and unreadable headers are not filtered
+            Err(_) => return true,
+        };
+        if let Some((min_width, min_height)) = self.min_size {
+            if width < min_width || height < min_height {
+                return false;
+            }
+        }
+        if let Some(tolerance) = self.aspect_tolerance {
+            if size.w > 0 && size.h > 0 && height > 0 {
+                let image = width as f64 / height as f64;
+                let output = size.w as f64 / size.h as f64;
+                if (image / output - 1.0).abs() > tolerance {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    #[cfg(feature = "svg")]
+    if crate::svg::is_svg(path) {
+        return true;
+    }
+    ImageFormat::from_path(path).is_ok()
+}
+
+/// Adds the images in `dir` to `paths`, and the errors of directories that cannot be read to `errors`
+fn collect(
+    dir: &Path,
+    recursive: bool,
+    paths: &mut Vec<PathBuf>,
+    errors: &mut Vec<WallpaperError>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push(WallpaperError::Io(dir.to_path_buf(), err));
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if recursive {
+                collect(&path, recursive, paths, errors);
+            }
+        } else if is_image(&path) {
+            paths.push(path);
+        }
+    }
+}
+
+/// Fisher-Yates shuffle seeded by the randomly keyed std hasher
+fn shuffle(paths: &mut [PathBuf]) {
+    let mut state = RandomState::new().build_hasher().finish() | 1;
+    for i in (1..paths.len()).rev() {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        paths.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+fn scan(dir: &Path, policy: &SelectionPolicy, size: Size<i32, Physical>) -> Scan {
+    let mut paths = Vec::new();
+    let mut errors = Vec::new();
+    collect(dir, policy.recursive, &mut paths, &mut errors);
+    paths.retain(|path| policy.accepts(path, size));
+    match policy.order {
+        SelectionOrder::Sequential => paths.sort(),
+        SelectionOrder::Random => shuffle(&mut paths),
+    }
+    (paths, errors)
+}
+
+/// Images found by a scan and the errors reading the directories
+pub(crate) type Scan = (Vec<PathBuf>, Vec<WallpaperError>);
+
+/// A directory used as playlist, scanned again whenever the output size changes
+#[derive(Debug)]
+pub(crate) struct Directory {
+    path: PathBuf,
+    pub policy: SelectionPolicy,
+    scanned: Option<Size<i32, Physical>>,
+    scan: Option<Receiver<Scan>>,
+}
+
+impl Directory {
+    pub fn new(path: PathBuf, policy: SelectionPolicy) -> Self {
+        Self {
+            path,
+            policy,
+            scanned: None,
+            scan: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Scans the directory on a separate thread for an output of `size`,
+    /// returns the images and the directories that could not be read once done
+    pub fn poll(&mut self, size: Size<i32, Physical>) -> Option<Scan> {
+        if self.scanned != Some(size) {
+            self.scanned = Some(size);
+            let (tx, rx) = mpsc::channel();
+            let (path, policy) = (self.path.clone(), self.policy.clone());
+            thread::spawn(move || {
+                let _ = tx.send(scan(&path, &policy, size));
+            });
+            self.scan = Some(rx);
+        }
+
+        let scan = self.scan.as_ref()?.try_recv().ok()?;
+        self.scan = None;
+        Some(scan)
+    }
+}
@@ -0,0 +1,124 @@
+/// Side of the tileable blue noise texture
+pub(crate) const SIZE: usize = 64;
+/// Spread of the filter measuring how clustered pixels are, in pixels
+const SIGMA: f32 = 1.9;
+
+/// Energy of all set pixels seen from every pixel, on a torus so the noise tiles seamlessly
+struct Energy {
+    kernel: Vec<f32>,
+    energy: Vec<f32>,
+}
+
+impl Energy {
+    fn new() -> Self {
+        let kernel = (0..SIZE * SIZE)
+            .map(|i| {
+                let wrap = |d: usize| d.min(SIZE - d) as f32;
+                let (dx, dy) = (wrap(i % SIZE), wrap(i / SIZE));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+        Self {
+            kernel,
+            energy: vec![0.0; SIZE * SIZE],
+        }
+    }
+
+    /// Adds the energy of pixel `index`, or removes it with a `sign` of `-1.0`
+    fn update(&mut self, index: usize, sign: f32) {
+        let (x, y) = (index % SIZE, index / SIZE);
+        for (row, energy) in self.energy.chunks_exact_mut(SIZE).enumerate() {
+            let dy = (row + SIZE - y) % SIZE;
+            let kernel = &self.kernel[dy * SIZE..(dy + 1) * SIZE];
+            for (column, energy) in energy.iter_mut().enumerate() {
+                *energy += sign * kernel[(column + SIZE - x) % SIZE];
+            }
+        }
+    }
+
+    /// The set pixel with the most set neighbours
+    fn tightest_cluster(&self, set: &[bool]) -> usize {
+        self.find(set, true, |a, b| a > b)
+    }
+
+    /// The unset pixel farthest from set ones
+    fn largest_void(&self, set: &[bool]) -> usize {
+        self.find(set, false, |a, b| a < b)
+    }
+
+    fn find(&self, set: &[bool], value: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut found = None;
+        for (i, energy) in self.energy.iter().enumerate() {
+            if set[i] == value && found.map_or(true, |f: usize| better(*energy, self.energy[f])) {
+                found = Some(i);
+            }
+        }
+        found.unwrap()
+    }
+}
+
+thread_local! {
+    static BLUE_NOISE: Vec<u8> = generate();
+}
+
+/// Calls `f` with a `SIZE` by `SIZE` blue noise threshold map,
+/// computed once per thread as it takes a moment
+pub(crate) fn with_blue_noise<T, F: FnOnce(&[u8]) -> T>(f: F) -> T {
+    BLUE_NOISE.with(|noise| f(noise))
+}
+
+/// Creates a blue noise threshold map by the void-and-cluster method,
+/// with every value occurring equally often and no visible pattern
+fn generate() -> Vec<u8> {
+    let pixels = SIZE * SIZE;
+    // a tenth of the pixels set at random, with a fixed seed so the noise is always the same
+    let mut seed = 0x2545_f491_u32;
+    let mut set = vec![false; pixels];
+    let mut energy = Energy::new();
+    let mut placed = 0;
+    while placed < pixels / 10 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let index = seed as usize % pixels;
+        if !set[index] {
+            set[index] = true;
+            energy.update(index, 1.0);
+            placed += 1;
+        }
+    }
+
+    // spreads the initial pixels evenly, moving the most clustered one into the largest void
+    loop {
+        let cluster = energy.tightest_cluster(&set);
+        set[cluster] = false;
+        energy.update(cluster, -1.0);
+        let void = energy.largest_void(&set);
+        set[void] = true;
+        energy.update(void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; pixels];
+    let initial = (set.clone(), energy.energy.clone());
+    // ranks the initial pixels by removing the most clustered first
+    for r in (0..placed).rev() {
+        let cluster = energy.tightest_cluster(&set);
+        set[cluster] = false;
+        energy.update(cluster, -1.0);
+        rank[cluster] = r;
+    }
+    // and all others by filling the largest void first
+    let (mut set, restored) = initial;
+    energy.energy = restored;
+    for r in placed..pixels {
+        let void = energy.largest_void(&set);
+        set[void] = true;
+        energy.update(void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter().map(|r| (r * 256 / pixels) as u8).collect()
+}
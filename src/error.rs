@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Errors that can occur while loading or drawing a wallpaper
+#[derive(Debug, thiserror::Error)]
+pub enum WallpaperError {
+    /// The wallpaper file could not be accessed
+    #[error("failed to open {}: {1}", .0.display())]
+    Io(PathBuf, #[source] std::io::Error),
+    /// The image could not be decoded
+    #[error("failed to decode image: {0}")]
+    Decode(#[from] image::ImageError),
+    /// The image could not be uploaded to the GPU, with the error of the renderer
+    #[error("failed to import texture: {0}")]
+    Import(#[source] Box<dyn Error + Send + Sync>),
+    /// The blurhash of a placeholder could not be decoded
+    #[error("invalid blurhash: {0}")]
+    Blurhash(String),
+    /// The renderer failed to draw the wallpaper, with its error
+    #[error("failed to render: {0}")]
+    Render(#[source] Box<dyn Error + Send + Sync>),
+    /// A shader failed to compile or link, with the log of the driver
+    #[error("failed to compile shader: {0}")]
+    Shader(String),
+    /// The video could not be played
+    #[cfg(any(feature = "video", feature = "mpv"))]
+    #[error("failed to play video {}: {1}", .0.display())]
+    Video(PathBuf, #[source] Box<dyn Error + Send + Sync>),
+    /// The audio for the visualizer could not be captured
+    #[cfg(feature = "audio")]
+    #[error("failed to capture audio: {0}")]
+    Audio(#[source] pipewire::Error),
+    /// Changes of the wallpaper file cannot be followed
+    #[cfg(feature = "watch")]
+    #[error("failed to watch {}: {1}", .0.display())]
+    Watch(PathBuf, #[source] notify::Error),
+    /// The image of a provider could not be updated, the cached image is shown instead
+    #[cfg(feature = "remote")]
+    #[error("failed to update {0}: {1}")]
+    Provider(String, #[source] std::io::Error),
+    /// The pywal color files could not be written
+    #[cfg(feature = "pywal")]
+    #[error("failed to export pywal colors: {0}")]
+    Pywal(#[source] std::io::Error),
+    /// The configuration file could not be parsed
+    #[cfg(feature = "config")]
+    #[error("invalid configuration {}: {1}", .0.display())]
+    Config(PathBuf, #[source] toml::de::Error),
+}
+
+impl WallpaperError {
+    /// Returns the error of the renderer if this is an import or render error of an `E`,
+    /// or gives the error back
+    pub(crate) fn into_renderer_error<E: Error + 'static>(self) -> Result<E, Self> {
+        match self {
+            WallpaperError::Import(err) if err.is::<E>() => Ok(*err.downcast::<E>().unwrap()),
+            WallpaperError::Render(err) if err.is::<E>() => Ok(*err.downcast::<E>().unwrap()),
+            err => Err(err),
+        }
+    }
+}
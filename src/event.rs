@@ -0,0 +1,57 @@
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use crate::WallpaperError;
+
+/// Something that happened to a `WallpaperState`
+#[derive(Debug, Clone)]
+pub enum WallpaperEvent {
+    /// An image finished loading in the background and is shown now
+    Loaded,
+    /// Loading an image, a video or another source of the wallpaper failed,
+    /// the previous wallpaper stays visible.
+    ///
+    /// Also sent for errors the wallpaper recovered from, like a directory that could not be scanned.
+    LoadFailed(Arc<WallpaperError>),
+    /// The displayed wallpaper was replaced
+    Changed,
+    /// The transition to the current image finished
+    TransitionFinished,
+}
+
+/// Everyone interested in the events of a `WallpaperState`
+#[derive(Default)]
+pub(crate) struct Events {
+    callbacks: Vec<Box<dyn FnMut(&WallpaperEvent)>>,
+    senders: Vec<Sender<WallpaperEvent>>,
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("callbacks", &self.callbacks.len())
+            .field("senders", &self.senders.len())
+            .finish()
+    }
+}
+
+impl Events {
+    pub fn add_callback<F: FnMut(&WallpaperEvent) + 'static>(&mut self, callback: F) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<WallpaperEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.senders.push(tx);
+        rx
+    }
+
+    /// Hands `event` to every callback and receiver, forgetting dropped receivers
+    pub fn emit(&mut self, event: WallpaperEvent) {
+        for callback in &mut self.callbacks {
+            callback(&event);
+        }
+        self.senders.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
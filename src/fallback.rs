@@ -0,0 +1,63 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use image::ImageError;
+
+/// Shown if a wallpaper fails to load while nothing is on screen yet
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fallback {
+    /// An image, like the default wallpaper of the system
+    Path(PathBuf),
+    /// A solid color in RGBA, which never fails
+    Color([f32; 4]),
+}
+
+/// How failed loads of a wallpaper file are tried again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Tries after the first one, before the error is reported
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// 3 retries after 1, 2 and 4 seconds
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retry number `attempt`, counting from 0
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.delay
+            .checked_mul(1 << attempt.min(16))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Returns `true` for errors that may go away by trying again,
+/// like a file on a network share timing out or one still being written
+pub(crate) fn is_transient(err: &ImageError) -> bool {
+    match err {
+        ImageError::IoError(err) => matches!(
+            err.kind(),
+            ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::TimedOut
+                | ErrorKind::UnexpectedEof
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+        ),
+        _ => false,
+    }
+}
@@ -0,0 +1,941 @@
+use std::any::Any;
+use std::time::Instant;
+
+use smithay::{
+    backend::renderer::{
+        gles2::{ffi, Gles2Renderer, Gles2Texture},
+        Renderer, Texture,
+    },
+    utils::{Buffer, Physical, Rectangle, Size, Transform},
+};
+
+use crate::{
+    context::{self, PerContext},
+    dither, shader, tools, Lut, WallpaperError,
+};
+
+/// From `GL_EXT_texture_filter_anisotropic`, which the bindings of smithay do not include
+const TEXTURE_MAX_ANISOTROPY_EXT: ffi::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY_EXT: ffi::types::GLenum = 0x84FF;
+
+const VERTEX_SHADER: &str = r#"
+attribute vec2 position;
+attribute vec2 tex_coords;
+attribute vec2 area_coords;
+attribute vec2 previous_coords;
+varying vec2 v_coords;
+varying vec2 v_area;
+varying vec2 v_previous;
+void main() {
+    v_coords = tex_coords;
+    v_area = area_coords;
+    v_previous = previous_coords;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_HEADER: &str = r#"
+precision mediump float;
+uniform sampler2D tex;
+uniform float alpha;
+uniform float time;
+uniform mat3 color_matrix;
+uniform vec3 color_offset;
+uniform sampler2D lut;
+uniform float lut_size;
+uniform vec3 lut_min;
+uniform vec3 lut_max;
+uniform vec2 area_size;
+uniform float corner_radius;
+uniform float vignette;
+uniform float dim;
+uniform int tone_mapping;
+uniform float exposure;
+uniform bool linear_light;
+uniform bool decode;
+uniform sampler2D previous;
+uniform bool decode_previous;
+uniform float progress;
+uniform sampler2D noise;
+uniform float noise_size;
+uniform bool dither;
+varying vec2 v_coords;
+varying vec2 v_area;
+varying vec2 v_previous;
+
+// the sRGB transfer functions, on premultiplied colors
+vec4 to_linear(vec4 c) {
+    if (c.a <= 0.0) {
+        return c;
+    }
+    vec3 rgb = c.rgb / c.a;
+    rgb = mix(rgb / 12.92, pow((rgb + 0.055) / 1.055, vec3(2.4)), step(0.04045, rgb));
+    return vec4(rgb * c.a, c.a);
+}
+
+vec3 to_srgb(vec3 c, float a) {
+    if (a <= 0.0) {
+        return c;
+    }
+    vec3 rgb = c / a;
+    rgb = mix(rgb * 12.92, 1.055 * pow(rgb, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, rgb));
+    return rgb * a;
+}
+
+// samples t in linear light if enabled, unless it is an sRGB texture decoding itself
+vec4 texel(sampler2D t, vec2 coords, bool decode_texture) {
+    vec4 c = texture2D(t, coords);
+    return linear_light && decode_texture ? to_linear(c) : c;
+}
+
+// looks up c in the 3D LUT stored as blue slices side by side, interpolating between slices
+vec3 grade(vec3 c) {
+    float n = lut_size;
+    c = clamp((c - lut_min) / (lut_max - lut_min), 0.0, 1.0) * (n - 1.0);
+    float b0 = floor(c.b);
+    float b1 = min(b0 + 1.0, n - 1.0);
+    float y = (c.g + 0.5) / n;
+    vec3 c0 = texture2D(lut, vec2((c.r + 0.5 + b0 * n) / (n * n), y)).rgb;
+    vec3 c1 = texture2D(lut, vec2((c.r + 0.5 + b1 * n) / (n * n), y)).rgb;
+    return mix(c0, c1, c.b - b0);
+}
+
+// maps the colors of HDR images, which may exceed 1.0, into the displayable range
+vec3 tone_map(vec3 c) {
+    c *= exposure;
+    if (tone_mapping == 1) {
+        return c / (1.0 + c);
+    } else if (tone_mapping == 2) {
+        // filmic curve of ACES, fitted by Krzysztof Narkowicz
+        return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+    }
+    return c;
+}
+
+// how much of the pixel lies within the rounded corners of the area, smoothed over a pixel
+float coverage() {
+    vec2 half_size = area_size * 0.5;
+    float radius = min(corner_radius, min(half_size.x, half_size.y));
+    vec2 q = abs(v_area * area_size - half_size) - (half_size - radius);
+    return clamp(0.5 - (length(max(q, 0.0)) - radius), 0.0, 1.0);
+}
+"#;
+
+const DEFAULT_EFFECT: &str = r#"
+vec4 effect(vec4 color, vec2 coords) {
+    return color;
+}
+"#;
+
+const FRAGMENT_FOOTER: &str = r#"
+void main() {
+    vec4 color = texel(tex, v_coords, decode);
+    // cross-fading from the previous image, which is transparent outside of its quad
+    if (progress >= 0.0) {
+        vec4 before = vec4(0.0);
+        if (v_previous == clamp(v_previous, 0.0, 1.0)) {
+            before = texel(previous, v_previous, decode_previous);
+        }
+        color = mix(before, color, progress);
+    }
+    color.rgb = tone_map(color.rgb);
+    vec3 rgb = clamp(color_matrix * color.rgb + color_offset, 0.0, 1.0);
+    float shade = (1.0 - dim) * (1.0 - vignette * smoothstep(0.3, 0.75, length(v_area - 0.5)));
+    // dimmed in linear light, the LUT and effect expect sRGB
+    if (linear_light) {
+        rgb = to_srgb(rgb * shade, color.a);
+        shade = 1.0;
+    }
+    if (lut_size > 0.0) {
+        rgb = grade(rgb);
+    }
+    vec4 result = effect(vec4(rgb, color.a), v_coords);
+    result.rgb *= shade;
+    if (corner_radius > 0.0) {
+        result *= coverage();
+    }
+    gl_FragColor = result * alpha;
+    // blue noise of an 8 bit step hides the banding of smooth gradients
+    if (dither) {
+        float offset = texture2D(noise, gl_FragCoord.xy / noise_size).r - 0.5;
+        gl_FragColor.rgb += offset / 255.0 * gl_FragColor.a;
+    }
+}
+"#;
+
+/// Contribution of each channel to the perceived brightness, Rec. 709
+const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Daylight, which is shown unchanged
+pub(crate) const NEUTRAL_TEMPERATURE: f32 = 6500.0;
+
+/// Color adjustment applied to every pixel, `matrix * rgb + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ColorTransform {
+    /// Column-major, like GL expects it
+    matrix: [f32; 9],
+    offset: [f32; 3],
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self {
+            matrix: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            offset: [0.0; 3],
+        }
+    }
+}
+
+impl ColorTransform {
+    /// Multiplies each channel by its factor
+    pub fn scale(factors: [f32; 3]) -> Self {
+        let [r, g, b] = factors;
+        Self {
+            matrix: [r, 0.0, 0.0, 0.0, g, 0.0, 0.0, 0.0, b],
+            offset: [0.0; 3],
+        }
+    }
+
+    /// Moves each channel away from `pivot` by `factor`
+    fn contrast(factor: f32, pivot: f32) -> Self {
+        let mut transform = Self::scale([factor; 3]);
+        transform.offset = [pivot * (1.0 - factor); 3];
+        transform
+    }
+
+    /// Moves each pixel away from its luminance by `factor`
+    fn saturation(factor: f32) -> Self {
+        let mut matrix = [0.0; 9];
+        for col in 0..3 {
+            for row in 0..3 {
+                let identity = if row == col { 1.0 } else { 0.0 };
+                matrix[col * 3 + row] = (1.0 - factor) * LUMINANCE[col] + factor * identity;
+            }
+        }
+        Self {
+            matrix,
+            offset: [0.0; 3],
+        }
+    }
+
+    /// Applies `next` to the result of this transform
+    pub fn then(self, next: ColorTransform) -> Self {
+        let mut matrix = [0.0; 9];
+        let mut offset = next.offset;
+        for row in 0..3 {
+            for col in 0..3 {
+                matrix[col * 3 + row] = (0..3)
+                    .map(|k| next.matrix[k * 3 + row] * self.matrix[col * 3 + k])
+                    .sum();
+            }
+            offset[row] += (0..3)
+                .map(|k| next.matrix[k * 3 + row] * self.offset[k])
+                .sum::<f32>();
+        }
+        Self { matrix, offset }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Color adjustments applied to the wallpaper while drawing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Effects {
+    /// Factor each channel is multiplied by, `1.0` is unchanged and lower values darken
+    pub brightness: f32,
+    /// Factor the distance to middle gray is multiplied by, `1.0` is unchanged
+    pub contrast: f32,
+    /// Factor the distance to the luminance is multiplied by, `1.0` is unchanged and `0.0` gray
+    pub saturation: f32,
+    /// Blends towards the grayscale image, `0.0` is unchanged and `1.0` fully gray
+    pub grayscale: f32,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self {
+            brightness: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            grayscale: 0.0,
+        }
+    }
+}
+
+impl Effects {
+    pub(crate) fn color_transform(&self) -> ColorTransform {
+        let saturation = self.saturation * (1.0 - self.grayscale.clamp(0.0, 1.0));
+        ColorTransform::saturation(saturation)
+            .then(ColorTransform::contrast(self.contrast, 0.5))
+            .then(ColorTransform::scale([self.brightness; 3]))
+    }
+}
+
+/// How colors of HDR images brighter than white are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Clips them to white, which keeps images with little HDR content unchanged
+    Clip,
+    /// Compresses all colors smoothly by `c / (1 + c)`, which darkens the image overall
+    Reinhard,
+    /// The filmic curve of ACES, with more contrast than `Reinhard`
+    Aces,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping::Clip
+    }
+}
+
+/// A GLSL snippet run over the wallpaper after the built-in color adjustments
+///
+/// The snippet has to define `vec4 effect(vec4 color, vec2 coords)`, returning the color
+/// of the pixel at the texture coordinates `coords`, whose adjusted color is `color`.
+/// It may sample the wallpaper texture `tex` and read the seconds since it was set from `time`.
+///
+/// ```glsl
+/// vec4 effect(vec4 color, vec2 coords) {
+///     float scanline = 0.9 + 0.1 * sin(coords.y * 800.0 + time * 4.0);
+///     return vec4(color.rgb * scanline, color.a);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostShader {
+    source: String,
+    animated: bool,
+}
+
+impl PostShader {
+    pub fn new<S: Into<String>>(source: S) -> Self {
+        Self {
+            source: source.into(),
+            animated: false,
+        }
+    }
+
+    /// Draws the wallpaper every frame, needed if the snippet uses `time`
+    pub fn animated(mut self, animated: bool) -> Self {
+        self.animated = animated;
+        self
+    }
+}
+
+/// Everything applied to the wallpaper while drawing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Params {
+    pub color: ColorTransform,
+    pub lut: Option<Lut>,
+    /// Darkening towards the edges of the area, `0.0` is none and `1.0` black corners
+    pub vignette: f32,
+    /// Radius of the rounded corners of the area in physical pixels
+    pub corner_radius: f32,
+    /// Darkening of the whole wallpaper, only applied here if it is drawn with the filter
+    pub dim: f32,
+    /// Samples of anisotropic filtering, `1.0` or less is off
+    pub anisotropy: f32,
+    /// Applied to textures holding HDR images
+    pub tone_mapping: ToneMapping,
+    /// Brightness of HDR images in stops, `0.0` is unchanged
+    pub exposure: f32,
+    /// Samples, blends and dims in linear light instead of on the sRGB values
+    pub linear: bool,
+    /// Adds blue noise against banding
+    pub dither: bool,
+    /// Seconds since the post-processing shader was set
+    pub time: f32,
+}
+
+impl Params {
+    /// Returns `true` if drawing with these parameters changes nothing but the dimming
+    pub fn is_identity(&self) -> bool {
+        self.color.is_identity()
+            && self.lut.is_none()
+            && self.vignette <= 0.0
+            && self.corner_radius <= 0.0
+            && self.anisotropy <= 1.0
+            && !self.linear
+            && !self.dither
+    }
+}
+
+/// Where the wallpaper is drawn to
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Target<'a> {
+    /// Size of the output in physical pixels, before its transform is applied
+    pub size: Size<i32, Physical>,
+    pub transform: Transform,
+    /// Area of the wallpaper on the output, vignette and corners are relative to it
+    pub area: Rectangle<i32, Physical>,
+    /// The texture has mipmaps to sample trilinearly
+    pub mipmaps: bool,
+    /// Samples the nearest texel instead of interpolating, for pixel art
+    pub nearest: bool,
+    /// Repeats the texture for source rectangles larger than it
+    pub repeat: bool,
+    /// The texture holds floats of an HDR image, which are tone mapped
+    pub hdr: bool,
+    /// The texture is in an sRGB format, which is decoded to linear light by sampling it
+    pub srgb: bool,
+    /// Parts of the output drawn to, anything outside is left alone
+    pub damage: &'a [Rectangle<i32, Physical>],
+}
+
+/// The image a cross-fade started from, mixed into the drawn one in linear light
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Previous<'a, T> {
+    pub texture: &'a T,
+    pub srgb: bool,
+    /// Part of the texture drawn into `dst`
+    pub src: Rectangle<i32, Buffer>,
+    pub dst: Rectangle<f64, Physical>,
+    /// How far the fade got, `0.0` shows only the previous image
+    pub progress: f32,
+}
+
+/// Returns the color of a black body at `kelvin` as RGB factors, relative to daylight
+pub(crate) fn temperature(kelvin: f32) -> [f32; 3] {
+    let rgb = |kelvin: f32| {
+        // approximation by Tanner Helland, accurate between 1000K and 40000K
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.69873 * (t - 60.0).powf(-0.13320476)
+        };
+        let g = if t <= 66.0 {
+            99.4708 * t.ln() - 161.11957
+        } else {
+            288.12216 * (t - 60.0).powf(-0.07551485)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.51773 * (t - 10.0).ln() - 305.04479
+        };
+        [r, g, b].map(|c: f32| c.clamp(0.0, 255.0))
+    };
+    let (color, neutral) = (rgb(kelvin), rgb(NEUTRAL_TEMPERATURE));
+    [0, 1, 2].map(|i| (color[i] / neutral[i]).min(1.0))
+}
+
+/// A linked filter program and its uniform locations
+#[derive(Debug)]
+struct Program {
+    program: ffi::types::GLuint,
+    position: ffi::types::GLint,
+    tex_coords: ffi::types::GLint,
+    area_coords: ffi::types::GLint,
+    previous_coords: ffi::types::GLint,
+    tex: ffi::types::GLint,
+    alpha: ffi::types::GLint,
+    color_matrix: ffi::types::GLint,
+    color_offset: ffi::types::GLint,
+    lut: ffi::types::GLint,
+    lut_size: ffi::types::GLint,
+    lut_min: ffi::types::GLint,
+    lut_max: ffi::types::GLint,
+    time: ffi::types::GLint,
+    area_size: ffi::types::GLint,
+    corner_radius: ffi::types::GLint,
+    vignette: ffi::types::GLint,
+    dim: ffi::types::GLint,
+    tone_mapping: ffi::types::GLint,
+    exposure: ffi::types::GLint,
+    linear: ffi::types::GLint,
+    decode: ffi::types::GLint,
+    previous: ffi::types::GLint,
+    decode_previous: ffi::types::GLint,
+    progress: ffi::types::GLint,
+    noise: ffi::types::GLint,
+    noise_size: ffi::types::GLint,
+    dither: ffi::types::GLint,
+}
+
+#[derive(Debug)]
+enum Compiled {
+    Pending,
+    Ready(Program),
+    Failed,
+}
+
+impl Default for Compiled {
+    fn default() -> Self {
+        Compiled::Pending
+    }
+}
+
+unsafe fn compile(gl: &ffi::Gles2, effect: &str) -> Result<Program, String> {
+    let program = shader::link(
+        gl,
+        VERTEX_SHADER,
+        &format!("{}{}{}", FRAGMENT_HEADER, effect, FRAGMENT_FOOTER),
+    )?;
+    Ok(Program {
+        program,
+        position: shader::attribute(gl, program, "position"),
+        tex_coords: shader::attribute(gl, program, "tex_coords"),
+        area_coords: shader::attribute(gl, program, "area_coords"),
+        previous_coords: shader::attribute(gl, program, "previous_coords"),
+        tex: shader::uniform(gl, program, "tex"),
+        alpha: shader::uniform(gl, program, "alpha"),
+        color_matrix: shader::uniform(gl, program, "color_matrix"),
+        color_offset: shader::uniform(gl, program, "color_offset"),
+        lut: shader::uniform(gl, program, "lut"),
+        lut_size: shader::uniform(gl, program, "lut_size"),
+        lut_min: shader::uniform(gl, program, "lut_min"),
+        lut_max: shader::uniform(gl, program, "lut_max"),
+        time: shader::uniform(gl, program, "time"),
+        area_size: shader::uniform(gl, program, "area_size"),
+        corner_radius: shader::uniform(gl, program, "corner_radius"),
+        vignette: shader::uniform(gl, program, "vignette"),
+        dim: shader::uniform(gl, program, "dim"),
+        tone_mapping: shader::uniform(gl, program, "tone_mapping"),
+        exposure: shader::uniform(gl, program, "exposure"),
+        linear: shader::uniform(gl, program, "linear_light"),
+        decode: shader::uniform(gl, program, "decode"),
+        previous: shader::uniform(gl, program, "previous"),
+        decode_previous: shader::uniform(gl, program, "decode_previous"),
+        progress: shader::uniform(gl, program, "progress"),
+        noise: shader::uniform(gl, program, "noise"),
+        noise_size: shader::uniform(gl, program, "noise_size"),
+        dither: shader::uniform(gl, program, "dither"),
+    })
+}
+
+/// Uploads the blue noise as a single channel texture repeating across the output
+unsafe fn create_noise(gl: &ffi::Gles2) -> ffi::types::GLuint {
+    let mut tex = 0;
+    gl.GenTextures(1, &mut tex);
+    gl.BindTexture(ffi::TEXTURE_2D, tex);
+    gl.PixelStorei(ffi::UNPACK_ALIGNMENT, 1);
+    dither::with_blue_noise(|noise| {
+        gl.TexImage2D(
+            ffi::TEXTURE_2D,
+            0,
+            ffi::LUMINANCE as i32,
+            dither::SIZE as i32,
+            dither::SIZE as i32,
+            0,
+            ffi::LUMINANCE,
+            ffi::UNSIGNED_BYTE,
+            noise.as_ptr() as *const _,
+        )
+    });
+    gl.PixelStorei(ffi::UNPACK_ALIGNMENT, 4);
+    gl.TexParameteri(
+        ffi::TEXTURE_2D,
+        ffi::TEXTURE_MIN_FILTER,
+        ffi::NEAREST as i32,
+    );
+    gl.TexParameteri(
+        ffi::TEXTURE_2D,
+        ffi::TEXTURE_MAG_FILTER,
+        ffi::NEAREST as i32,
+    );
+    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_WRAP_S, ffi::REPEAT as i32);
+    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_WRAP_T, ffi::REPEAT as i32);
+    gl.BindTexture(ffi::TEXTURE_2D, 0);
+    tex
+}
+
+/// The GL objects of a filter in one context
+#[derive(Debug, Default)]
+struct Objects {
+    program: Compiled,
+    /// Most anisotropic filtering samples supported, `1.0` without the extension
+    max_anisotropy: Option<f32>,
+    /// Blue noise for dithering, created on first use
+    noise: Option<ffi::types::GLuint>,
+}
+
+/// Draws textures with their colors adjusted, compiled on first use
+#[derive(Debug)]
+pub(crate) struct Filter {
+    effect: Option<PostShader>,
+    started: Instant,
+    objects: PerContext<Objects>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(None, Instant::now())
+    }
+}
+
+impl Filter {
+    /// Creates the filter, the time of `effect` starts at `now`
+    pub fn new(effect: Option<PostShader>, now: Instant) -> Self {
+        Self {
+            effect,
+            started: now,
+            objects: PerContext::default(),
+        }
+    }
+
+    pub fn effect(&self) -> Option<&PostShader> {
+        self.effect.as_ref()
+    }
+
+    /// Returns the time of the post-processing shader at `now` in seconds
+    pub fn time(&self, now: Instant) -> f32 {
+        now.saturating_duration_since(self.started).as_secs_f32()
+    }
+
+    /// Returns `true` if the post-processing shader changes every frame
+    pub fn animated(&self) -> bool {
+        self.effect.as_ref().map_or(false, |effect| effect.animated)
+    }
+
+    /// Deletes the compiled program and the noise texture of `r`, they are created again on the next draw
+    pub fn destroy(&self, r: &mut Gles2Renderer) {
+        let objects = match self.objects.take(context::id(&*r)) {
+            Some(objects) => objects,
+            None => return,
+        };
+        let _ = r.with_context(|_renderer, gl| unsafe {
+            if let Compiled::Ready(compiled) = &objects.program {
+                gl.DeleteProgram(compiled.program);
+            }
+            if let Some(noise) = objects.noise {
+                gl.DeleteTextures(1, &noise);
+            }
+        });
+    }
+
+    /// Returns the bytes of the noise textures created in all contexts
+    pub fn texture_bytes(&self) -> usize {
+        let mut bytes = 0;
+        self.objects.for_each(|objects| {
+            if objects.noise.is_some() {
+                bytes += dither::SIZE * dither::SIZE;
+            }
+        });
+        bytes
+    }
+
+    /// Forgets the programs and noise textures without deleting them, after the GL contexts were lost
+    pub fn forget(&self) {
+        self.objects.clear();
+    }
+
+    /// Draws `src` of `texture` into `dst` of the output described by `target`.
+    ///
+    /// Returns `false` if the program failed to compile and nothing was drawn,
+    /// the compile error is returned once instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        r: &mut Gles2Renderer,
+        texture: &Gles2Texture,
+        src: Rectangle<i32, Buffer>,
+        dst: Rectangle<f64, Physical>,
+        target: Target<'_>,
+        alpha: f32,
+        params: &Params,
+        previous: Option<Previous<'_, Gles2Texture>>,
+    ) -> Result<bool, WallpaperError> {
+        let (size, transform) = (target.size, target.transform);
+        let texture_size = texture.size().to_f64();
+        let (u0, v0) = (
+            src.loc.x as f64 / texture_size.w,
+            src.loc.y as f64 / texture_size.h,
+        );
+        let (u1, v1) = (
+            (src.loc.x + src.size.w) as f64 / texture_size.w,
+            (src.loc.y + src.size.h) as f64 / texture_size.h,
+        );
+
+        // raw GL draws bypass the projection of the renderer, so undo the output transform here
+        let area = size.to_f64();
+        let buffer = transform.transform_size(size).to_f64();
+        let corner = |x: f64, y: f64| {
+            let point = transform
+                .invert()
+                .transform_rect_in(
+                    Rectangle::<f64, Physical>::from_loc_and_size((x, y), (0.0, 0.0)),
+                    &area,
+                )
+                .loc;
+            // GL window coordinates start at the bottom-left corner
+            [
+                (point.x / buffer.w * 2.0 - 1.0) as f32,
+                (1.0 - point.y / buffer.h * 2.0) as f32,
+            ]
+        };
+        let (x0, y0) = (dst.loc.x, dst.loc.y);
+        let (x1, y1) = (dst.loc.x + dst.size.w, dst.loc.y + dst.size.h);
+        let corners = [
+            corner(x0, y0),
+            corner(x1, y0),
+            corner(x0, y1),
+            corner(x1, y1),
+        ];
+        let quad = corners.concat();
+        let coords = [u0, v0, u1, v0, u0, v1, u1, v1].map(|c| c as f32).to_vec();
+        // position within the wallpaper area, for the vignette and corners
+        let wallpaper = target.area.to_f64();
+        let (a0, b0) = (
+            (x0 - wallpaper.loc.x) / wallpaper.size.w,
+            (y0 - wallpaper.loc.y) / wallpaper.size.h,
+        );
+        let (a1, b1) = (
+            (x1 - wallpaper.loc.x) / wallpaper.size.w,
+            (y1 - wallpaper.loc.y) / wallpaper.size.h,
+        );
+        let area_coords = [a0, b0, a1, b0, a0, b1, a1, b1].map(|c| c as f32).to_vec();
+        // where the corners of `dst` lie on the texture of the previous image
+        let previous_coords = previous.map(|previous| {
+            let size = previous.texture.size().to_f64();
+            let (src, dst) = (previous.src.to_f64(), previous.dst);
+            let u = |x: f64| (src.loc.x + (x - dst.loc.x) * src.size.w / dst.size.w) / size.w;
+            let v = |y: f64| (src.loc.y + (y - dst.loc.y) * src.size.h / dst.size.h) / size.h;
+            [u(x0), v(y0), u(x1), v(y0), u(x0), v(y1), u(x1), v(y1)]
+                .map(|c| c as f32)
+                .to_vec()
+        });
+        let color = &params.color;
+        // the LUT texture is uploaded before drawing, like the wallpaper texture
+        let lut = params.lut.as_ref().and_then(|lut| {
+            let texture = lut.texture.borrow();
+            let texture = texture.as_ref()?.texture.downcast_ref::<Gles2Texture>()?;
+            Some((lut.size() as f32, lut.domain(), texture.clone()))
+        });
+
+        let mut objects = self.objects.get(context::id(&*r));
+        let objects = &mut *objects;
+        r.with_context(|_renderer, gl| unsafe {
+            let program = &mut objects.program;
+            if let Compiled::Pending = *program {
+                let effect = self
+                    .effect
+                    .as_ref()
+                    .map_or(DEFAULT_EFFECT, |effect| &effect.source);
+                match compile(gl, effect) {
+                    Ok(compiled) => *program = Compiled::Ready(compiled),
+                    Err(err) => {
+                        *program = Compiled::Failed;
+                        return Err(WallpaperError::Shader(err));
+                    }
+                }
+            }
+            let program = match &*program {
+                Compiled::Ready(program) => program,
+                _ => return Ok(false),
+            };
+
+            gl.UseProgram(program.program);
+            gl.ActiveTexture(ffi::TEXTURE0);
+            gl.BindTexture(ffi::TEXTURE_2D, texture.tex_id());
+            if target.nearest {
+                gl.TexParameteri(
+                    ffi::TEXTURE_2D,
+                    ffi::TEXTURE_MIN_FILTER,
+                    ffi::NEAREST as i32,
+                );
+                gl.TexParameteri(
+                    ffi::TEXTURE_2D,
+                    ffi::TEXTURE_MAG_FILTER,
+                    ffi::NEAREST as i32,
+                );
+            } else if target.mipmaps {
+                gl.TexParameteri(
+                    ffi::TEXTURE_2D,
+                    ffi::TEXTURE_MIN_FILTER,
+                    ffi::LINEAR_MIPMAP_LINEAR as i32,
+                );
+            }
+            let max_anisotropy = *objects.max_anisotropy.get_or_insert_with(|| {
+                let mut max = 1.0;
+                if tools::has_extension(gl, "GL_EXT_texture_filter_anisotropic") {
+                    gl.GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max);
+                }
+                max
+            });
+            let anisotropy = params.anisotropy.min(max_anisotropy);
+            if anisotropy > 1.0 {
+                gl.TexParameterf(ffi::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY_EXT, anisotropy);
+            }
+            gl.Uniform1i(program.tex, 0);
+            gl.Uniform1f(program.alpha, alpha);
+            gl.Uniform1f(program.time, params.time);
+            gl.UniformMatrix3fv(program.color_matrix, 1, ffi::FALSE, color.matrix.as_ptr());
+            gl.Uniform3fv(program.color_offset, 1, color.offset.as_ptr());
+            gl.Uniform2f(
+                program.area_size,
+                wallpaper.size.w as f32,
+                wallpaper.size.h as f32,
+            );
+            gl.Uniform1f(program.corner_radius, params.corner_radius);
+            gl.Uniform1f(program.vignette, params.vignette.clamp(0.0, 1.0));
+            gl.Uniform1f(program.dim, params.dim);
+            gl.Uniform1i(program.linear, params.linear as i32);
+            gl.Uniform1i(program.decode, !target.srgb as i32);
+            if target.hdr {
+                gl.Uniform1i(program.tone_mapping, params.tone_mapping as i32);
+                gl.Uniform1f(program.exposure, params.exposure.exp2());
+            } else {
+                gl.Uniform1i(program.tone_mapping, ToneMapping::Clip as i32);
+                gl.Uniform1f(program.exposure, 1.0);
+            }
+            match &lut {
+                Some((size, (min, max), texture)) => {
+                    gl.ActiveTexture(ffi::TEXTURE1);
+                    gl.BindTexture(ffi::TEXTURE_2D, texture.tex_id());
+                    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+                    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+                    gl.TexParameteri(
+                        ffi::TEXTURE_2D,
+                        ffi::TEXTURE_WRAP_S,
+                        ffi::CLAMP_TO_EDGE as i32,
+                    );
+                    gl.TexParameteri(
+                        ffi::TEXTURE_2D,
+                        ffi::TEXTURE_WRAP_T,
+                        ffi::CLAMP_TO_EDGE as i32,
+                    );
+                    gl.Uniform1i(program.lut, 1);
+                    gl.Uniform1f(program.lut_size, *size);
+                    gl.Uniform3fv(program.lut_min, 1, min.as_ptr());
+                    gl.Uniform3fv(program.lut_max, 1, max.as_ptr());
+                    gl.ActiveTexture(ffi::TEXTURE0);
+                }
+                None => gl.Uniform1f(program.lut_size, 0.0),
+            }
+            match &previous {
+                Some(previous) => {
+                    gl.ActiveTexture(ffi::TEXTURE2);
+                    gl.BindTexture(ffi::TEXTURE_2D, previous.texture.tex_id());
+                    gl.Uniform1i(program.previous, 2);
+                    gl.Uniform1i(program.decode_previous, !previous.srgb as i32);
+                    gl.Uniform1f(program.progress, previous.progress);
+                    gl.ActiveTexture(ffi::TEXTURE0);
+                }
+                None => gl.Uniform1f(program.progress, -1.0),
+            }
+            gl.Uniform1i(program.dither, params.dither as i32);
+            if params.dither {
+                let noise = *objects.noise.get_or_insert_with(|| create_noise(gl));
+                gl.ActiveTexture(ffi::TEXTURE3);
+                gl.BindTexture(ffi::TEXTURE_2D, noise);
+                gl.Uniform1i(program.noise, 3);
+                gl.Uniform1f(program.noise_size, dither::SIZE as f32);
+                gl.ActiveTexture(ffi::TEXTURE0);
+            }
+
+            gl.Enable(ffi::BLEND);
+            gl.BlendFunc(ffi::ONE, ffi::ONE_MINUS_SRC_ALPHA);
+            gl.BindBuffer(ffi::ARRAY_BUFFER, 0);
+            let attributes = [
+                (program.position, &quad),
+                (program.tex_coords, &coords),
+                (program.area_coords, &area_coords),
+            ];
+            let previous_attribute = previous_coords
+                .as_ref()
+                .map(|coords| (program.previous_coords, coords));
+            for (attribute, data) in attributes.into_iter().chain(previous_attribute) {
+                gl.EnableVertexAttribArray(attribute as u32);
+                gl.VertexAttribPointer(
+                    attribute as u32,
+                    2,
+                    ffi::FLOAT,
+                    ffi::FALSE,
+                    0,
+                    data.as_ptr() as *const _,
+                );
+            }
+            shader::clipped(gl, target.damage, size, transform, || {
+                gl.DrawArrays(ffi::TRIANGLE_STRIP, 0, 4)
+            });
+            gl.DisableVertexAttribArray(program.position as u32);
+            gl.DisableVertexAttribArray(program.tex_coords as u32);
+            gl.DisableVertexAttribArray(program.area_coords as u32);
+            if lut.is_some() {
+                gl.ActiveTexture(ffi::TEXTURE1);
+                gl.BindTexture(ffi::TEXTURE_2D, 0);
+                gl.ActiveTexture(ffi::TEXTURE0);
+            }
+            if params.dither {
+                gl.ActiveTexture(ffi::TEXTURE3);
+                gl.BindTexture(ffi::TEXTURE_2D, 0);
+                gl.ActiveTexture(ffi::TEXTURE0);
+            }
+            if previous.is_some() {
+                gl.DisableVertexAttribArray(program.previous_coords as u32);
+                gl.ActiveTexture(ffi::TEXTURE2);
+                gl.BindTexture(ffi::TEXTURE_2D, 0);
+                gl.ActiveTexture(ffi::TEXTURE0);
+            }
+            // the texture may be shared with wallpapers drawn without these
+            if anisotropy > 1.0 {
+                gl.TexParameterf(ffi::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY_EXT, 1.0);
+            }
+            if target.nearest {
+                gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+                gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+            }
+            if target.repeat {
+                gl.TexParameteri(
+                    ffi::TEXTURE_2D,
+                    ffi::TEXTURE_WRAP_S,
+                    ffi::CLAMP_TO_EDGE as i32,
+                );
+                gl.TexParameteri(
+                    ffi::TEXTURE_2D,
+                    ffi::TEXTURE_WRAP_T,
+                    ffi::CLAMP_TO_EDGE as i32,
+                );
+            }
+            gl.BindTexture(ffi::TEXTURE_2D, 0);
+            gl.UseProgram(0);
+            Ok(true)
+        })
+        .map_err(|err| WallpaperError::Render(Box::new(err)))?
+    }
+}
+
+/// Draws with `filter` if `r` is a `Gles2Renderer`, returns `false` for other renderers
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn apply<R>(
+    r: &mut R,
+    filter: &Filter,
+    texture: &<R as Renderer>::TextureId,
+    src: Rectangle<i32, Buffer>,
+    dst: Rectangle<f64, Physical>,
+    target: Target<'_>,
+    alpha: f32,
+    params: &Params,
+    previous: Option<Previous<'_, <R as Renderer>::TextureId>>,
+) -> Result<bool, WallpaperError>
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let r = match (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+        Some(r) => r,
+        None => return Ok(false),
+    };
+    let texture = match (texture as &dyn Any).downcast_ref::<Gles2Texture>() {
+        Some(texture) => texture,
+        None => return Ok(false),
+    };
+    let previous = match previous {
+        Some(previous) => match (previous.texture as &dyn Any).downcast_ref::<Gles2Texture>() {
+            Some(texture) => Some(Previous {
+                texture,
+                srgb: previous.srgb,
+                src: previous.src,
+                dst: previous.dst,
+                progress: previous.progress,
+            }),
+            None => return Ok(false),
+        },
+        None => None,
+    };
+    filter.draw(r, texture, src, dst, target, alpha, params, previous)
+}
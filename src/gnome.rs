@@ -0,0 +1,273 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate, NaiveDateTime};
+
+/// A part of a GNOME background slideshow
+#[derive(Debug, Clone)]
+enum Entry {
+    /// Shows `file` for `duration`
+    Static { duration: f64, file: PathBuf },
+    /// Blends into `to` over `duration`
+    Transition { duration: f64, to: PathBuf },
+}
+
+impl Entry {
+    fn duration(&self) -> f64 {
+        match self {
+            Entry::Static { duration, .. } | Entry::Transition { duration, .. } => *duration,
+        }
+    }
+}
+
+/// Returns `true` for `.xml` files, which are read as GNOME background slideshows
+pub(crate) fn is_gnome_xml(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("xml"))
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// A GNOME `.xml` background slideshow, which repeats from its start time on
+#[derive(Debug)]
+pub(crate) struct GnomeSlideshow {
+    start: NaiveDateTime,
+    entries: Vec<Entry>,
+    total: f64,
+    shown: Option<PathBuf>,
+    /// `None` until it was first polled
+    next: Option<Instant>,
+}
+
+/// Returns the text of the child `name` of `node`
+fn child<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+/// Reads the file of a `file` or `to` element, picking the largest of several sizes
+fn file(node: roxmltree::Node<'_, '_>) -> Option<PathBuf> {
+    node.children()
+        .filter(|child| child.has_tag_name("size"))
+        .max_by_key(|size| {
+            size.attribute("width")
+                .and_then(|width| width.parse::<u32>().ok())
+                .unwrap_or(0)
+        })
+        .and_then(|size| size.text())
+        .or_else(|| node.text())
+        .map(|path| PathBuf::from(path.trim()))
+        .filter(|path| !path.as_os_str().is_empty())
+}
+
+/// Reads a `starttime` element
+fn start_time(node: roxmltree::Node<'_, '_>) -> Option<NaiveDateTime> {
+    let value = |name| child(node, name).and_then(|v| v.parse::<u32>().ok());
+    NaiveDate::from_ymd_opt(value("year")? as i32, value("month")?, value("day")?)?.and_hms_opt(
+        value("hour").unwrap_or(0),
+        value("minute").unwrap_or(0),
+        value("second").unwrap_or(0),
+    )
+}
+
+impl GnomeSlideshow {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(data: &str) -> io::Result<Self> {
+        let doc = roxmltree::Document::parse(data).map_err(invalid)?;
+        let root = doc.root_element();
+
+        // slideshows without a start time count from the start of 2000
+        let start = match root
+            .children()
+            .find(|child| child.has_tag_name("starttime"))
+        {
+            Some(start) => start_time(start),
+            None => NaiveDate::from_ymd_opt(2000, 1, 1).and_then(|date| date.and_hms_opt(0, 0, 0)),
+        }
+        .ok_or_else(|| invalid("invalid start time"))?;
+
+        let mut entries = Vec::new();
+        for node in root.children().filter(|node| node.is_element()) {
+            let duration = child(node, "duration")
+                .and_then(|duration| duration.parse::<f64>().ok())
+                .filter(|duration| *duration >= 0.0);
+            match node.tag_name().name() {
+                "static" => entries.push(Entry::Static {
+                    duration: duration.ok_or_else(|| invalid("static without duration"))?,
+                    file: node
+                        .children()
+                        .find(|child| child.has_tag_name("file"))
+                        .and_then(file)
+                        .ok_or_else(|| invalid("static without file"))?,
+                }),
+                "transition" => entries.push(Entry::Transition {
+                    duration: duration.ok_or_else(|| invalid("transition without duration"))?,
+                    to: node
+                        .children()
+                        .find(|child| child.has_tag_name("to"))
+                        .and_then(file)
+                        .ok_or_else(|| invalid("transition without target"))?,
+                }),
+                _ => {}
+            }
+        }
+
+        let total = entries.iter().map(Entry::duration).sum::<f64>();
+        if total <= 0.0 {
+            return Err(invalid("slideshow without images"));
+        }
+        Ok(Self {
+            start,
+            entries,
+            total,
+            shown: None,
+            next: None,
+        })
+    }
+
+    /// When the next entry starts, `now` if the slideshow was not polled yet
+    pub fn deadline(&self, now: Instant) -> Instant {
+        self.next.unwrap_or(now)
+    }
+
+    /// Returns the image to show once the next entry started at `now`,
+    /// with the remaining duration of the transition into it
+    pub fn poll(&mut self, now: Instant) -> Option<(PathBuf, Option<Duration>)> {
+        if self.next.map_or(false, |next| now < next) {
+            return None;
+        }
+
+        let local = Local::now().naive_local();
+        let elapsed = (local - self.start).num_milliseconds() as f64 / 1000.0;
+        let mut offset = elapsed.rem_euclid(self.total);
+        let (entry, remaining) = self
+            .entries
+            .iter()
+            .find_map(|entry| {
+                if offset < entry.duration() {
+                    Some((entry, entry.duration() - offset))
+                } else {
+                    offset -= entry.duration();
+                    None
+                }
+            })
+            .unwrap_or_else(|| (&self.entries[0], self.entries[0].duration()));
+        let remaining = Duration::from_secs_f64(remaining.max(0.001));
+        self.next = Some(now + remaining);
+
+        let (path, fade) = match entry {
+            Entry::Static { file, .. } => (file, None),
+            Entry::Transition { to, .. } => (to, Some(remaining)),
+        };
+        if self.shown.as_ref() == Some(path) {
+            return None;
+        }
+        self.shown = Some(path.clone());
+        Some((path.clone(), fade))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLIDESHOW: &str = r#"
+        <background>
+          <starttime>
+            <year>2011</year><month>11</month><day>24</day>
+            <hour>7</hour><minute>0</minute><second>0</second>
+          </starttime>
+          <static>
+            <duration>1795.0</duration>
+            <file>/usr/share/backgrounds/morning.jpg</file>
+          </static>
+          <transition type="overlay">
+            <duration>5.0</duration>
+            <from>/usr/share/backgrounds/morning.jpg</from>
+            <to>/usr/share/backgrounds/night.jpg</to>
+          </transition>
+          <static>
+            <duration>1800.0</duration>
+            <file>
+              <size width="1024" height="768">/usr/share/backgrounds/night-1024.jpg</size>
+              <size width="3840" height="2160">/usr/share/backgrounds/night-3840.jpg</size>
+              <size width="1920" height="1080">/usr/share/backgrounds/night-1920.jpg</size>
+            </file>
+          </static>
+        </background>
+    "#;
+
+    #[test]
+    fn reads_start_time_and_entries() {
+        let slideshow = GnomeSlideshow::parse(SLIDESHOW).unwrap();
+        assert_eq!(
+            slideshow.start,
+            NaiveDate::from_ymd_opt(2011, 11, 24)
+                .and_then(|date| date.and_hms_opt(7, 0, 0))
+                .unwrap()
+        );
+        assert_eq!(slideshow.entries.len(), 3);
+        assert_eq!(slideshow.total, 3600.0);
+        assert!(matches!(
+            &slideshow.entries[1],
+            Entry::Transition { duration, to }
+                if *duration == 5.0 && to == Path::new("/usr/share/backgrounds/night.jpg")
+        ));
+    }
+
+    #[test]
+    fn picks_the_widest_size() {
+        let slideshow = GnomeSlideshow::parse(SLIDESHOW).unwrap();
+        assert!(matches!(
+            &slideshow.entries[2],
+            Entry::Static { file, .. } if file == Path::new("/usr/share/backgrounds/night-3840.jpg")
+        ));
+    }
+
+    #[test]
+    fn start_time_defaults_to_2000() {
+        let slideshow = GnomeSlideshow::parse(
+            "<background><static><duration>60</duration><file>a.png</file></static></background>",
+        )
+        .unwrap();
+        assert_eq!(
+            slideshow.start,
+            NaiveDate::from_ymd_opt(2000, 1, 1)
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_start_times() {
+        let err = GnomeSlideshow::parse(
+            "<background><starttime><year>2011</year><month>2</month><day>30</day></starttime>\
+             <static><duration>60</duration><file>a.png</file></static></background>",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_incomplete_entries() {
+        assert!(GnomeSlideshow::parse(
+            "<background><static><file>a.png</file></static></background>"
+        )
+        .is_err());
+        assert!(GnomeSlideshow::parse(
+            "<background><transition><duration>5</duration></transition></background>"
+        )
+        .is_err());
+        assert!(GnomeSlideshow::parse("<background></background>").is_err());
+        assert!(GnomeSlideshow::parse("not xml").is_err());
+    }
+}
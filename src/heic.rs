@@ -0,0 +1,273 @@
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{Local, Timelike, Utc};
+
+use crate::heif::{error, open};
+use crate::solar::{self, SunPosition};
+
+const DAY_SECS: f64 = 24.0 * 60.0 * 60.0;
+/// Images of wallpapers following the sun are picked again after this time
+const SOLAR_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Returns `true` for `.heic` and `.heif` files, and `.avif` files used by KDE with the `kde` feature
+pub(crate) fn is_heic(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| {
+        ext.eq_ignore_ascii_case("heic")
+            || ext.eq_ignore_ascii_case("heif")
+            || (cfg!(feature = "kde") && ext.eq_ignore_ascii_case("avif"))
+    })
+}
+
+/// How the image of a dynamic wallpaper is picked
+#[derive(Debug, Clone)]
+enum Schedule {
+    /// `(fraction of the day, image index)` pairs, sorted by time
+    Time(Vec<(f64, usize)>),
+    /// Separate images for day and night
+    Appearance { light: usize, dark: usize },
+    /// `(sun position, image index)` pairs, `fallback` is used while the location is unknown
+    Solar {
+        positions: Vec<(SunPosition, usize)>,
+        fallback: Box<Schedule>,
+    },
+}
+
+impl Schedule {
+    /// Returns the image index shown `secs` after local midnight and the seconds until it changes
+    fn select(&self, secs: f64) -> Option<(usize, f64)> {
+        match self {
+            Schedule::Time(times) => {
+                let fraction = secs / DAY_SECS;
+                let index = times
+                    .iter()
+                    .rev()
+                    .find(|(time, _)| *time <= fraction)
+                    .or_else(|| times.last())
+                    .map(|(_, index)| *index)?;
+                let next = times
+                    .iter()
+                    .map(|(time, _)| *time)
+                    .find(|time| *time > fraction)
+                    .unwrap_or(times[0].0 + 1.0);
+                Some((index, (next - fraction) * DAY_SECS))
+            }
+            Schedule::Appearance { light, dark } => {
+                let (day, until) = crate::timeline::daylight();
+                Some((if day { *light } else { *dark }, until.as_secs_f64()))
+            }
+            Schedule::Solar {
+                positions,
+                fallback,
+            } => {
+                let coordinates = match solar::location() {
+                    Some(coordinates) => coordinates,
+                    None => return fallback.select(secs),
+                };
+                let sun = solar::sun_position(coordinates, Utc::now());
+                let (elevation, azimuth) = (sun.elevation.to_radians(), sun.azimuth.to_radians());
+                // the image whose sun is the smallest angle away from the real one
+                let closeness = |position: &SunPosition| {
+                    let (e, a) = (
+                        position.elevation.to_radians(),
+                        position.azimuth.to_radians(),
+                    );
+                    elevation.sin() * e.sin() + elevation.cos() * e.cos() * (azimuth - a).cos()
+                };
+                let index = positions
+                    .iter()
+                    .max_by(|a, b| closeness(&a.0).total_cmp(&closeness(&b.0)))
+                    .map(|(_, index)| *index)?;
+                Some((index, SOLAR_INTERVAL.as_secs_f64()))
+            }
+        }
+    }
+}
+
+/// Light and dark images of a solar wallpaper without appearance metadata, by sun elevation
+fn solar_appearance(positions: &[(SunPosition, usize)]) -> Option<Schedule> {
+    let by_elevation = |a: &&(SunPosition, usize), b: &&(SunPosition, usize)| {
+        a.0.elevation.total_cmp(&b.0.elevation)
+    };
+    Some(Schedule::Appearance {
+        light: positions.iter().max_by(by_elevation)?.1,
+        dark: positions.iter().min_by(by_elevation)?.1,
+    })
+}
+
+/// Extracts the base64 encoded value of the XMP property `name`, as attribute or element
+fn xmp_property<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    if let Some(start) = xmp.find(&format!("{}=\"", name)) {
+        let value = &xmp[start + name.len() + 2..];
+        return value.find('"').map(|end| &value[..end]);
+    }
+    let start = xmp.find(&format!("<{}>", name))? + name.len() + 2;
+    let value = &xmp[start..];
+    value.find('<').map(|end| value[..end].trim())
+}
+
+fn appearance(dict: &plist::Dictionary) -> Option<Schedule> {
+    let ap = dict.get("ap")?.as_dictionary()?;
+    Some(Schedule::Appearance {
+        light: ap.get("l")?.as_unsigned_integer()? as usize,
+        dark: ap.get("d")?.as_unsigned_integer()? as usize,
+    })
+}
+
+/// Reads the metadata of KDE's dynamic wallpapers, a base64 encoded JSON list of images
+#[cfg(feature = "kde")]
+fn parse_kde_schedule(xmp: &str) -> Option<Schedule> {
+    let data = base64::decode(xmp_property(xmp, "plasma:DynamicWallpaper")?).ok()?;
+    let json = serde_json::from_slice::<serde_json::Value>(&data).ok()?;
+    // older versions wrap the list in an object
+    let entries = json.as_array().or_else(|| json.get("Meta")?.as_array())?;
+    let index = |entry: &serde_json::Value| Some(entry.get("Index")?.as_u64()? as usize);
+
+    let time_of_day = |name: &str| {
+        entries
+            .iter()
+            .find(|entry| entry.get("TimeOfDay").and_then(|t| t.as_str()) == Some(name))
+            .and_then(index)
+    };
+    if let (Some(light), Some(dark)) = (time_of_day("day"), time_of_day("night")) {
+        return Some(Schedule::Appearance { light, dark });
+    }
+
+    let mut times = entries
+        .iter()
+        .filter_map(|entry| Some((entry.get("Time")?.as_f64()?, index(entry)?)))
+        .collect::<Vec<_>>();
+    if times.is_empty() {
+        return None;
+    }
+    times.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let positions = entries
+        .iter()
+        .filter_map(|entry| {
+            let position = SunPosition {
+                elevation: entry.get("SolarElevation")?.as_f64()?,
+                azimuth: entry.get("SolarAzimuth")?.as_f64()?,
+            };
+            Some((position, index(entry)?))
+        })
+        .collect::<Vec<_>>();
+    if positions.is_empty() {
+        return Some(Schedule::Time(times));
+    }
+    Some(Schedule::Solar {
+        positions,
+        fallback: Box::new(Schedule::Time(times)),
+    })
+}
+
+fn parse_schedule(xmp: &str) -> Option<Schedule> {
+    #[cfg(feature = "kde")]
+    if let Some(schedule) = parse_kde_schedule(xmp) {
+        return Some(schedule);
+    }
+
+    let (value, solar) = match xmp_property(xmp, "apple_desktop:h24") {
+        Some(value) => (value, false),
+        None => (xmp_property(xmp, "apple_desktop:solar")?, true),
+    };
+    let data = base64::decode(value).ok()?;
+    let plist = plist::Value::from_reader(Cursor::new(data)).ok()?;
+    let dict = plist.as_dictionary()?;
+
+    if solar {
+        let positions = dict
+            .get("si")?
+            .as_array()?
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.as_dictionary()?;
+                let position = SunPosition {
+                    elevation: entry.get("e")?.as_real()?,
+                    azimuth: entry.get("a")?.as_real()?,
+                };
+                Some((position, entry.get("i")?.as_unsigned_integer()? as usize))
+            })
+            .collect::<Vec<_>>();
+        // without the location the day and night images are shown instead
+        let fallback = appearance(dict).or_else(|| solar_appearance(&positions))?;
+        return Some(Schedule::Solar {
+            positions,
+            fallback: Box::new(fallback),
+        });
+    }
+
+    let mut times = dict
+        .get("ti")?
+        .as_array()?
+        .iter()
+        .filter_map(|entry| {
+            let entry = entry.as_dictionary()?;
+            Some((
+                entry.get("t")?.as_real()?,
+                entry.get("i")?.as_unsigned_integer()? as usize,
+            ))
+        })
+        .collect::<Vec<_>>();
+    if times.is_empty() {
+        return appearance(dict);
+    }
+    times.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Some(Schedule::Time(times))
+}
+
+/// An Apple or KDE dynamic wallpaper, showing one of its images depending on the time of day
+/// or the position of the sun
+#[derive(Debug)]
+pub(crate) struct DynamicHeic {
+    path: PathBuf,
+    schedule: Schedule,
+    shown: Option<usize>,
+    /// `None` until it was first polled
+    next: Option<Instant>,
+}
+
+impl DynamicHeic {
+    /// Reads the metadata of the HEIF file at `path`, `None` if it is not a dynamic wallpaper
+    pub fn open(path: &Path) -> io::Result<Option<Self>> {
+        let ctx = open(path)?;
+        let handle = ctx.primary_image_handle().map_err(error)?;
+        let mut ids = vec![0; handle.number_of_metadata_blocks("mime") as usize];
+        let len = handle.metadata_block_ids("mime", &mut ids);
+        ids.truncate(len);
+
+        let schedule = ids
+            .into_iter()
+            .filter_map(|id| handle.metadata(id).ok())
+            .find_map(|xmp| parse_schedule(&String::from_utf8_lossy(&xmp)));
+        Ok(schedule.map(|schedule| Self {
+            path: path.to_path_buf(),
+            schedule,
+            shown: None,
+            next: None,
+        }))
+    }
+
+    /// When the image changes next
+    pub fn deadline(&self, now: Instant) -> Instant {
+        self.next.unwrap_or(now)
+    }
+
+    /// Returns the path and index of the image to decode once it changed at `now`
+    pub fn poll(&mut self, now: Instant) -> Option<(PathBuf, usize)> {
+        if self.next.map_or(false, |next| now < next) {
+            return None;
+        }
+
+        let secs = Local::now().num_seconds_from_midnight() as f64;
+        let (index, until) = self.schedule.select(secs)?;
+        self.next = Some(now + Duration::from_secs_f64(until.max(1.0)));
+
+        if self.shown == Some(index) {
+            return None;
+        }
+        self.shown = Some(index);
+        Some((self.path.clone(), index))
+    }
+}
@@ -0,0 +1,87 @@
+use std::io;
+use std::path::Path;
+
+use image::{DynamicImage, ImageError, ImageResult, RgbaImage};
+use libheif_rs::{ColorSpace, HeifContext, ImageHandle, ItemId, RgbChroma};
+
+use crate::loader::decode_limits;
+
+/// Brands of the `ftyp` box of HEIF files with still images libheif decodes, HEVC and AV1 coded
+const BRANDS: [&[u8; 4]; 8] = [
+    b"heic", b"heix", b"heim", b"heis", b"mif1", b"msf1", b"avif", b"avis",
+];
+
+pub(crate) fn error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+/// Returns `true` for `.heic`, `.heif`, `.hif` and `.avif` files
+pub(crate) fn is_heif(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| {
+        ["heic", "heif", "hif", "avif"]
+            .iter()
+            .any(|heif| ext.eq_ignore_ascii_case(heif))
+    })
+}
+
+/// Returns `true` if `data` starts like a HEIF or AVIF file, which the image crate does not detect
+pub(crate) fn is_heif_data(data: &[u8]) -> bool {
+    data.len() >= 12 && &data[4..8] == b"ftyp" && BRANDS.iter().any(|brand| data[8..12] == **brand)
+}
+
+pub(crate) fn open(path: &Path) -> io::Result<HeifContext> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| error("path is not valid UTF-8"))?;
+    HeifContext::read_from_file(path).map_err(error)
+}
+
+pub(crate) fn image_ids(ctx: &HeifContext) -> Vec<ItemId> {
+    let mut ids = vec![0; ctx.number_of_top_level_images()];
+    let len = ctx.top_level_image_ids(&mut ids);
+    ids.truncate(len);
+    ids
+}
+
+/// Decodes the top-level image `index` of the HEIF or AVIF file at `path`, or its primary image
+pub(crate) fn decode_file(path: &Path, index: Option<usize>) -> ImageResult<DynamicImage> {
+    let ctx = open(path)?;
+    let handle = match index {
+        Some(index) => {
+            let id = *image_ids(&ctx)
+                .get(index)
+                .ok_or_else(|| error(format!("no image {} in {}", index, path.display())))?;
+            ctx.image_handle(id)
+        }
+        None => ctx.primary_image_handle(),
+    }
+    .map_err(error)?;
+    decode(&handle)
+}
+
+/// Decodes the primary image of a HEIF or AVIF file in memory
+pub(crate) fn decode_bytes(data: &[u8]) -> ImageResult<DynamicImage> {
+    let ctx = HeifContext::read_from_bytes(data).map_err(error)?;
+    let handle = ctx.primary_image_handle().map_err(error)?;
+    decode(&handle)
+}
+
+fn decode(handle: &ImageHandle) -> ImageResult<DynamicImage> {
+    decode_limits().check_rgba((handle.width(), handle.height()))?;
+    let image = handle
+        .decode(ColorSpace::Rgb(RgbChroma::Rgba), false)
+        .map_err(error)?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| error("decoded image has no interleaved plane"))?;
+
+    let (width, height) = (plane.width, plane.height);
+    let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        data.extend_from_slice(&row[..width as usize * 4]);
+    }
+    RgbaImage::from_raw(width, height, data)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| ImageError::IoError(error("invalid image buffer")))
+}
@@ -0,0 +1,44 @@
+use image::DynamicImage;
+use lcms2::{InfoType, Intent, LCMSResult, Locale, PixelFormat, Profile, Transform};
+
+/// Parses the ICC profile `icc`, `None` if it is sRGB already
+pub(crate) fn parse(icc: &[u8]) -> LCMSResult<Option<Profile>> {
+    let profile = Profile::new_icc(icc)?;
+    // most images are tagged sRGB, those need no conversion
+    let description = profile.info(InfoType::Description, Locale::none());
+    if description.map_or(false, |description| description.starts_with("sRGB")) {
+        return Ok(None);
+    }
+    Ok(Some(profile))
+}
+
+/// Converts `image` from the color space of `profile` to sRGB, keeping it in floats
+/// if it has more than 8 bits per channel
+pub(crate) fn to_srgb(image: DynamicImage, profile: &Profile) -> LCMSResult<DynamicImage> {
+    let color = image.color();
+    if color.bytes_per_pixel() > color.channel_count() {
+        let mut image = image.into_rgba32f();
+        transform(profile, PixelFormat::RGBA_FLT, &mut *image)?;
+        Ok(DynamicImage::ImageRgba32F(image))
+    } else {
+        let mut image = image.into_rgba8();
+        transform(profile, PixelFormat::RGBA_8, &mut *image)?;
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
+
+/// Converts the interleaved RGBA channels in `data` in place
+fn transform<T: Copy>(profile: &Profile, format: PixelFormat, data: &mut [T]) -> LCMSResult<()> {
+    let srgb = Profile::new_srgb();
+    let transform =
+        Transform::<[T; 4], [T; 4]>::new(profile, format, &srgb, format, Intent::Perceptual)?;
+    let mut pixels = data
+        .chunks_exact(4)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+        .collect::<Vec<_>>();
+    transform.transform_in_place(&mut pixels);
+    for (pixel, converted) in data.chunks_exact_mut(4).zip(pixels) {
+        pixel.copy_from_slice(&converted);
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use smithay::reexports::calloop::{
+    generic::Generic, EventSource, Interest, Mode, Poll, PostAction, Readiness, Token, TokenFactory,
+};
+
+/// Longest request line accepted, longer requests are answered with an error
+const MAX_LINE: usize = 4096;
+
+/// A command received over the IPC socket
+///
+/// The protocol is a single line per connection:
+/// - `set <output> <path>` sets the wallpaper of `output`, or of all outputs if `output` is `*`
+/// - `next [output]` skips to the next playlist image of `output`, or of all outputs
+/// - `query` lists every output with its wallpaper
+///
+/// The reply contains one line per result followed by `ok`, or a single `error: <message>` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpcRequest {
+    Set {
+        output: Option<String>,
+        path: PathBuf,
+    },
+    Next {
+        output: Option<String>,
+    },
+    Query,
+}
+
+impl IpcRequest {
+    /// Parses a single request line
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let output = |name: &str| match name {
+            "" | "*" => None,
+            name => Some(name.to_string()),
+        };
+        match command {
+            "set" => match args.trim().split_once(' ') {
+                Some((name, path)) if !path.trim().is_empty() => Ok(IpcRequest::Set {
+                    output: output(name),
+                    path: PathBuf::from(path.trim()),
+                }),
+                _ => Err("usage: set <output> <path>".into()),
+            },
+            "next" => Ok(IpcRequest::Next {
+                output: output(args.trim()),
+            }),
+            "query" => Ok(IpcRequest::Query),
+            command => Err(format!("unknown command {:?}", command)),
+        }
+    }
+}
+
+/// Reply to an `IpcRequest`, the lines sent back on success or an error message
+pub type IpcReply = Result<Vec<String>, String>;
+
+/// A connection waiting for its request line to arrive
+#[derive(Debug)]
+struct Client {
+    stream: Generic<UnixStream>,
+    buffer: Vec<u8>,
+    registered: bool,
+    done: bool,
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> Self {
+        Self {
+            stream: Generic::new(stream, Interest::READ, Mode::Level),
+            buffer: Vec::new(),
+            registered: false,
+            done: false,
+        }
+    }
+}
+
+/// A calloop event source accepting `IpcRequest`s on a unix socket
+///
+/// The callback is called for every request and its return value is sent back to the client,
+/// [`WallpaperManager::handle_ipc`](crate::WallpaperManager::handle_ipc) implements all requests.
+/// Every connection is polled by the event loop, so a slow client never blocks it.
+#[derive(Debug)]
+pub struct IpcSource {
+    path: PathBuf,
+    listener: Generic<UnixListener>,
+    clients: Vec<Client>,
+}
+
+impl IpcSource {
+    /// Listens on the socket at `path`, replacing a stale socket left behind by a previous instance
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() && UnixStream::connect(&path).is_err() {
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            path,
+            listener: Generic::new(listener, Interest::READ, Mode::Level),
+            clients: Vec::new(),
+        })
+    }
+
+    /// `$XDG_RUNTIME_DIR/smithay-wallpaper.sock`
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("smithay-wallpaper.sock"))
+    }
+
+    /// Path of the socket
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for IpcSource {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads what arrived of the request, returns the line once it is complete or the client hung up
+fn read_line(mut stream: &UnixStream, buffer: &mut Vec<u8>) -> io::Result<Option<String>> {
+    let mut chunk = [0; 512];
+    loop {
+        if let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            return Ok(Some(String::from_utf8_lossy(&buffer[..end]).into_owned()));
+        }
+        if buffer.len() > MAX_LINE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request line too long",
+            ));
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return Ok(Some(String::from_utf8_lossy(buffer).into_owned())),
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Writes `reply` to the client
+fn write_reply(mut stream: &UnixStream, reply: IpcReply) -> io::Result<()> {
+    match reply {
+        Ok(lines) => {
+            for line in lines {
+                writeln!(stream, "{}", line)?;
+            }
+            writeln!(stream, "ok")
+        }
+        Err(err) => writeln!(stream, "error: {}", err),
+    }
+}
+
+impl EventSource for IpcSource {
+    type Event = IpcRequest;
+    type Metadata = ();
+    type Ret = IpcReply;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> io::Result<PostAction>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        // new and finished clients are (un)registered by `reregister`
+        let mut changed = false;
+        let clients = &mut self.clients;
+        self.listener
+            .process_events(readiness, token, |_, listener| loop {
+                match listener.accept() {
+                    // a client that cannot be made non-blocking is disconnected right away
+                    Ok((stream, _)) => {
+                        if stream.set_nonblocking(true).is_ok() {
+                            clients.push(Client::new(stream));
+                            changed = true;
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        return Ok(PostAction::Continue)
+                    }
+                    Err(err) => return Err(err),
+                }
+            })?;
+
+        for client in self.clients.iter_mut().filter(|client| client.registered) {
+            let Client {
+                stream,
+                buffer,
+                done,
+                ..
+            } = client;
+            stream.process_events(readiness, token, |_, stream| {
+                let reply = match read_line(stream, buffer) {
+                    Ok(Some(line)) => {
+                        IpcRequest::parse(&line).and_then(|request| callback(request, &mut ()))
+                    }
+                    Ok(None) => return Ok(PostAction::Continue),
+                    Err(err) => Err(err.to_string()),
+                };
+                // the client is dropped either way, there is nobody left to tell if it went away
+                let _ = write_reply(stream, reply);
+                *done = true;
+                Ok(PostAction::Continue)
+            })?;
+            changed |= client.done;
+        }
+
+        Ok(if changed {
+            PostAction::Reregister
+        } else {
+            PostAction::Continue
+        })
+    }
+
+    fn register(&mut self, poll: &mut Poll, token_factory: &mut TokenFactory) -> io::Result<()> {
+        self.listener.register(poll, token_factory)?;
+        for client in &mut self.clients {
+            client.stream.register(poll, token_factory)?;
+            client.registered = true;
+        }
+        Ok(())
+    }
+
+    fn reregister(&mut self, poll: &mut Poll, token_factory: &mut TokenFactory) -> io::Result<()> {
+        self.listener.reregister(poll, token_factory)?;
+        for client in &mut self.clients {
+            if client.done {
+                client.stream.unregister(poll)?;
+            } else if client.registered {
+                client.stream.reregister(poll, token_factory)?;
+            } else {
+                client.stream.register(poll, token_factory)?;
+                client.registered = true;
+            }
+        }
+        self.clients.retain(|client| !client.done);
+        Ok(())
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> io::Result<()> {
+        self.listener.unregister(poll)?;
+        for client in self.clients.iter_mut().filter(|client| client.registered) {
+            client.stream.unregister(poll)?;
+            client.registered = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_for_an_output_or_all() {
+        assert_eq!(
+            IpcRequest::parse("set DP-1 /tmp/a b.png\n"),
+            Ok(IpcRequest::Set {
+                output: Some("DP-1".into()),
+                path: PathBuf::from("/tmp/a b.png"),
+            })
+        );
+        assert_eq!(
+            IpcRequest::parse("set * /tmp/a.png"),
+            Ok(IpcRequest::Set {
+                output: None,
+                path: PathBuf::from("/tmp/a.png"),
+            })
+        );
+    }
+
+    #[test]
+    fn set_needs_a_path() {
+        assert!(IpcRequest::parse("set DP-1").is_err());
+        assert!(IpcRequest::parse("set DP-1  ").is_err());
+        assert!(IpcRequest::parse("set").is_err());
+    }
+
+    #[test]
+    fn next_and_query() {
+        assert_eq!(
+            IpcRequest::parse("next"),
+            Ok(IpcRequest::Next { output: None })
+        );
+        assert_eq!(
+            IpcRequest::parse("next HDMI-A-1"),
+            Ok(IpcRequest::Next {
+                output: Some("HDMI-A-1".into())
+            })
+        );
+        assert_eq!(IpcRequest::parse(" query \n"), Ok(IpcRequest::Query));
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert!(IpcRequest::parse("").is_err());
+        assert!(IpcRequest::parse("reload").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::time::Duration;
+
+use image::{DynamicImage, ImageError, ImageResult, Rgba32FImage};
+use jxl_oxide::{FrameBuffer, JxlImage};
+
+use crate::loader::{self, decode_limits, Loaded};
+
+/// Start of a bare JPEG XL codestream
+const CODESTREAM: [u8; 2] = [0xff, 0x0a];
+/// Start of a JPEG XL file in the ISOBMFF container
+const CONTAINER: [u8; 12] = [
+    0, 0, 0, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+];
+
+fn error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> ImageError {
+    ImageError::IoError(io::Error::new(io::ErrorKind::Other, err))
+}
+
+/// Returns `true` for `.jxl` files
+pub(crate) fn is_jxl(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("jxl"))
+}
+
+/// Returns `true` if `data` starts like a JPEG XL file, which the image crate does not detect
+pub(crate) fn is_jxl_data(data: &[u8]) -> bool {
+    data.starts_with(&CODESTREAM) || data.starts_with(&CONTAINER)
+}
+
+/// Decodes the JPEG XL file at `path`, keeping all frames if it is animated
+pub(crate) fn open(path: &Path) -> ImageResult<Loaded> {
+    let image = JxlImage::builder().open(path).map_err(error)?;
+    load(image)
+}
+
+/// Decodes a JPEG XL file in memory, like [`open`] does for files
+pub(crate) fn decode(data: &[u8]) -> ImageResult<Loaded> {
+    let image = JxlImage::builder().read(Cursor::new(data)).map_err(error)?;
+    load(image)
+}
+
+fn load(image: JxlImage) -> ImageResult<Loaded> {
+    let limits = decode_limits();
+    let (width, height) = (image.width(), image.height());
+    limits.check_rgba((width, height))?;
+
+    let animation = image.image_header().metadata.animation.clone();
+    let frames = image.num_loaded_keyframes();
+    let animation = match animation {
+        Some(animation) if frames > 1 => animation,
+        _ => {
+            let render = image.render_frame(0).map_err(error)?;
+            return to_image(render.image()).map(Loaded::Image);
+        }
+    };
+
+    // the duration of a frame is counted in ticks of the animation
+    let tick = animation.tps_denominator as f64 / animation.tps_numerator.max(1) as f64;
+    let mut bytes = 0u64;
+    let mut decoded = Vec::with_capacity(frames);
+    for index in 0..frames {
+        bytes = bytes.saturating_add(width as u64 * height as u64 * 4);
+        limits.check_bytes(bytes)?;
+        let render = image.render_frame(index).map_err(error)?;
+        let delay = Duration::from_secs_f64(render.duration() as f64 * tick);
+        // frames are kept in 8 bits like those of every other animation
+        let frame = DynamicImage::ImageRgba8(to_image(render.image())?.into_rgba8());
+        decoded.push((frame, loader::frame_delay(delay)));
+    }
+    Ok(Loaded::Animation(decoded))
+}
+
+/// Converts the interleaved channels of `buffer` to an RGBA image in floats,
+/// which the loader keeps for HDR or converts to 8 bits
+fn to_image(buffer: FrameBuffer) -> ImageResult<DynamicImage> {
+    let (width, height) = (buffer.width() as u32, buffer.height() as u32);
+    let rgba = |pixel: &[f32]| match *pixel {
+        [l] => Some([l, l, l, 1.0]),
+        [l, a] => Some([l, l, l, a]),
+        [r, g, b] => Some([r, g, b, 1.0]),
+        [r, g, b, a] => Some([r, g, b, a]),
+        _ => None,
+    };
+    let data = buffer
+        .buf()
+        .chunks_exact(buffer.channels())
+        .map(rgba)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| error(format!("unsupported {} channel image", buffer.channels())))?
+        .concat();
+    Rgba32FImage::from_raw(width, height, data)
+        .map(DynamicImage::ImageRgba32F)
+        .ok_or_else(|| error("invalid image buffer"))
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Returns `true` for directories containing a Plasma wallpaper package
+pub(crate) fn is_package(path: &Path) -> bool {
+    path.join("contents").is_dir()
+        && (path.join("metadata.json").is_file() || path.join("metadata.desktop").is_file())
+}
+
+/// Returns the largest image of `dir`, going by the `WIDTHxHEIGHT` file names Plasma uses
+fn largest(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let size = |path: &Path| {
+        let (width, height) = path.file_stem()?.to_str()?.split_once('x')?;
+        Some(width.parse::<u64>().ok()? * height.parse::<u64>().ok()?)
+    };
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images
+        .into_iter()
+        .max_by_key(|path| size(path).unwrap_or(0)))
+}
+
+/// A Plasma wallpaper package, showing its dark variant at night if it has one
+#[derive(Debug)]
+pub(crate) struct PlasmaPackage {
+    light: PathBuf,
+    dark: Option<PathBuf>,
+    shown: Option<PathBuf>,
+    /// `None` before the first poll and without a dark variant
+    next: Option<Instant>,
+}
+
+impl PlasmaPackage {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let contents = path.join("contents");
+        let dark = match largest(&contents.join("images_dark")) {
+            Ok(dark) => dark,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        let light = largest(&contents.join("images"))?
+            .or_else(|| dark.clone())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no images in {}", contents.display()),
+                )
+            })?;
+        Ok(Self {
+            light,
+            dark,
+            shown: None,
+            next: None,
+        })
+    }
+
+    /// When the variant changes next, `None` without a dark variant once the image is shown
+    pub fn deadline(&self, now: Instant) -> Option<Instant> {
+        match self.next {
+            Some(next) => Some(next),
+            None => self.shown.is_none().then(|| now),
+        }
+    }
+
+    /// Returns the image to show once the variant changed at `now`
+    pub fn poll(&mut self, now: Instant) -> Option<PathBuf> {
+        if self.deadline(now).map_or(true, |next| now < next) {
+            return None;
+        }
+
+        let path = match &self.dark {
+            Some(dark) => {
+                let (day, until) = crate::timeline::daylight();
+                self.next = Some(now + until);
+                if day {
+                    &self.light
+                } else {
+                    dark
+                }
+            }
+            None => {
+                self.next = None;
+                &self.light
+            }
+        };
+        if self.shown.as_ref() == Some(path) {
+            return None;
+        }
+        self.shown = Some(path.clone());
+        Some(path.clone())
+    }
+}
@@ -0,0 +1,89 @@
+use std::cell::RefCell;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use image::{DynamicImage, ImageError, ImageResult};
+
+use crate::loader::{self, Loaded};
+use crate::CachedTexture;
+
+/// Returns `true` if `path` is a layered wallpaper manifest
+pub(crate) fn is_layers(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("layers"))
+}
+
+fn invalid(err: String) -> ImageError {
+    ImageError::IoError(io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Decodes the layers listed in the manifest at `path`, from back to front.
+///
+/// Every line holds the depth of a layer and the path of its image, relative to the manifest:
+///
+/// ```text
+/// # depth from 0.0 (still) to 1.0 (moves the most)
+/// 0.0 sky.png
+/// 0.4 mountains.png
+/// 1.0 trees.png
+/// ```
+pub(crate) fn load(path: &Path) -> ImageResult<Vec<(DynamicImage, f64)>> {
+    let manifest = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut layers = Vec::new();
+    for (number, line) in manifest.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (depth, file) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| invalid(format!("line {}: expected a depth and a path", number + 1)))?;
+        let depth = depth
+            .parse::<f64>()
+            .map_err(|err| invalid(format!("line {}: {}", number + 1, err)))?;
+        let image = match loader::read(&dir.join(file.trim()))? {
+            Loaded::Image(image) => image,
+            // layers are still images, animations show their first frame
+            Loaded::Animation(mut frames) => frames.remove(0).0,
+            Loaded::Layers(_) => {
+                return Err(invalid(format!(
+                    "line {}: layers cannot be nested",
+                    number + 1
+                )))
+            }
+            #[cfg(feature = "heic")]
+            Loaded::Source(_) => {
+                return Err(invalid(format!(
+                    "line {}: dynamic wallpapers cannot be layers",
+                    number + 1
+                )))
+            }
+        };
+        layers.push((image, depth.max(0.0)));
+    }
+    if layers.is_empty() {
+        return Err(invalid(String::from("no layers")));
+    }
+    Ok(layers)
+}
+
+/// A single image of a layered wallpaper
+#[derive(Debug)]
+pub(crate) struct Layer {
+    pub image: Rc<Option<DynamicImage>>,
+    pub texture: Rc<RefCell<Option<CachedTexture>>>,
+    /// How much the layer moves, relative to the parallax strength
+    pub depth: f64,
+}
+
+impl Layer {
+    pub fn new(image: DynamicImage, depth: f64) -> Self {
+        Self {
+            image: Rc::new(Some(image)),
+            texture: Rc::new(RefCell::new(None)),
+            depth,
+        }
+    }
+}
@@ -1,30 +1,31 @@
-#![feature(thread_is_running)]
-
-#[cfg(feature = "async_loading")]
-use std::thread;
+use std::time::{Duration, Instant};
 
+use std::any::Any;
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::ptr;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
 
 use std::path::{Path, PathBuf};
 
-use image::{DynamicImage, ImageResult};
+use image::DynamicImage;
+
+use loader::Loaded;
 use smithay::utils::Transform;
 use smithay::{
+    backend::allocator::dmabuf::Dmabuf,
     backend::renderer::{
-        gles2::{Gles2Frame, Gles2Renderer},
-        Frame,
+        gles2::{Gles2Renderer, Gles2Texture},
+        Frame, ImportDma, ImportMem, Renderer,
     },
-    desktop::space::RenderZindex,
-    utils::{Logical, Physical, Rectangle, Size},
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Size},
     wayland::seat::{Keysym, ModifiersState},
 };
 
 #[cfg(feature = "render_element")]
 use smithay::{
-    backend::renderer::gles2::{Gles2Error, Gles2Texture},
-    desktop::space::{RenderElement, SpaceOutputTuple},
-    utils::Point,
+    backend::renderer::Texture,
+    desktop::space::{RenderElement, RenderZindex, SpaceOutputTuple},
 };
 
 #[cfg(feature = "render_element")]
@@ -36,7 +37,138 @@ use std::{
     },
 };
 
+mod animation;
+#[cfg(feature = "audio")]
+mod audio;
+mod background;
+mod blur;
+mod blurhash;
+mod cache;
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(feature = "config")]
+mod config;
+mod context;
+#[cfg(feature = "dbus")]
+mod dbus;
+#[cfg(feature = "async_loading")]
+mod directory;
+mod dither;
+mod error;
+mod event;
+#[cfg(feature = "async_loading")]
+mod fallback;
+mod filter;
+#[cfg(feature = "gnome")]
+mod gnome;
+#[cfg(feature = "heic")]
+mod heic;
+#[cfg(feature = "heif")]
+mod heif;
+#[cfg(feature = "icc")]
+mod icc;
+#[cfg(feature = "ipc")]
+mod ipc;
+#[cfg(feature = "jxl")]
+mod jxl;
+#[cfg(feature = "kde")]
+mod kde;
+mod layers;
+mod loader;
+mod lut;
+mod manager;
+mod motion;
+#[cfg(feature = "mpv")]
+mod mpv;
+mod palette;
+#[cfg(feature = "async_loading")]
+mod pool;
+#[cfg(feature = "portal")]
+mod portal;
+mod power;
+#[cfg(feature = "remote")]
+mod provider;
+#[cfg(feature = "pywal")]
+mod pywal;
+#[cfg(feature = "remote")]
+mod remote;
+mod scaling;
+mod scheme;
+mod shader;
+#[cfg(feature = "async_loading")]
+mod slideshow;
+#[cfg(feature = "software")]
+mod software;
+#[cfg(feature = "timeline")]
+mod solar;
+#[cfg(feature = "calloop")]
+mod source;
+mod stats;
+#[cfg(feature = "svg")]
+mod svg;
+#[cfg(feature = "timeline")]
+mod timeline;
 mod tools;
+mod transition;
+mod upload;
+#[cfg(feature = "video")]
+mod video;
+#[cfg(feature = "watch")]
+mod watch;
+mod workspace;
+
+pub use background::{ColorStop, GradientKind, GradientSpec};
+pub use cache::{set_texture_budget, texture_budget, RetentionPolicy};
+#[cfg(feature = "config")]
+pub use config::{OutputConfig, WallpaperConfig};
+#[cfg(feature = "dbus")]
+pub use dbus::{DbusServer, DBUS_NAME, DBUS_PATH};
+#[cfg(feature = "async_loading")]
+pub use directory::{SelectionOrder, SelectionPolicy};
+pub use error::WallpaperError;
+pub use event::WallpaperEvent;
+#[cfg(feature = "async_loading")]
+pub use fallback::{Fallback, RetryPolicy};
+pub use filter::{Effects, PostShader, ToneMapping};
+#[cfg(feature = "ipc")]
+pub use ipc::{IpcReply, IpcRequest, IpcSource};
+pub use loader::{decode_limits, set_decode_limits, DecodeLimits, Resampling};
+pub use lut::Lut;
+pub use manager::WallpaperManager;
+pub use motion::{Easing, KenBurns};
+#[cfg(feature = "mpv")]
+pub use mpv::MpvOptions;
+pub use palette::Edge;
+#[cfg(feature = "portal")]
+pub use portal::{PORTAL_NAME, PORTAL_PATH};
+#[cfg(feature = "upower")]
+pub use power::PowerSourceWatcher;
+pub use power::{PowerPolicy, PowerSource};
+#[cfg(feature = "remote")]
+pub use provider::{Bing, NasaApod, Provider};
+pub use scaling::{Anchor, ScalingMode};
+pub use scheme::ColorScheme;
+#[cfg(feature = "dbus")]
+pub use scheme::ColorSchemeWatcher;
+#[cfg(feature = "timeline")]
+pub use solar::{
+    day_phase, location, set_location_provider, sun_position, Coordinates, DayPhase,
+    LocationProvider, SunPosition,
+};
+#[cfg(feature = "calloop")]
+pub use source::WallpaperSource;
+pub use stats::WallpaperStats;
+#[cfg(feature = "timeline")]
+pub use timeline::Timeline;
+pub use transition::Transition;
+pub use workspace::WorkspaceWallpapers;
+
+/// Time `WallpaperState::set_dim` takes to reach the new dimming
+const DIM_DURATION: Duration = Duration::from_millis(250);
+
+/// Cross-fade between light and dark variants if no `Transition` is set
+#[cfg(feature = "async_loading")]
+const VARIANT_FADE: Duration = Duration::from_secs(1);
 
 #[cfg(feature = "render_element")]
 static WALLPAPER_ID: AtomicUsize = AtomicUsize::new(0);
@@ -57,148 +189,3313 @@ fn next_id() -> usize {
     id
 }
 
+/// Unique id of a `WallpaperState`, which is released again once the state is dropped
+#[derive(Debug)]
+struct WallpaperId(usize);
+
+impl Default for WallpaperId {
+    fn default() -> Self {
+        #[cfg(feature = "render_element")]
+        let id = next_id();
+        #[cfg(not(feature = "render_element"))]
+        let id = 0;
+        WallpaperId(id)
+    }
+}
+
+#[cfg(feature = "render_element")]
+impl Drop for WallpaperId {
+    fn drop(&mut self) {
+        WALLPAPER_IDS.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// The uploaded texture of an image and the animation frame it currently holds
+///
+/// The texture type depends on the renderer used to draw the wallpaper.
+#[derive(Debug)]
+struct CachedTexture {
+    texture: Box<dyn Any>,
+    frame: usize,
+    /// Size of the image, which may be gone already
+    size: Size<i32, Buffer>,
+    /// The texture has mipmaps, so it can be drawn smaller without shimmering
+    mipmaps: bool,
+    /// The texture holds the floats of an HDR image, which are tone mapped while drawing
+    hdr: bool,
+    /// The texture is in an sRGB format, so sampling it decodes to linear light
+    srgb: bool,
+    /// Imported for drawing in linear light, see [`WallpaperState::set_linear_blending`]
+    linear: bool,
+    /// Imported from a dmabuf the renderer samples as an external image, e.g. in YUV,
+    /// which only it can draw, without the filter
+    external: bool,
+    /// Holds ETC blocks, which cannot be updated with pixels
+    compressed: bool,
+    /// EGL context of the renderer that imported the texture, see [`context::id`]
+    context: usize,
+    /// Memory the texture takes in its format, including mipmaps
+    bytes: usize,
+}
+
+/// Sums the bytes of the `textures`, counting slots that appear several times once
+fn texture_bytes(textures: &[&Rc<RefCell<Option<CachedTexture>>>]) -> usize {
+    textures
+        .iter()
+        .enumerate()
+        .filter(|(i, texture)| {
+            !textures[..*i]
+                .iter()
+                .any(|other| Rc::ptr_eq(other, texture))
+        })
+        .filter_map(|(_, texture)| texture.borrow().as_ref().map(|cached| cached.bytes))
+        .sum()
+}
+
+/// An image that stays on screen while the next one streams in, with its texture and frame
+type Held = (
+    Rc<Option<DynamicImage>>,
+    Rc<RefCell<Option<CachedTexture>>>,
+    usize,
+);
+
+impl CachedTexture {
+    /// Returns `true` if the texture was imported by a renderer with textures of type `T`,
+    /// whole or split into tiles
+    fn is<T: 'static>(&self) -> bool {
+        self.texture.is::<T>() || self.texture.is::<tools::Tiles<T>>()
+    }
+}
+
 /// Global smithay-egui state
+///
+/// Color adjustments, the LUT, post shaders, dithering, linear blending and the blur
+/// are only drawn by the `Gles2Renderer`, other renderers draw the image without them.
 #[derive(Debug, Default)]
 pub struct WallpaperState {
-    id: usize,
+    id: WallpaperId,
+    #[cfg(feature = "async_loading")]
+    loading: Option<loader::Pending>,
+    /// Incremented for every load started, results of older loads are dropped
+    #[cfg(feature = "async_loading")]
+    generation: u64,
     #[cfg(feature = "async_loading")]
-    join: Option<thread::JoinHandle<ImageResult<DynamicImage>>>,
+    slideshow: Option<slideshow::Slideshow>,
+    #[cfg(feature = "async_loading")]
+    directory: Option<directory::Directory>,
+    #[cfg(feature = "async_loading")]
+    notify: loader::Notify,
+    #[cfg(feature = "remote")]
+    provider: Option<provider::Refresh>,
+    #[cfg(feature = "timeline")]
+    timeline: Option<timeline::Schedule>,
+    #[cfg(feature = "gnome")]
+    gnome: Option<gnome::GnomeSlideshow>,
+    #[cfg(feature = "heic")]
+    heic: Option<heic::DynamicHeic>,
+    #[cfg(feature = "kde")]
+    kde: Option<kde::PlasmaPackage>,
+    #[cfg(feature = "async_loading")]
+    next_transition: Option<Transition>,
+    #[cfg(feature = "async_loading")]
+    variants: Option<(PathBuf, PathBuf)>,
+    #[cfg(feature = "async_loading")]
+    prescale: bool,
+    #[cfg(feature = "async_loading")]
+    resampling: Resampling,
+    #[cfg(feature = "async_loading")]
+    fallbacks: Vec<Fallback>,
+    /// Index of the next fallback to show if the current one fails as well
+    #[cfg(feature = "async_loading")]
+    next_fallback: usize,
+    #[cfg(feature = "async_loading")]
+    retry_policy: Option<RetryPolicy>,
+    /// Retries of the current file so far
+    #[cfg(feature = "async_loading")]
+    attempts: u32,
+    #[cfg(feature = "async_loading")]
+    retry: Option<(PathBuf, Instant)>,
+    color_scheme: ColorScheme,
+    #[cfg(feature = "calloop")]
+    waker: Option<source::Waker>,
     image: Rc<Option<DynamicImage>>,
-    texture: Rc<RefCell<Option<Gles2Texture>>>,
+    texture: Rc<RefCell<Option<CachedTexture>>>,
+    scaling: ScalingMode,
+    anchor: Anchor,
+    motion: Option<motion::Motion>,
+    parallax: f64,
+    transition: Transition,
+    fading: Option<transition::Fading>,
+    animation: Option<animation::Animation>,
+    layers: Vec<layers::Layer>,
+    workspace_position: Option<f64>,
+    #[cfg(feature = "video")]
+    video: Option<video::Video>,
+    #[cfg(feature = "mpv")]
+    mpv: Option<Rc<mpv::Player>>,
+    dmabuf: Option<Dmabuf>,
+    #[cfg(feature = "compress")]
+    compression: bool,
+    #[cfg(feature = "compress")]
+    compression_job: Option<compress::Compression>,
+    #[cfg(feature = "compress")]
+    compressed: Option<Rc<compress::Compressed>>,
+    background: Option<background::Background>,
+    #[cfg(feature = "svg")]
+    svg: Option<svg::Svg>,
+    #[cfg(feature = "watch")]
+    watch: Option<watch::Watch>,
+    shader: Option<Rc<shader::Shader>>,
+    #[cfg(feature = "audio")]
+    audio: Option<audio::AudioCapture>,
+    blur: Option<Rc<blur::Blur>>,
+    dim: transition::Animated,
+    dim_texture: Rc<RefCell<Option<CachedTexture>>>,
+    temperature: Option<f32>,
+    anisotropy: f32,
+    tone_mapping: ToneMapping,
+    /// In stops
+    exposure: f32,
+    linear_blending: bool,
+    dither: bool,
+    effects: Effects,
+    lut: Option<Lut>,
+    filter: Rc<filter::Filter>,
+    colors: palette::Analysis,
+    palette_job: Option<palette::Extraction>,
+    palette_callback: palette::Callback,
+    #[cfg(feature = "pywal")]
+    pywal: bool,
+    pointer: Option<Point<f64, Logical>>,
+    span: Option<Rectangle<i32, Logical>>,
+    viewport: Option<Rectangle<f64, Logical>>,
+    frame: usize,
+    dirty: bool,
+    output: Option<(Rectangle<i32, Physical>, Size<i32, Physical>)>,
+    /// The output is off or disabled, see [`WallpaperState::set_output_active`]
+    output_off: bool,
+    occluded: bool,
+    power_source: PowerSource,
+    battery_policy: PowerPolicy,
+    /// See [`WallpaperState::set_max_fps`]
+    max_fps: Option<f64>,
+    /// When animated content changed last, limited by `max_fps` and the battery policy
+    pacing: power::Pacing,
+    /// Time set by [`WallpaperState::tick`], the system clock is used until it is first called
+    clock: Option<Instant>,
+    error: Option<Arc<WallpaperError>>,
+    error_placeholder: bool,
+    retention: RetentionPolicy,
+    /// Streams large images in over several frames, see [`WallpaperState::set_streaming_upload`]
+    uploader: Option<Rc<upload::Uploader>>,
+    held: Option<Held>,
+    context: Rc<context::Tracker>,
+    /// The image and texture last looked at for transparency, and the size of the image if it has none
+    opaque: (
+        Weak<Option<DynamicImage>>,
+        Weak<RefCell<Option<CachedTexture>>>,
+        Option<Size<i32, Buffer>>,
+    ),
+    #[cfg(feature = "render_element")]
+    z_index: Option<u8>,
+    events: event::Events,
+    stats: Rc<stats::Counters>,
 }
 
 /// A single rendered egui interface frame
 pub struct WallpaperFrame {
     state_id: usize,
     area: Rectangle<i32, Physical>,
-    size: Size<i32, Physical>,
+    span: Option<Rectangle<i32, Physical>>,
+    viewport: Option<Rectangle<f64, Logical>>,
+    scale: f64,
     image: Rc<Option<DynamicImage>>,
-    texture: Rc<RefCell<Option<Gles2Texture>>>,
+    texture: Rc<RefCell<Option<CachedTexture>>>,
+    frame: usize,
+    /// The animation frame shown before and the region `frame` differs from it in
+    changed: Option<(usize, Rectangle<i32, Buffer>)>,
+    dmabuf: Option<Dmabuf>,
+    #[cfg(feature = "mpv")]
+    mpv: Option<Rc<mpv::Player>>,
+    #[cfg(feature = "compress")]
+    compressed: Option<Rc<compress::Compressed>>,
+    scaling: ScalingMode,
+    anchor: Anchor,
+    views: Vec<motion::View>,
+    layers: Vec<(
+        Rc<Option<DynamicImage>>,
+        Rc<RefCell<Option<CachedTexture>>>,
+        Vec<motion::View>,
+    )>,
+    fading: Option<transition::Fading>,
+    uploader: Option<Rc<upload::Uploader>>,
+    held: Option<Held>,
+    context: Rc<context::Tracker>,
+    /// Size of the image if it covers what it is drawn over, see [`WallpaperFrame::opaque_regions`]
+    opaque: Option<Size<i32, Buffer>>,
+    #[cfg(feature = "render_element")]
+    z_index: u8,
+    shader: Option<(Rc<shader::Shader>, shader::Uniforms)>,
+    blur: Option<Rc<blur::Blur>>,
+    dim: f32,
+    dim_texture: Rc<RefCell<Option<CachedTexture>>>,
+    filter: Rc<filter::Filter>,
+    params: filter::Params,
+    size: Size<i32, Physical>,
+    transform: Transform,
+    damaged: bool,
+    stats: Rc<stats::Counters>,
 }
 
 impl WallpaperState {
     /// Creates a new `WallpaperState`
     pub fn new() -> Self {
-        Self {
-            id: next_id(),
-            ..Default::default()
+        Self::default()
+    }
+
+    /// Imports the texture of the current image ahead of time, so the first draw does not stall
+    pub fn upload<R>(&mut self, r: &mut R) -> Result<(), WallpaperError>
+    where
+        R: Renderer + ImportMem + 'static,
+        <R as Renderer>::TextureId: 'static,
+        <R as Renderer>::Error: Send + Sync,
+    {
+        #[cfg(feature = "async_loading")]
+        self.check();
+        for layer in &self.layers {
+            if let Some(image) = &*layer.image {
+                prepare_texture(
+                    r,
+                    image,
+                    &layer.texture,
+                    0,
+                    self.linear_blending,
+                    &self.stats,
+                )?;
+            }
+        }
+        if let Some(image) = &*self.image {
+            prepare_texture(
+                r,
+                image,
+                &self.texture,
+                self.frame,
+                self.linear_blending,
+                &self.stats,
+            )?;
+        }
+        if let Some(dmabuf) = &self.dmabuf {
+            prepare_dmabuf(r, dmabuf, &self.texture, self.frame)?;
+        }
+        self.release_images();
+        Ok(())
+    }
+
+    /// Frees the decoded images whose textures are uploaded, if the [`RetentionPolicy`] allows it
+    fn release_images(&mut self) {
+        if self.retention != RetentionPolicy::DropAfterUpload
+            || self.animation.is_some()
+            || self.background.is_some()
+        {
+            return;
+        }
+        #[cfg(feature = "video")]
+        if self.video.is_some() {
+            return;
+        }
+        #[cfg(feature = "mpv")]
+        if self.mpv.is_some() {
+            return;
+        }
+        if self.image.is_some() && self.texture.borrow().is_some() {
+            self.image = Rc::new(None);
+        }
+        for layer in &mut self.layers {
+            if layer.image.is_some() && layer.texture.borrow().is_some() {
+                layer.image = Rc::new(None);
+            }
+        }
+    }
+
+    /// Looks for transparent pixels in the image, once per image.
+    ///
+    /// Frames of animations and videos are only checked for an alpha channel, looking at every pixel
+    /// takes too long. Images freed after their upload keep what was found while they were loaded.
+    fn check_opaque(&mut self) {
+        let (image, texture, _) = &self.opaque;
+        if ptr::eq(image.as_ptr(), Rc::as_ptr(&self.image)) {
+            return;
+        }
+        #[cfg(feature = "video")]
+        let animated = self.animation.is_some() || self.video.is_some();
+        #[cfg(not(feature = "video"))]
+        let animated = self.animation.is_some();
+        let size = match &*self.image {
+            Some(image) if animated && image.color().has_alpha() => None,
+            Some(image) if !animated && !tools::is_opaque(image) => None,
+            Some(image) => Some(Size::from((image.width() as i32, image.height() as i32))),
+            None if ptr::eq(texture.as_ptr(), Rc::as_ptr(&self.texture)) => self.opaque.2,
+            None => None,
+        };
+        self.opaque = (
+            Rc::downgrade(&self.image),
+            Rc::downgrade(&self.texture),
+            size,
+        );
+    }
+
+    /// Returns `true` if an image is shown, even if it was freed after the upload
+    fn has_image(&self) -> bool {
+        self.image.is_some() || self.texture.borrow().is_some()
+    }
+
+    /// Forgets all textures and GL programs without deleting them,
+    /// for when the compositor recreated its renderer, e.g. after a GPU reset.
+    ///
+    /// They are created again on the next draw, textures from the retained images,
+    /// so with [`RetentionPolicy::DropAfterUpload`] still images stay blank until the next one is set.
+    /// Another `Gles2Renderer`, like that of a second GPU, gets textures and programs of its own
+    /// without calling this.
+    pub fn invalidate_textures(&mut self) {
+        self.context.invalidate();
+        self.dirty = true;
+    }
+
+    /// Releases all GPU resources this state holds of `renderer`.
+    ///
+    /// Textures are imported again the next time the wallpaper is drawn.
+    pub fn destroy<R: Renderer + 'static>(&mut self, renderer: &mut R) {
+        let context = context::id(&*renderer);
+        let lut = self.lut.iter().map(|lut| &lut.texture);
+        let fading = self.fading.iter().map(|fading| &fading.texture);
+        let layers = self.layers.iter().map(|layer| &layer.texture);
+        for texture in [&self.texture, &self.dim_texture]
+            .into_iter()
+            .chain(lut)
+            .chain(fading)
+            .chain(layers)
+        {
+            context::select(texture, context);
+        }
+        *self.texture.borrow_mut() = None;
+        *self.dim_texture.borrow_mut() = None;
+        for layer in &self.layers {
+            *layer.texture.borrow_mut() = None;
+        }
+        if let Some(fading) = self.fading.take() {
+            *fading.texture.borrow_mut() = None;
+        }
+        if let Some(renderer) = (renderer as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+            if let Some(shader) = &self.shader {
+                shader.destroy(renderer);
+            }
+            if let Some(blur) = &self.blur {
+                blur.destroy(renderer);
+            }
+            #[cfg(feature = "mpv")]
+            if let Some(player) = &self.mpv {
+                player.destroy(renderer);
+            }
+            self.filter.destroy(renderer);
+            if let Some(uploader) = &self.uploader {
+                uploader.destroy(renderer);
+            }
+            if let Some(lut) = &self.lut {
+                *lut.texture.borrow_mut() = None;
+            }
         }
     }
 
     #[cfg(feature = "async_loading")]
     fn check(&mut self) {
-        if let Some(join) = self.join.take() {
-            if !join.is_running() {
-                if let Ok(Ok(image)) = join.join() {
-                    self.image = Rc::new(Some(image))
-                } else {
-                    println!("error loading image");
+        #[cfg(feature = "watch")]
+        let changed = self.watch.as_ref().map(|watch| match watch.poll() {
+            Ok(changed) => Ok(changed.then(|| watch.path().to_path_buf())),
+            Err(err) => Err(WallpaperError::Watch(watch.path().to_path_buf(), err)),
+        });
+        #[cfg(feature = "watch")]
+        match changed {
+            Some(Ok(Some(path))) => self.reload(path),
+            Some(Err(err)) => {
+                self.report(err);
+            }
+            _ => {}
+        }
+
+        if self
+            .retry
+            .as_ref()
+            .map_or(false, |(_, at)| *at <= self.now())
+        {
+            if let Some((path, _)) = self.retry.take() {
+                self.start_loading(loader::Pending::open(path, self.notify.clone()));
+            }
+        }
+
+        if let Some(preview) = self
+            .loading
+            .as_ref()
+            .filter(|l| l.generation() == self.generation)
+            .and_then(|l| l.poll_preview())
+        {
+            self.set_loaded(Loaded::Image(preview), None);
+            // the full image replaces its preview without a transition
+            self.next_transition = Some(Transition::None);
+        }
+        if let Some(completed) = self
+            .loading
+            .as_ref()
+            .filter(|l| l.generation() == self.generation)
+            .and_then(|l| l.poll())
+        {
+            let result = self.completed(completed);
+            let path = self
+                .loading
+                .take()
+                .and_then(|l| l.path().map(Path::to_path_buf));
+            match result {
+                // the current image stays until the first image of the source is decoded
+                #[cfg(feature = "heic")]
+                Ok((Loaded::Source(source), _)) => self.set_source(source),
+                Ok((loaded, key)) => {
+                    self.set_loaded(loaded, key);
+                    self.events.emit(WallpaperEvent::Loaded);
                 }
-            } else {
-                self.join = Some(join);
+                Err(err) => self.load_failed(err, path),
+            }
+        }
+
+        let now = self.now();
+        #[cfg(feature = "remote")]
+        if let Some(load) = self.provider.as_mut().and_then(|p| p.poll(now)) {
+            self.start_loading(loader::Pending::spawn_download(load, self.notify.clone()));
+        }
+
+        #[cfg(feature = "timeline")]
+        if let Some(timeline) = &mut self.timeline {
+            if let Some(path) = timeline.poll(now) {
+                self.next_transition = Some(timeline.transition());
+                self.start_loading(loader::Pending::spawn(
+                    move || loader::open(path),
+                    self.notify.clone(),
+                ));
+            }
+        }
+
+        #[cfg(feature = "gnome")]
+        if let Some((path, fade)) = self.gnome.as_mut().and_then(|g| g.poll(now)) {
+            self.next_transition = Some(fade.map_or(Transition::None, Transition::Fade));
+            self.start_loading(loader::Pending::spawn(
+                move || loader::open(path),
+                self.notify.clone(),
+            ));
+        }
+
+        #[cfg(feature = "heic")]
+        if let Some((path, index)) = self.heic.as_mut().and_then(|h| h.poll(now)) {
+            self.start_loading(loader::Pending::spawn(
+                move || heif::decode_file(&path, Some(index)).map(Loaded::Image),
+                self.notify.clone(),
+            ));
+        }
+
+        #[cfg(feature = "kde")]
+        if let Some(path) = self.kde.as_mut().and_then(|k| k.poll(now)) {
+            self.start_loading(loader::Pending::spawn(
+                move || loader::open(path),
+                self.notify.clone(),
+            ));
+        }
+
+        let prescale = self.prescale_target();
+        match self
+            .slideshow
+            .as_mut()
+            .and_then(|s| s.poll(prescale, now))
+            .map(|completed| self.completed(completed))
+        {
+            Some(Ok((loaded, key))) => {
+                #[cfg(feature = "svg")]
+                {
+                    self.svg = None;
+                }
+                self.set_loaded(loaded, key);
+                self.events.emit(WallpaperEvent::Loaded);
             }
+            Some(Err(err)) => self.load_failed(err, None),
+            None => {}
         }
     }
 
-    /// Produce a new frame of egui to draw onto your output buffer.
-    ///
-    /// - `ui` is your drawing function
-    /// - `area` limits the space egui will be using.
-    /// - `size` has to be the total size of the buffer the ui will be displayed in
-    /// - `scale` is the scale egui should render in
-    /// - `start_time` need to be a fixed point in time before the first `run` call to measure animation-times and the like.
-    /// - `modifiers` should be the current state of modifiers pressed on the keyboards.
-    pub fn run(
+    /// Shows the images of a `source` opened on the loader threads from now on
+    #[cfg(feature = "heic")]
+    fn set_source(&mut self, source: loader::Source) {
+        match source {
+            loader::Source::Heic(dynamic) => self.heic = Some(dynamic),
+            #[cfg(feature = "kde")]
+            loader::Source::Package(package) => self.kde = Some(package),
+        }
+    }
+
+    /// Adds the decodes of a finished load to the stats, reports the errors it recovered from
+    /// and returns its result
+    #[cfg(feature = "async_loading")]
+    fn completed(
         &mut self,
-        area: Rectangle<i32, Logical>,
-        size: Size<i32, Physical>,
-    ) -> WallpaperFrame {
-        let area = area.to_physical(1);
+        completed: loader::Completed,
+    ) -> image::ImageResult<(Loaded, Option<cache::Key>)> {
+        self.stats.record_decodes(completed.decodes);
+        for err in completed.warnings {
+            self.report(err);
+        }
+        completed.result
+    }
 
-        #[cfg(feature = "async_loading")]
-        self.check();
+    /// Replaces the pending load with `pending`, cancelling the previous one
+    #[cfg(feature = "async_loading")]
+    fn start_loading(&mut self, pending: loader::Pending) {
+        self.generation += 1;
+        let prescale = self.prescale_target();
+        self.loading = Some(
+            pending
+                .with_generation(self.generation)
+                .with_prescale(prescale),
+        );
+    }
 
-        WallpaperFrame {
-            state_id: self.id,
-            area,
-            size,
-            image: self.image.clone(),
-            texture: self.texture.clone(),
+    /// Returns the size images are drawn at if [`WallpaperState::set_prescale`] is enabled,
+    /// once the output is known and the image is not spanned across several outputs
+    #[cfg(feature = "async_loading")]
+    fn prescale_target(&self) -> Option<loader::Prescale> {
+        if !self.prescale || self.span.is_some() {
+            return None;
+        }
+        let (area, _) = self.output?;
+        let ken_burns = self.motion.as_ref().map_or(1.0, |motion| {
+            motion.ken_burns.zoom.0.max(motion.ken_burns.zoom.1)
+        });
+        let zoom = ken_burns.max(1.0 + self.parallax);
+        Some(loader::Prescale {
+            size: (
+                (area.size.w as f64 * zoom).ceil() as u32,
+                (area.size.h as f64 * zoom).ceil() as u32,
+            ),
+            mode: self.scaling,
+            resampling: self.resampling,
+        })
+    }
+
+    /// Loads the watched file again after it changed on disk
+    #[cfg(feature = "watch")]
+    fn reload(&mut self, path: PathBuf) {
+        #[cfg(feature = "svg")]
+        if let Some(svg) = &mut self.svg {
+            svg.rendered = None;
+            return;
         }
+        self.start_loading(loader::Pending::spawn(
+            move || loader::open(path),
+            self.notify.clone(),
+        ));
     }
 
+    /// Tries the file at `path` again if `err` may be transient, reports it otherwise
+    /// and shows the next fallback if there is no image yet
     #[cfg(feature = "async_loading")]
-    pub fn set<P: AsRef<Path>>(&mut self, path: P) {
-        let path = PathBuf::from(path.as_ref());
-        self.join = Some(thread::spawn(move || image::open(path)));
+    fn load_failed(&mut self, err: image::ImageError, path: Option<PathBuf>) {
+        if let (Some(policy), Some(path)) = (self.retry_policy, path) {
+            if fallback::is_transient(&err) && self.attempts < policy.attempts {
+                self.retry = Some((path, self.now() + policy.backoff(self.attempts)));
+                self.attempts += 1;
+                return;
+            }
+        }
+        self.attempts = 0;
+        let blank = !self.has_image() && self.layers.is_empty() && self.shader.is_none();
+        #[cfg(feature = "video")]
+        let blank = blank && self.video.is_none();
+        #[cfg(feature = "mpv")]
+        let blank = blank && self.mpv.is_none();
+        self.set_error(err.into());
+        if !blank {
+            return;
+        }
+        match self.fallbacks.get(self.next_fallback).cloned() {
+            Some(Fallback::Path(path)) => {
+                self.next_fallback += 1;
+                self.start_loading(loader::Pending::open(path, self.notify.clone()));
+            }
+            Some(Fallback::Color(color)) => {
+                self.next_fallback += 1;
+                self.set_loaded(Loaded::Image(background::color(color)), None);
+                self.background = Some(background::Background::Color(color));
+            }
+            None => {}
+        }
     }
-}
 
-impl WallpaperFrame {
-    /// Draw this frame in the currently active GL-context
-    pub fn draw(&self, r: &mut Gles2Renderer, frame: &mut Gles2Frame) -> Result<(), Gles2Error> {
-        if let Some(image) = &*self.image {
-            let mut cached_texture = self.texture.borrow_mut();
-            let texture;
+    /// Remembers a failed load, keeping the current image unless the error placeholder is enabled
+    fn set_error(&mut self, err: WallpaperError) {
+        if self.error_placeholder {
+            self.set_loaded(Loaded::Image(background::error_placeholder()), None);
+        }
+        self.error = Some(self.report(err));
+    }
 
-            if let Some(cached_texture) = &*cached_texture {
-                texture = cached_texture.clone();
-            } else {
-                *cached_texture =
-                    Some(tools::import_bitmap(r, &image.to_rgba8(), self.size.into()).unwrap());
-                texture = cached_texture.as_ref().unwrap().clone();
-            }
-
-            frame.render_texture_at(
-                &texture,
-                Point::<i32, Logical>::from((0, 0))
-                    .to_f64()
-                    .to_physical(1.0)
-                    .to_i32_round(),
-                1,
-                1.0,
-                Transform::Normal,
-                &[Rectangle::from_loc_and_size((0, 0), (i32::MAX, i32::MAX))],
-                1.0,
-            )
-        } else {
-            Ok(())
+    /// Hands `err` to the listeners as [`WallpaperEvent::LoadFailed`]
+    fn report(&mut self, err: WallpaperError) -> Arc<WallpaperError> {
+        let err = Arc::new(err);
+        self.events.emit(WallpaperEvent::LoadFailed(err.clone()));
+        err
+    }
+
+    /// Returns the error of the last failed load, if the following load did not succeed yet
+    pub fn error(&self) -> Option<&WallpaperError> {
+        self.error.as_deref()
+    }
+
+    /// Returns what this wallpaper cost so far and the memory of the textures it currently holds.
+    ///
+    /// Textures shared with other wallpapers showing the same image are counted by each of them,
+    /// [`WallpaperManager::stats`] counts them once.
+    pub fn stats(&self) -> WallpaperStats {
+        let textures = self.textures();
+        self.stats
+            .snapshot(texture_bytes(&textures) + self.own_texture_bytes())
+    }
+
+    /// Returns the texture slots held, which may be shared with other states
+    fn textures(&self) -> Vec<&Rc<RefCell<Option<CachedTexture>>>> {
+        let mut textures = vec![&self.texture];
+        textures.extend(self.fading.as_ref().map(|fading| &fading.texture));
+        textures.extend(self.held.as_ref().map(|(_, texture, _)| texture));
+        textures.push(&self.dim_texture);
+        textures.extend(self.layers.iter().map(|layer| &layer.texture));
+        textures.extend(self.lut.as_ref().map(|lut| &lut.texture));
+        textures
+    }
+
+    /// Returns the bytes of the textures of the blur and the filter, which only this state holds
+    fn own_texture_bytes(&self) -> usize {
+        let blur = self.blur.as_ref().map_or(0, |blur| blur.texture_bytes());
+        blur + self.filter.texture_bytes()
+    }
+
+    /// Shows a generated placeholder instead of keeping the current image if a wallpaper fails to load,
+    /// so a broken wallpaper path is noticed right away.
+    ///
+    /// Fallbacks set by [`WallpaperState::set_fallbacks`] still replace the placeholder.
+    pub fn set_error_placeholder(&mut self, enabled: bool) {
+        self.error_placeholder = enabled;
+    }
+
+    /// Returns `true` if [`WallpaperState::set_error_placeholder`] is enabled
+    pub fn error_placeholder(&self) -> bool {
+        self.error_placeholder
+    }
+
+    /// Sets what is shown, in order, if a wallpaper fails to load while nothing is on screen yet.
+    ///
+    /// A failing fallback image moves on to the next one, so ending the list with a
+    /// [`Fallback::Color`] always shows something.
+    #[cfg(feature = "async_loading")]
+    pub fn set_fallbacks(&mut self, fallbacks: Vec<Fallback>) {
+        self.fallbacks = fallbacks;
+        self.next_fallback = 0;
+    }
+
+    /// Returns the fallbacks set by [`WallpaperState::set_fallbacks`]
+    #[cfg(feature = "async_loading")]
+    pub fn fallbacks(&self) -> &[Fallback] {
+        &self.fallbacks
+    }
+
+    /// Tries files set by [`WallpaperState::set`] again after transient IO errors, `None` to report them right away.
+    ///
+    /// Errors are only reported once all retries failed.
+    #[cfg(feature = "async_loading")]
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+        if policy.is_none() {
+            self.retry = None;
         }
     }
-}
 
-#[cfg(feature = "render_element")]
-impl RenderElement<Gles2Renderer, Gles2Frame, Gles2Error, Gles2Texture> for WallpaperFrame {
-    fn id(&self) -> usize {
-        self.state_id
+    /// Returns the policy set by [`WallpaperState::set_retry_policy`]
+    #[cfg(feature = "async_loading")]
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy
     }
 
-    fn geometry(&self) -> Rectangle<i32, Logical> {
-        Rectangle::<i32, Logical>::from_loc_and_size((0, 0), (0, 0))
+    /// Calls `callback` with every [`WallpaperEvent`], from the methods of this state
+    /// that caused them, like [`WallpaperState::run`].
+    ///
+    /// Errors are only reported through events and [`WallpaperState::error`], nothing is printed.
+    pub fn on_event<F: FnMut(&WallpaperEvent) + 'static>(&mut self, callback: F) {
+        self.events.add_callback(callback);
     }
 
-    fn accumulated_damage(
-        &self,
-        _for_values: Option<SpaceOutputTuple<'_, '_>>,
-    ) -> Vec<Rectangle<i32, Logical>> {
-        vec![]
+    /// Returns a receiver of every [`WallpaperEvent`], like [`WallpaperState::on_event`]
+    pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<WallpaperEvent> {
+        self.events.subscribe()
     }
 
-    fn draw(
-        &self,
-        renderer: &mut Gles2Renderer,
-        frame: &mut Gles2Frame,
-        _scale: f64,
-        _damage: &[Rectangle<i32, Logical>],
-        log: &slog::Logger,
-    ) -> Result<(), Gles2Error> {
-        if let Err(err) = WallpaperFrame::draw(self, renderer, frame) {
-            slog::error!(log, "egui rendering error: {}", err);
+    /// Replaces the displayed image, invalidating the cached texture.
+    ///
+    /// Images with a `key` share their texture with other wallpapers showing the same image.
+    fn set_loaded(&mut self, loaded: Loaded, key: Option<cache::Key>) {
+        self.error = None;
+        #[cfg(feature = "async_loading")]
+        let transition = self.next_transition.take().unwrap_or(self.transition);
+        #[cfg(not(feature = "async_loading"))]
+        let transition = self.transition;
+        // streamed in over several frames, the image shown so far stays until then
+        self.held = match transition {
+            Transition::Fade(_) => None,
+            _ if self.uploader.is_some() && self.has_image() => {
+                Some((self.image.clone(), self.texture.clone(), self.frame))
+            }
+            _ => None,
+        };
+        if let Transition::Fade(duration) = transition {
+            if self.has_image() {
+                self.fading = Some(transition::Fading::new(
+                    self.image.clone(),
+                    self.texture.clone(),
+                    self.frame,
+                    duration,
+                    self.now(),
+                ));
+            }
+        }
+        #[cfg(feature = "video")]
+        {
+            self.video = None;
+        }
+        #[cfg(feature = "mpv")]
+        {
+            self.mpv = None;
+        }
+        self.dmabuf = None;
+        self.background = None;
+        self.shader = None;
+        self.frame = 0;
+        self.layers.clear();
+        match loaded {
+            Loaded::Image(image) => {
+                self.animation = None;
+                self.image = Rc::new(Some(image));
+            }
+            Loaded::Animation(frames) => {
+                let animation = animation::Animation::new(frames, self.now());
+                self.image = animation.current();
+                self.animation = Some(animation);
+                self.update_playback();
+            }
+            Loaded::Layers(layers) => {
+                self.animation = None;
+                self.fading = None;
+                self.image = Rc::new(None);
+                self.layers = layers
+                    .into_iter()
+                    .map(|(image, depth)| layers::Layer::new(image, depth))
+                    .collect();
+            }
+            #[cfg(feature = "heic")]
+            Loaded::Source(source) => {
+                self.animation = None;
+                self.image = Rc::new(None);
+                self.set_source(source);
+            }
+        }
+        let texture = match key {
+            Some(key) => {
+                let texture = cache::texture(key);
+                self.stats.record_lookup(texture.borrow().is_some());
+                texture
+            }
+            None => Rc::new(RefCell::new(None)),
+        };
+        cache::recycle(std::mem::replace(&mut self.texture, texture));
+        self.dirty = true;
+        self.extract_palette();
+        #[cfg(feature = "compress")]
+        self.start_compression();
+        self.events.emit(WallpaperEvent::Changed);
+    }
+
+    /// Starts transcoding the still image to ETC blocks on a separate thread,
+    /// if [`WallpaperState::set_texture_compression`] is enabled
+    #[cfg(feature = "compress")]
+    fn start_compression(&mut self) {
+        self.compressed = None;
+        self.compression_job = None;
+        let image = match &*self.image {
+            Some(image) if self.compression && self.animation.is_none() => image,
+            _ => return,
+        };
+        #[cfg(feature = "async_loading")]
+        let notify = self.notify.clone();
+        self.compression_job = Some(compress::Compression::spawn(image, move || {
+            #[cfg(feature = "async_loading")]
+            notify.notify();
+        }));
+    }
+
+    /// Starts computing the palette of the new image on a separate thread
+    fn extract_palette(&mut self) {
+        let image = match (&*self.image, self.layers.first()) {
+            (Some(image), _) => image,
+            (None, Some(layer)) => match &*layer.image {
+                Some(image) => image,
+                None => return,
+            },
+            (None, None) => return,
+        };
+        #[cfg(feature = "async_loading")]
+        let notify = self.notify.clone();
+        self.palette_job = Some(palette::Extraction::spawn(image, move || {
+            #[cfg(feature = "async_loading")]
+            notify.notify();
+        }));
+    }
+
+    /// Takes the colors once they are computed and tells the callback if the palette changed
+    fn check_palette(&mut self) {
+        if let Some(colors) = self.palette_job.as_ref().and_then(|job| job.poll()) {
+            self.palette_job = None;
+            let changed = colors.palette != self.colors.palette;
+            self.colors = colors;
+            if changed {
+                if let Some(callback) = &mut self.palette_callback.0 {
+                    callback(&self.colors.palette);
+                }
+                #[cfg(feature = "pywal")]
+                if self.pywal {
+                    self.export_pywal();
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "pywal")]
+    fn export_pywal(&mut self) {
+        if let Err(err) = pywal::export(&self.colors.palette) {
+            self.report(WallpaperError::Pywal(err));
+        }
+    }
+
+    /// Writes pywal color files to `~/.cache/wal` whenever the palette changes,
+    /// so everything themed by pywal follows the wallpaper.
+    ///
+    /// With several outputs it should only be enabled for one of them.
+    #[cfg(feature = "pywal")]
+    pub fn set_pywal_export(&mut self, enabled: bool) {
+        self.pywal = enabled;
+        if enabled && !self.colors.palette.is_empty() {
+            self.export_pywal();
+        }
+    }
+
+    /// Returns the dominant colors of the image in RGBA, the most common first.
+    ///
+    /// The palette is computed on a separate thread whenever the image changes,
+    /// it is empty until the first one is done.
+    pub fn palette(&self) -> &[[f32; 4]] {
+        &self.colors.palette
+    }
+
+    /// Returns the average color of the image in RGBA, computed with the palette
+    pub fn average_color(&self) -> Option<[f32; 4]> {
+        self.colors.average
+    }
+
+    /// Returns the average color of an eighth of the image along `edge` in RGBA,
+    /// so bars and panels at that edge can blend into the wallpaper
+    pub fn edge_color(&self, edge: Edge) -> Option<[f32; 4]> {
+        self.colors.edge(edge)
+    }
+
+    /// Calls `callback` with the new palette whenever it changed, e.g. to theme borders and bars
+    /// to match the wallpaper. It is called from [`WallpaperState::run`].
+    pub fn on_palette_changed<F: FnMut(&[[f32; 4]]) + 'static>(&mut self, callback: F) {
+        self.palette_callback = palette::Callback(Some(Box::new(callback)));
+    }
+
+    /// Advances animations, transitions, pan and zoom motions, dimming and shaders to `now`.
+    ///
+    /// Once this was called they only progress with the time passed here, so compositors can drive
+    /// them from their frame callbacks, e.g. with the presentation time of the last frame,
+    /// and tests get the same frames on every run. Times before the last tick are ignored.
+    /// Slideshows, providers and retries are due by this time as well, while schedules
+    /// pick their image by the local time of day.
+    pub fn tick(&mut self, now: Instant) {
+        if self.clock.map_or(true, |clock| clock < now) {
+            self.clock = Some(now);
+        }
+    }
+
+    /// Returns the time set by [`WallpaperState::tick`], or the current time if it was never called
+    fn now(&self) -> Instant {
+        self.clock.unwrap_or_else(Instant::now)
+    }
+
+    /// Produce a new frame of the wallpaper to draw onto your output buffer.
+    ///
+    /// - `area` limits the space the wallpaper will be drawn in.
+    /// - `size` has to be the total size of the buffer the wallpaper will be displayed in,
+    ///   like the mode size of the output
+    /// - `scale` is the possibly fractional scale factor of the output,
+    ///   the image is drawn at the physical resolution
+    /// - `transform` is the output transform, which has to be passed to `Renderer::render` as well,
+    ///   so the image is drawn upright on rotated and flipped outputs
+    pub fn run(
+        &mut self,
+        area: Rectangle<i32, Logical>,
+        size: Size<i32, Physical>,
+        scale: f64,
+        transform: Transform,
+    ) -> WallpaperFrame {
+        self.release_images();
+        let views = self.views(area, 1.0);
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                (
+                    layer.image.clone(),
+                    layer.texture.clone(),
+                    self.views(area, layer.depth),
+                )
+            })
+            .collect();
+        // the renderer applies the transform, the wallpaper is laid out in the transformed space
+        let size = transform.transform_size(size);
+        let area = tools::to_physical(area, size, scale);
+
+        #[cfg(feature = "svg")]
+        self.rasterize_svg(area.size);
+
+        #[cfg(feature = "async_loading")]
+        self.scan_directory(area.size);
+
+        #[cfg(feature = "async_loading")]
+        self.check();
+        self.check_palette();
+
+        // animated content only changes as often as the frame rate cap and battery policy allow
+        let now = self.now();
+        let due = !self.frozen() && self.pacing.due(self.frame_interval(), now);
+
+        if let Some(animation) = self.animation.as_mut().filter(|_| due) {
+            if let Some(image) = animation.advance(now) {
+                self.image = image;
+                self.frame = animation.index();
+                self.dirty = true;
+            }
+        }
+
+        if let Some(background::Background::Gradient {
+            spec,
+            size: rendered,
+        }) = &mut self.background
+        {
+            if *rendered != Some(area.size) {
+                *rendered = Some(area.size);
+                self.image = Rc::new(Some(background::gradient(spec, area.size)));
+                self.texture = Rc::new(RefCell::new(None));
+                self.dirty = true;
+            }
+        }
+
+        #[cfg(feature = "video")]
+        let frame = match self.video.as_mut().filter(|_| due).map(|v| v.poll()) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => {
+                self.report(err);
+                None
+            }
+            None => None,
+        };
+        #[cfg(feature = "video")]
+        match frame {
+            Some(video::Frame::Image(image)) => {
+                let resized = self
+                    .image
+                    .as_ref()
+                    .as_ref()
+                    .map(|i| (i.width(), i.height()))
+                    != Some((image.width(), image.height()));
+                self.image = Rc::new(Some(image));
+                self.frame = self.frame.wrapping_add(1);
+                self.dirty = true;
+                if resized {
+                    self.texture = Rc::new(RefCell::new(None));
+                }
+            }
+            // imported again for every frame, which holds no pixels on the CPU
+            #[cfg(feature = "vaapi")]
+            Some(video::Frame::Dmabuf(dmabuf)) => {
+                self.image = Rc::new(None);
+                self.dmabuf = Some(dmabuf);
+                self.frame = self.frame.wrapping_add(1);
+                self.dirty = true;
+            }
+            None => {}
+        }
+
+        #[cfg(feature = "compress")]
+        if let Some(compressed) = self.compression_job.as_ref().and_then(|job| job.poll()) {
+            self.compression_job = None;
+            self.compressed = compressed.map(Rc::new);
+            self.dirty = true;
+        }
+
+        #[cfg(feature = "mpv")]
+        if due && self.mpv.as_ref().map_or(false, |player| player.updated()) {
+            // rendered into the same texture, a new frame index makes the blur run again
+            self.frame = self.frame.wrapping_add(1);
+            self.dirty = true;
+        }
+
+        if let Some(fading) = &mut self.fading {
+            if !fading.update(now) {
+                if let Some(fading) = self.fading.take() {
+                    cache::recycle(fading.texture);
+                }
+                self.events.emit(WallpaperEvent::TransitionFinished);
+            }
+        }
+
+        if self.held.is_some() && !self.uploading() {
+            self.held = None;
+        }
+
+        if self.output != Some((area, size)) {
+            self.output = Some((area, size));
+            self.dirty = true;
+        }
+        self.check_opaque();
+        // running transitions, dimming and shaders change every frame, unless nothing is shown
+        let damaged = self.dirty
+            || self.uploading()
+            || (due
+                && (self.fading.is_some()
+                    || self.motion.is_some()
+                    || self.dim.animating(now)
+                    || self.shader.is_some()
+                    || self.filter.animated()));
+        // nothing of a covered output is seen, its changes are kept for when it is uncovered
+        let damaged = damaged && !self.occluded;
+        if !self.occluded {
+            self.dirty = false;
+        }
+        if due && damaged {
+            self.pacing.shown(now);
+        }
+
+        #[cfg(feature = "calloop")]
+        if let Some(waker) = &self.waker {
+            waker.set_deadline(self.next_update());
+        }
+
+        WallpaperFrame {
+            state_id: self.id.0,
+            area,
+            span: self
+                .span
+                .map(|span| span.to_f64().to_physical(scale).to_i32_round()),
+            viewport: self.viewport,
+            scale,
+            image: self.image.clone(),
+            texture: self.texture.clone(),
+            frame: self.frame,
+            changed: self
+                .animation
+                .as_mut()
+                .and_then(|animation| animation.changed()),
+            dmabuf: self.dmabuf.clone(),
+            #[cfg(feature = "mpv")]
+            mpv: self.mpv.clone(),
+            // backgrounds are rendered again for every output size
+            #[cfg(feature = "compress")]
+            compressed: self
+                .compressed
+                .clone()
+                .filter(|_| self.background.is_none()),
+            scaling: if self.background.is_some() {
+                ScalingMode::Stretch
+            } else {
+                self.scaling
+            },
+            anchor: self.anchor,
+            views,
+            layers,
+            fading: self.fading.clone(),
+            uploader: self.uploader.clone(),
+            held: self.held.clone(),
+            context: self.context.clone(),
+            // anything drawn over or mixed into the image may let the output behind show through
+            opaque: self.opaque.2.filter(|_| {
+                self.shader.is_none()
+                    && self.fading.is_none()
+                    && self.held.is_none()
+                    && self.dmabuf.is_none()
+            }),
+            #[cfg(feature = "render_element")]
+            z_index: self.z_index(),
+            shader: self.shader.as_ref().map(|shader| {
+                let uniforms = shader.uniforms(area, size, scale, transform, self.pointer, now);
+                #[cfg(feature = "audio")]
+                let uniforms = match &self.audio {
+                    Some(audio) => uniforms.with_audio(audio.spectrum()),
+                    None => uniforms,
+                };
+                (shader.clone(), uniforms)
+            }),
+            blur: self.blur.clone(),
+            dim: self.dim.value(now),
+            dim_texture: self.dim_texture.clone(),
+            filter: self.filter.clone(),
+            params: filter::Params {
+                dim: self.dim.value(now),
+                ..self.filter_params()
+            },
+            size,
+            transform,
+            damaged,
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Returns when the wallpaper changes next and has to be drawn again.
+    ///
+    /// Running transitions, videos, shaders, pan and zoom motions and animated post shaders
+    /// change every frame and return the current time, or when the next frame is due with a
+    /// frame rate cap. While the output is off or occluded they are paused.
+    pub fn next_update(&self) -> Option<Instant> {
+        let frozen = self.frozen();
+        let interval = self.frame_interval();
+        #[allow(unused_mut)]
+        let mut continuous = self.fading.is_some()
+            || self.motion.is_some()
+            || self.dim.animating(self.now())
+            || self.shader.is_some()
+            || self.filter.animated();
+        #[cfg(feature = "video")]
+        {
+            continuous |= self.video.is_some();
+        }
+        #[cfg(feature = "mpv")]
+        {
+            continuous |= self.mpv.is_some();
+        }
+        // uploads only progress while the wallpaper is drawn
+        if self.uploading() && !self.occluded {
+            return Some(self.now());
+        }
+        if continuous && !frozen {
+            return Some(self.pacing.next(interval, self.now()));
+        }
+
+        let animation = self
+            .animation
+            .as_ref()
+            .filter(|_| !frozen)
+            .map(|animation| self.pacing.next(interval, animation.deadline()));
+        #[cfg(feature = "async_loading")]
+        let slideshow = self.slideshow.as_ref().and_then(|s| s.deadline());
+        #[cfg(not(feature = "async_loading"))]
+        let slideshow = None;
+        #[cfg(feature = "remote")]
+        let provider = self.provider.as_ref().map(|p| p.deadline(self.now()));
+        #[cfg(not(feature = "remote"))]
+        let provider = None;
+        #[cfg(feature = "timeline")]
+        let timeline = self.timeline.as_ref().map(|t| t.deadline(self.now()));
+        #[cfg(not(feature = "timeline"))]
+        let timeline = None;
+        #[cfg(feature = "gnome")]
+        let gnome = self.gnome.as_ref().map(|g| g.deadline(self.now()));
+        #[cfg(not(feature = "gnome"))]
+        let gnome = None;
+        #[cfg(feature = "heic")]
+        let heic = self.heic.as_ref().map(|h| h.deadline(self.now()));
+        #[cfg(not(feature = "heic"))]
+        let heic = None;
+        #[cfg(feature = "kde")]
+        let kde = self.kde.as_ref().and_then(|k| k.deadline(self.now()));
+        #[cfg(not(feature = "kde"))]
+        let kde = None;
+        #[cfg(feature = "async_loading")]
+        let retry = self.retry.as_ref().map(|(_, at)| *at);
+        #[cfg(not(feature = "async_loading"))]
+        let retry = None;
+        [
+            animation, slideshow, provider, timeline, gnome, heic, kde, retry,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Tells whether the output is on, pausing animations, videos and shaders while it is
+    /// off or disabled, e.g. by DPMS.
+    ///
+    /// [`WallpaperState::run`] reports no damage for them until the output is active again,
+    /// and animations continue where they stopped.
+    pub fn set_output_active(&mut self, active: bool) {
+        if self.output_off == !active {
+            return;
+        }
+        self.output_off = !active;
+        self.playback_changed();
+    }
+
+    /// Returns `false` if the output was set to be off with [`WallpaperState::set_output_active`]
+    pub fn output_active(&self) -> bool {
+        !self.output_off
+    }
+
+    /// Tells whether an opaque window, like a fullscreen one, covers the whole output.
+    ///
+    /// While it is occluded animations, videos and shaders are paused and [`WallpaperState::run`]
+    /// reports no damage at all. Changes made in the meantime are drawn once it is uncovered.
+    pub fn set_occluded(&mut self, occluded: bool) {
+        if self.occluded == occluded {
+            return;
+        }
+        self.occluded = occluded;
+        self.playback_changed();
+    }
+
+    /// Returns `true` if the output was set to be covered with [`WallpaperState::set_occluded`]
+    pub fn occluded(&self) -> bool {
+        self.occluded
+    }
+
+    /// Caps animations, videos, transitions and shaders to `fps` frames per second, independent
+    /// of the refresh rate of the output, or lifts the cap with `None`
+    ///
+    /// [`WallpaperState::run`] reports damage and [`WallpaperState::next_update`] schedules redraws
+    /// only as often as the cap allows. On battery the lower rate of the cap and a
+    /// [`PowerPolicy::MaxFps`] applies.
+    pub fn set_max_fps(&mut self, fps: Option<f64>) {
+        if self.max_fps == fps {
+            return;
+        }
+        self.max_fps = fps;
+        #[cfg(feature = "calloop")]
+        if let Some(waker) = &self.waker {
+            waker.set_deadline(self.next_update());
+        }
+    }
+
+    /// Returns the frame rate cap set by [`WallpaperState::set_max_fps`]
+    pub fn max_fps(&self) -> Option<f64> {
+        self.max_fps
+    }
+
+    /// Sets whether the system runs on battery, applying the [`PowerPolicy`] set by
+    /// [`WallpaperState::set_battery_policy`]
+    ///
+    /// The compositor calls this on changes, e.g. as reported by a `PowerSourceWatcher` with the `upower` feature.
+    pub fn set_power_source(&mut self, source: PowerSource) {
+        if self.power_source == source {
+            return;
+        }
+        self.power_source = source;
+        self.playback_changed();
+    }
+
+    /// Returns the power source set by [`WallpaperState::set_power_source`]
+    pub fn power_source(&self) -> PowerSource {
+        self.power_source
+    }
+
+    /// Sets how animations, videos, transitions and shaders are drawn on battery,
+    /// by default like on AC power
+    pub fn set_battery_policy(&mut self, policy: PowerPolicy) {
+        if self.battery_policy == policy {
+            return;
+        }
+        self.battery_policy = policy;
+        self.playback_changed();
+    }
+
+    /// Returns the policy set by [`WallpaperState::set_battery_policy`]
+    pub fn battery_policy(&self) -> PowerPolicy {
+        self.battery_policy
+    }
+
+    /// Applies a change of the output, occlusion, power source or battery policy
+    fn playback_changed(&mut self) {
+        self.update_playback();
+        self.dirty = true;
+        #[cfg(feature = "calloop")]
+        if let Some(waker) = &self.waker {
+            waker.set_deadline(self.next_update());
+        }
+    }
+
+    /// Returns the policy applying to the current power source
+    fn power_policy(&self) -> PowerPolicy {
+        match self.power_source {
+            PowerSource::Ac => PowerPolicy::Full,
+            PowerSource::Battery => self.battery_policy,
+        }
+    }
+
+    /// Returns the least time between two frames of animated content, if it is limited
+    fn frame_interval(&self) -> Option<Duration> {
+        power::frame_interval(self.max_fps, self.power_policy())
+    }
+
+    /// Returns `true` if nothing animated has to be drawn
+    fn frozen(&self) -> bool {
+        self.output_off || self.occluded || self.power_policy() == PowerPolicy::Freeze
+    }
+
+    /// Pauses or resumes animations and videos as the wallpaper is frozen or not
+    fn update_playback(&mut self) {
+        let frozen = self.frozen();
+        let now = self.now();
+        if let Some(animation) = &mut self.animation {
+            if frozen {
+                animation.pause(now);
+            } else {
+                animation.resume(now);
+            }
+        }
+        #[cfg(feature = "video")]
+        if let Some(Err(err)) = self.video.as_ref().map(|video| video.set_playing(!frozen)) {
+            self.report(err);
+        }
+        #[cfg(feature = "mpv")]
+        if let Some(Err(err)) = self.mpv.as_ref().map(|player| player.set_paused(frozen)) {
+            self.report(err);
+        }
+    }
+
+    /// Creates an event source waking up the event loop whenever the wallpaper has to be drawn again.
+    ///
+    /// Its callback is called once a load finished, the next animation frame or playlist image is due
+    /// or a transition is running, so the compositor only has to render on these events
+    /// instead of calling [`WallpaperState::run`] every frame.
+    #[cfg(feature = "calloop")]
+    pub fn event_source(&mut self) -> std::io::Result<WallpaperSource> {
+        let (waker, source) = source::new(self.next_update())?;
+        self.notify = waker.notify();
+        self.waker = Some(waker);
+        Ok(source)
+    }
+
+    /// Rasterizes the current SVG again if the output size or scaling mode changed
+    #[cfg(feature = "svg")]
+    fn rasterize_svg(&mut self, size: Size<i32, Physical>) {
+        let mode = self.scaling;
+        let svg = match &mut self.svg {
+            Some(svg) if svg.rendered != Some((size, mode)) => svg,
+            _ => return,
+        };
+        svg.rendered = Some((size, mode));
+        let path = svg.path.clone();
+
+        #[cfg(feature = "async_loading")]
+        {
+            self.start_loading(loader::Pending::spawn(
+                move || svg::render(&path, Some(size), mode).map(Loaded::Image),
+                self.notify.clone(),
+            ));
+        }
+        #[cfg(not(feature = "async_loading"))]
+        match svg::render(&path, Some(size), mode) {
+            Ok(image) => {
+                let loaded = Loaded::Image(image);
+                let key = loaded.key();
+                self.set_loaded(loaded, key)
+            }
+            Err(err) => self.set_error(err.into()),
+        }
+    }
+
+    /// Starts the playlist of the directory once it was scanned for the current output size
+    #[cfg(feature = "async_loading")]
+    fn scan_directory(&mut self, size: Size<i32, Physical>) {
+        let (paths, errors) = match self.directory.as_mut().and_then(|d| d.poll(size)) {
+            Some(scan) => scan,
+            None => return,
+        };
+        for err in errors {
+            self.report(err);
+        }
+        // `set_playlist` stops other sources
+        let directory = self.directory.take().unwrap();
+        if paths.is_empty() {
+            self.set_error(WallpaperError::Io(
+                directory.path().to_path_buf(),
+                std::io::Error::new(std::io::ErrorKind::NotFound, "no matching images"),
+            ));
+        } else {
+            let interval = directory.policy.interval;
+            if let Err(err) = self.set_playlist(paths, interval) {
+                self.set_error(err);
+            }
+        }
+        self.directory = Some(directory);
+    }
+
+    /// Returns the currently used `ScalingMode`
+    pub fn scaling_mode(&self) -> ScalingMode {
+        self.scaling
+    }
+
+    /// Sets how the image is fitted into the output
+    pub fn set_scaling_mode(&mut self, mode: ScalingMode) {
+        self.scaling = mode;
+        self.dirty = true;
+    }
+
+    /// Returns the alignment set by [`WallpaperState::set_anchor`]
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+
+    /// Aligns the image to `anchor`, deciding which part is cropped by [`ScalingMode::Fill`]
+    /// and where it is placed by the other modes, except [`ScalingMode::Stretch`] and [`ScalingMode::Tile`]
+    pub fn set_anchor(&mut self, anchor: Anchor) {
+        if self.anchor != anchor {
+            self.anchor = anchor;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the z-index set by [`WallpaperState::set_z_index`], the background layer by default
+    #[cfg(feature = "render_element")]
+    pub fn z_index(&self) -> u8 {
+        self.z_index.unwrap_or(RenderZindex::Background as u8)
+    }
+
+    /// Sets where the wallpaper is sorted among the other elements of a space,
+    /// e.g. `RenderZindex::Overlay as u8` to show it above all windows, like a screensaver
+    #[cfg(feature = "render_element")]
+    pub fn set_z_index(&mut self, z_index: u8) {
+        if self.z_index() != z_index {
+            self.z_index = Some(z_index);
+            self.dirty = true;
+        }
+    }
+
+    /// Sets whether the decoded image is kept once its texture is uploaded.
+    ///
+    /// With [`RetentionPolicy::DropAfterUpload`] the wallpaper stays blank after [`WallpaperState::destroy`]
+    /// or when drawn by another kind of renderer, until the next image is set.
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) {
+        self.retention = policy;
+    }
+
+    /// Returns the policy set by [`WallpaperState::set_retention_policy`]
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// Transcodes still images to ETC2 blocks on a separate thread once loaded,
+    /// so their textures take an eighth of the GPU memory, at a slight loss of quality.
+    ///
+    /// The image is shown uncompressed until it is transcoded. Images with transparency,
+    /// and those set before it is enabled, stay uncompressed.
+    /// It is only supported by the `Gles2Renderer` on GLES 3 or with `GL_OES_compressed_ETC1_RGB8_texture`.
+    #[cfg(feature = "compress")]
+    pub fn set_texture_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+        if !enabled {
+            self.compression_job = None;
+            self.compressed = None;
+        }
+    }
+
+    /// Returns `true` if [`WallpaperState::set_texture_compression`] is enabled
+    #[cfg(feature = "compress")]
+    pub fn texture_compression(&self) -> bool {
+        self.compression
+    }
+
+    /// Streams large images into their textures through pixel buffer objects over several frames,
+    /// instead of stalling the frame that first draws them.
+    ///
+    /// The image shown before stays until the new one is uploaded.
+    /// It is only supported by the `Gles2Renderer` on GLES 3, other renderers import images at once.
+    pub fn set_streaming_upload(&mut self, enabled: bool) {
+        if enabled != self.uploader.is_some() {
+            self.uploader = enabled.then(|| Rc::new(upload::Uploader::default()));
+            self.held = None;
+        }
+    }
+
+    /// Returns `true` if [`WallpaperState::set_streaming_upload`] is enabled
+    pub fn streaming_upload(&self) -> bool {
+        self.uploader.is_some()
+    }
+
+    /// Returns `true` while the image may be streaming into its texture
+    fn uploading(&self) -> bool {
+        self.uploader.is_some() && self.image.is_some() && self.texture.borrow().is_none()
+    }
+
+    /// Scales images down to the size they are drawn at on the loader thread, before they are uploaded.
+    ///
+    /// This saves GPU memory and upload time for images much larger than the output,
+    /// but images loaded before the output grows or the [`ScalingMode`] changes stay at their smaller size.
+    /// Only applies to images loaded in the background once the output size is known.
+    #[cfg(feature = "async_loading")]
+    pub fn set_prescale(&mut self, enabled: bool) {
+        self.prescale = enabled;
+    }
+
+    /// Returns `true` if [`WallpaperState::set_prescale`] is enabled
+    #[cfg(feature = "async_loading")]
+    pub fn prescale(&self) -> bool {
+        self.prescale
+    }
+
+    /// Sets the filter images are scaled down with by [`WallpaperState::set_prescale`],
+    /// trading a slower start for sharper detail with [`Resampling::Lanczos3`]
+    #[cfg(feature = "async_loading")]
+    pub fn set_resampling(&mut self, resampling: Resampling) {
+        self.resampling = resampling;
+    }
+
+    /// Returns the filter set by [`WallpaperState::set_resampling`]
+    #[cfg(feature = "async_loading")]
+    pub fn resampling(&self) -> Resampling {
+        self.resampling
+    }
+
+    /// Returns the pan and zoom motion set by [`WallpaperState::set_ken_burns`]
+    pub fn ken_burns(&self) -> Option<KenBurns> {
+        self.motion.as_ref().map(|motion| motion.ken_burns)
+    }
+
+    /// Slowly pans and zooms across the image, until set to `None`.
+    ///
+    /// The motion continues across image changes and is not applied to shader wallpapers.
+    /// The wallpaper is drawn every frame while it is set.
+    pub fn set_ken_burns(&mut self, ken_burns: Option<KenBurns>) {
+        if self.ken_burns() != ken_burns {
+            let now = self.now();
+            self.motion = ken_burns.map(|ken_burns| motion::Motion::new(ken_burns, now));
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the `Transition` used when the image changes
+    pub fn transition(&self) -> Transition {
+        self.transition
+    }
+
+    /// Sets the `Transition` used when the image changes
+    pub fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    /// Loads the image at `path` in the background and shows it once it is decoded.
+    ///
+    /// Fails if the file cannot be accessed, decoding errors are reported by [`WallpaperState::error`].
+    /// Large JPEGs are shown at a low resolution first, until they are fully decoded.
+    /// With the `watch` feature the image is reloaded whenever the file changes.
+    /// With the `gnome` feature `.xml` files are played as GNOME background slideshows.
+    /// With the `heic` feature Apple dynamic wallpapers change their image over the day.
+    /// With the `kde` feature Plasma wallpaper packages and KDE dynamic wallpapers can be set,
+    /// showing their dark variant at night.
+    /// `.layers` manifests are shown as layered wallpapers, moving with the parallax.
+    #[cfg(feature = "async_loading")]
+    pub fn set<P: AsRef<Path>>(&mut self, path: P) -> Result<(), WallpaperError> {
+        let path = PathBuf::from(path.as_ref());
+        std::fs::metadata(&path).map_err(|err| WallpaperError::Io(path.clone(), err))?;
+        if let Some(path) = self.open_sources(path)? {
+            self.start_loading(loader::Pending::open(path, self.notify.clone()));
+        }
+        Ok(())
+    }
+
+    /// Replaces the sources of [`WallpaperState::set`] with the ones playing the file at `path`,
+    /// returns `path` back if it is an image that still has to be loaded
+    #[cfg(feature = "async_loading")]
+    fn open_sources(&mut self, path: PathBuf) -> Result<Option<PathBuf>, WallpaperError> {
+        self.variants = None;
+        self.next_fallback = 0;
+        self.attempts = 0;
+        self.retry = None;
+        self.slideshow = None;
+        self.directory = None;
+        #[cfg(feature = "timeline")]
+        {
+            self.timeline = None;
+        }
+        #[cfg(feature = "watch")]
+        {
+            self.watch = match watch::Watch::new(&path) {
+                Ok(watch) => Some(watch),
+                Err(err) => {
+                    self.report(WallpaperError::Watch(path.clone(), err));
+                    None
+                }
+            };
+        }
+        #[cfg(feature = "svg")]
+        {
+            self.svg = None;
+            if svg::is_svg(&path) {
+                // rasterized in `run` once the output size is known
+                self.loading = None;
+                self.svg = Some(svg::Svg {
+                    path,
+                    rendered: None,
+                });
+                return Ok(None);
+            }
+        }
+        #[cfg(feature = "gnome")]
+        {
+            self.gnome = None;
+            if gnome::is_gnome_xml(&path) {
+                let slideshow = gnome::GnomeSlideshow::open(&path)
+                    .map_err(|err| WallpaperError::Io(path.clone(), err))?;
+                self.loading = None;
+                self.gnome = Some(slideshow);
+                return Ok(None);
+            }
+        }
+        #[cfg(feature = "heic")]
+        {
+            self.heic = None;
+            if heic::is_heic(&path) {
+                // read on the loader threads, HEIF images without a schedule are shown like any image
+                self.start_loading(loader::Pending::spawn(
+                    move || match heic::DynamicHeic::open(&path)? {
+                        Some(dynamic) => Ok(Loaded::Source(loader::Source::Heic(dynamic))),
+                        None => loader::open(path),
+                    },
+                    self.notify.clone(),
+                ));
+                return Ok(None);
+            }
+        }
+        #[cfg(feature = "kde")]
+        {
+            self.kde = None;
+            if kde::is_package(&path) {
+                self.start_loading(loader::Pending::spawn(
+                    move || {
+                        let package = kde::PlasmaPackage::open(&path)?;
+                        Ok(Loaded::Source(loader::Source::Package(package)))
+                    },
+                    self.notify.clone(),
+                ));
+                return Ok(None);
+            }
+        }
+        Ok(Some(path))
+    }
+
+    /// Loads the image at `path` on the tokio blocking thread pool instead of a new thread.
+    ///
+    /// The returned future finishes once the image is decoded and does not borrow the state,
+    /// so it can be spawned on the runtime. The image is picked up on the main thread
+    /// by the next [`WallpaperState::run`], like with [`WallpaperState::set`].
+    ///
+    /// Like [`WallpaperState::set`] it fails if the file cannot be accessed, before the current
+    /// wallpaper is touched, and decoding errors are reported by [`WallpaperState::error`].
+    /// Files `set` plays as sources, like SVGs and GNOME slideshows, are started the same way
+    /// and the future finishes right away.
+    #[cfg(feature = "async")]
+    pub fn set_async<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> impl std::future::Future<Output = Result<(), WallpaperError>> + Send + 'static {
+        let path = PathBuf::from(path.as_ref());
+        let load = match std::fs::metadata(&path)
+            .map_err(|err| WallpaperError::Io(path.clone(), err))
+            .and_then(|_| self.open_sources(path))
+        {
+            Ok(Some(path)) => {
+                let (completer, pending) = loader::Pending::new(self.notify.clone());
+                self.start_loading(pending.with_path(path.clone()));
+                Ok(Some((completer, path)))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
+        };
+
+        async move {
+            let (completer, path) = match load? {
+                Some(load) => load,
+                None => return Ok(()),
+            };
+            let task_path = path.clone();
+            tokio::task::spawn_blocking(move || {
+                // another wallpaper may have been set in the meantime
+                if !completer.is_cancelled() {
+                    completer.complete(loader::open(task_path));
+                }
+            })
+            .await
+            .map_err(|err| {
+                WallpaperError::Io(path, std::io::Error::new(std::io::ErrorKind::Other, err))
+            })
+        }
+    }
+
+    /// Shows an already decoded image
+    pub fn set_image(&mut self, image: DynamicImage) {
+        self.stop_sources();
+        self.set_loaded(Loaded::Image(image), None);
+    }
+
+    /// Decodes the encoded image in `data` and shows it.
+    ///
+    /// With the `async_loading` feature decoding happens on a separate thread, like for [`WallpaperState::set`].
+    pub fn set_bytes(&mut self, data: &[u8]) {
+        self.stop_sources();
+
+        #[cfg(feature = "async_loading")]
+        {
+            let data = data.to_vec();
+            self.start_loading(loader::Pending::spawn(
+                move || loader::decode(&data),
+                self.notify.clone(),
+            ));
+        }
+        #[cfg(not(feature = "async_loading"))]
+        {
+            let result = loader::decode(data);
+            self.stats.record_decodes(stats::take_decodes());
+            match result {
+                Ok(loaded) => {
+                    let key = loaded.key();
+                    self.set_loaded(loaded, key)
+                }
+                Err(err) => self.set_error(err.into()),
+            }
+        }
+    }
+
+    /// Downloads the image at the http(s) `url` in the background and shows it once it is decoded.
+    ///
+    /// Downloads time out after 30 seconds and may not exceed 64 MiB,
+    /// errors are reported by [`WallpaperState::error`].
+    #[cfg(feature = "remote")]
+    pub fn set_url<S: Into<String>>(&mut self, url: S) {
+        self.stop_sources();
+        let url = url.into();
+        self.start_loading(loader::Pending::spawn_download(
+            move || {
+                remote::download(&url)
+                    .map_err(image::ImageError::IoError)
+                    .and_then(|data| loader::decode(&data))
+            },
+            self.notify.clone(),
+        ));
+    }
+
+    /// Shows the images of `provider`, like [`Bing`] or [`NasaApod`], asking it again after [`Provider::refresh`].
+    ///
+    /// Downloaded images are cached in `$XDG_CACHE_HOME/smithay-wallpaper`.
+    #[cfg(feature = "remote")]
+    pub fn set_provider<P: Provider>(&mut self, provider: P) {
+        self.stop_sources();
+        self.provider = Some(provider::Refresh::new(std::sync::Arc::new(provider)));
+    }
+
+    /// Switches between the images of `timeline` as the day advances
+    #[cfg(feature = "timeline")]
+    pub fn set_timeline(&mut self, timeline: Timeline) {
+        self.stop_sources();
+        self.timeline = Some(timeline::Schedule::new(timeline));
+    }
+
+    /// Plays the video at `path` in a loop using GStreamer
+    ///
+    /// With the `vaapi` feature the frames are decoded and converted on the GPU by the VA plugin,
+    /// if it is installed, and imported as dmabufs like with [`WallpaperState::set_dmabuf`].
+    #[cfg(feature = "video")]
+    pub fn set_video<P: AsRef<Path>>(&mut self, path: P) {
+        match video::Video::new(path.as_ref()) {
+            Ok(video) => {
+                self.stop_sources();
+                self.layers.clear();
+                self.video = Some(video);
+                self.update_playback();
+                self.events.emit(WallpaperEvent::Changed);
+            }
+            Err(err) => self.set_error(WallpaperError::Video(path.as_ref().to_path_buf(), err)),
+        }
+    }
+
+    /// Plays the video at `path` in a loop with mpv, which handles every container and codec
+    /// and decodes on the GPU as set by `options`
+    ///
+    /// mpv renders into a texture with its OpenGL render API,
+    /// so this is only supported by the `Gles2Renderer`, other renderers draw nothing.
+    #[cfg(feature = "mpv")]
+    pub fn set_mpv_video<P: AsRef<Path>>(&mut self, path: P, options: MpvOptions) {
+        match mpv::Player::new(path.as_ref(), &options) {
+            Ok(player) => {
+                self.stop_sources();
+                self.background = None;
+                self.shader = None;
+                self.layers.clear();
+                self.image = Rc::new(None);
+                self.texture = Rc::new(RefCell::new(None));
+                self.mpv = Some(Rc::new(player));
+                self.update_playback();
+                self.dirty = true;
+                self.events.emit(WallpaperEvent::Changed);
+            }
+            Err(err) => self.set_error(err),
+        }
+    }
+
+    /// Changes looping, speed and hardware decoding of the video played by mpv
+    #[cfg(feature = "mpv")]
+    pub fn set_mpv_options(&mut self, options: MpvOptions) {
+        if let Some(Err(err)) = self.mpv.as_ref().map(|player| player.set_options(&options)) {
+            self.report(err);
+        }
+    }
+
+    /// Shows `dmabuf`, e.g. a frame of a hardware video decoder or of another process,
+    /// which the renderer imports without copying it through the CPU.
+    ///
+    /// Call it again for every new frame, the buffer must not be written to while it is shown.
+    /// Only the `Gles2Renderer` imports dmabufs, other renderers draw nothing.
+    pub fn set_dmabuf(&mut self, dmabuf: Dmabuf) {
+        if self.dmabuf.is_none() {
+            self.stop_sources();
+            self.background = None;
+            self.shader = None;
+            self.fading = None;
+            self.layers.clear();
+            self.texture = Rc::new(RefCell::new(None));
+            self.events.emit(WallpaperEvent::Changed);
+        }
+        self.image = Rc::new(None);
+        self.dmabuf = Some(dmabuf);
+        self.frame = self.frame.wrapping_add(1);
+        self.dirty = true;
+    }
+
+    /// Shows the blurred image described by the blurhash `hash` until the next image is loaded.
+    ///
+    /// Unlike the other setters it keeps pending loads running, so it can be shown
+    /// while a large wallpaper passed to [`WallpaperState::set`] is decoded.
+    pub fn set_blurhash(&mut self, hash: &str) -> Result<(), WallpaperError> {
+        let image = blurhash::decode(hash).map_err(WallpaperError::Blurhash)?;
+        self.set_loaded(Loaded::Image(DynamicImage::ImageRgb8(image)), None);
+        Ok(())
+    }
+
+    /// Shows a solid `color` in RGBA
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.stop_sources();
+        self.set_loaded(Loaded::Image(background::color(color)), None);
+        self.background = Some(background::Background::Color(color));
+    }
+
+    /// Shows a gradient, which is rendered to fit each output size
+    pub fn set_gradient(&mut self, spec: GradientSpec) {
+        self.stop_sources();
+        self.set_loaded(
+            Loaded::Image(background::color(
+                spec.stops
+                    .first()
+                    .map_or([0.0, 0.0, 0.0, 1.0], |stop| stop.color),
+            )),
+            None,
+        );
+        self.background = Some(background::Background::Gradient { spec, size: None });
+    }
+
+    /// Renders the Shadertoy-style fragment shader `source` as wallpaper.
+    ///
+    /// `source` has to define `void mainImage(out vec4 fragColor, in vec2 fragCoord)`
+    /// and may use the `iResolution`, `iTime` and `iMouse` uniforms.
+    /// With the `audio` feature `iAudio` holds the spectrum of the playing audio,
+    /// see [`WallpaperState::set_audio_visualizer`].
+    ///
+    /// Compile errors are returned once by the next draw as [`WallpaperError::Shader`].
+    pub fn set_shader<S: Into<String>>(&mut self, source: S) {
+        self.stop_sources();
+        self.background = None;
+        self.fading = None;
+        self.layers.clear();
+        self.image = Rc::new(None);
+        self.texture = Rc::new(RefCell::new(None));
+        self.shader = Some(Rc::new(shader::Shader::new(source.into(), self.now())));
+        self.dirty = true;
+        self.events.emit(WallpaperEvent::Changed);
+    }
+
+    /// Returns `true` if the audio is captured for shader wallpapers
+    #[cfg(feature = "audio")]
+    pub fn audio_visualizer(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    /// Captures the audio played on the default output with PipeWire, for audio visualizers.
+    ///
+    /// Shader wallpapers receive the spectrum in `uniform float iAudio[64]`,
+    /// logarithmic bands from low to high frequencies, each from `0.0` (silent) to `1.0`.
+    /// The capture keeps running across wallpaper changes until it is disabled.
+    #[cfg(feature = "audio")]
+    pub fn set_audio_visualizer(&mut self, enabled: bool) {
+        if !enabled {
+            self.audio = None;
+        } else if self.audio.is_none() {
+            match audio::AudioCapture::new() {
+                Ok(capture) => self.audio = Some(capture),
+                Err(err) => {
+                    self.report(WallpaperError::Audio(err));
+                }
+            }
+        }
+    }
+
+    /// Returns the dimming set by [`WallpaperState::set_dim`]
+    pub fn dim(&self) -> f32 {
+        self.dim.target()
+    }
+
+    /// Darkens the wallpaper by `dim`, from `0.0` (unchanged) to `1.0` (black).
+    ///
+    /// The change is animated over a quarter second, e.g. to dim the background when the session idles or locks.
+    pub fn set_dim(&mut self, dim: f32) {
+        let dim = dim.clamp(0.0, 1.0);
+        if dim != self.dim.target() {
+            let now = self.now();
+            self.dim.animate(dim, DIM_DURATION, now);
+        }
+    }
+
+    /// Returns the color adjustments set by [`WallpaperState::set_effects`]
+    pub fn effects(&self) -> Effects {
+        self.effects
+    }
+
+    /// Adjusts brightness, contrast and saturation of the wallpaper while drawing,
+    /// so it can e.g. be darkened or desaturated without preprocessing the files.
+    pub fn set_effects(&mut self, effects: Effects) {
+        if self.effects != effects {
+            self.effects = effects;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the color temperature set by [`WallpaperState::set_color_temperature`]
+    pub fn color_temperature(&self) -> f32 {
+        self.temperature.unwrap_or(filter::NEUTRAL_TEMPERATURE)
+    }
+
+    /// Tints the wallpaper like a light source of `kelvin`, warming it up below 6500K.
+    ///
+    /// This is meant as night light for compositors that do not control the gamma of their outputs.
+    pub fn set_color_temperature(&mut self, kelvin: f32) {
+        let temperature = Some(kelvin).filter(|kelvin| *kelvin != filter::NEUTRAL_TEMPERATURE);
+        if self.temperature != temperature {
+            self.temperature = temperature;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the 3D LUT set by [`WallpaperState::set_lut`]
+    pub fn lut(&self) -> Option<&Lut> {
+        self.lut.as_ref()
+    }
+
+    /// Color-grades the wallpaper with a 3D LUT, e.g. to match the colors of a theme.
+    ///
+    /// The LUT is applied after all other color adjustments.
+    pub fn set_lut(&mut self, lut: Option<Lut>) {
+        if self.lut != lut {
+            self.lut = lut;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the post-processing shader set by [`WallpaperState::set_post_shader`]
+    pub fn post_shader(&self) -> Option<&PostShader> {
+        self.filter.effect()
+    }
+
+    /// Runs `shader` over the wallpaper image after all other color adjustments,
+    /// for custom effects not built into this crate.
+    ///
+    /// A compile error is returned once by the next draw as [`WallpaperError::Shader`],
+    /// the image is drawn without any adjustments then.
+    pub fn set_post_shader(&mut self, shader: Option<PostShader>) {
+        if self.filter.effect() != shader.as_ref() {
+            self.filter = Rc::new(filter::Filter::new(shader, self.now()));
+            self.dirty = true;
+        }
+    }
+
+    /// Combines all color adjustments into the parameters applied while drawing
+    fn filter_params(&self) -> filter::Params {
+        let mut color = self.effects.color_transform();
+        if let Some(kelvin) = self.temperature {
+            color = color.then(filter::ColorTransform::scale(filter::temperature(kelvin)));
+        }
+        filter::Params {
+            color,
+            lut: self.lut.clone(),
+            anisotropy: self.anisotropy,
+            tone_mapping: self.tone_mapping,
+            exposure: self.exposure,
+            linear: self.linear_blending,
+            dither: self.dither,
+            time: self.filter.time(self.now()),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the samples set by [`WallpaperState::set_anisotropic_filtering`], `1.0` if it is off
+    pub fn anisotropic_filtering(&self) -> f32 {
+        self.anisotropy.max(1.0)
+    }
+
+    /// Samples the image with up to `samples` anisotropic filtering samples, `1.0` disables it.
+    ///
+    /// This keeps the image sharp when drawn with a different scale along each axis,
+    /// like stretched or during overview effects. It is only supported by the `Gles2Renderer`
+    /// with the `GL_EXT_texture_filter_anisotropic` extension, the samples are limited to what it supports.
+    pub fn set_anisotropic_filtering(&mut self, samples: f32) {
+        if self.anisotropy != samples {
+            self.anisotropy = samples;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the tone mapping set by [`WallpaperState::set_tone_mapping`]
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    /// Shows colors of HDR images brighter than white with `tone_mapping`, `ToneMapping::Clip` by default.
+    ///
+    /// HDR images are only kept in floats with the `hdr` feature, and only uploaded
+    /// as such by the `Gles2Renderer` on GLES 3. Otherwise they are clipped to 8 bits while loading.
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        if self.tone_mapping != tone_mapping {
+            self.tone_mapping = tone_mapping;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the exposure set by [`WallpaperState::set_exposure`]
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Brightens HDR images by `stops` before tone mapping them, negative values darken them
+    pub fn set_exposure(&mut self, stops: f32) {
+        if self.exposure != stops {
+            self.exposure = stops;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns `true` if the wallpaper is drawn in linear light, see [`WallpaperState::set_linear_blending`]
+    pub fn linear_blending(&self) -> bool {
+        self.linear_blending
+    }
+
+    /// Scales, cross-fades and dims the image in linear light instead of on its sRGB values,
+    /// so fades do not pass through muddy, too dark colors.
+    ///
+    /// Images are uploaded as sRGB textures on GLES 3, otherwise they are decoded while drawing.
+    pub fn set_linear_blending(&mut self, linear: bool) {
+        if self.linear_blending != linear {
+            self.linear_blending = linear;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns `true` if the wallpaper is dithered, see [`WallpaperState::set_dithering`]
+    pub fn dithering(&self) -> bool {
+        self.dither
+    }
+
+    /// Adds blue noise of less than a color step to the wallpaper, which hides the banding
+    /// of smooth gradients on 8 bit outputs.
+    pub fn set_dithering(&mut self, dither: bool) {
+        if self.dither != dither {
+            self.dither = dither;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the blur radius set by [`WallpaperState::set_blur`], `0.0` if the image is not blurred
+    pub fn blur(&self) -> f32 {
+        self.blur.as_ref().map_or(0.0, |blur| blur.radius())
+    }
+
+    /// Blurs the image by roughly `radius` pixels, `0.0` disables the blur.
+    ///
+    /// The blur is rendered once per image into an offscreen texture,
+    /// so it is cheap to keep on e.g. during overview mode or on the lock screen.
+    /// If the blur shaders fail to compile the error is returned once by the next draw
+    /// as [`WallpaperError::Shader`] and the image is drawn sharp.
+    pub fn set_blur(&mut self, radius: f32) {
+        if radius <= 0.0 {
+            if self.blur.take().is_some() {
+                self.dirty = true;
+            }
+            return;
+        }
+        match &self.blur {
+            Some(blur) if blur.radius() == radius => return,
+            Some(blur) => blur.set_radius(radius),
+            None => self.blur = Some(Rc::new(blur::Blur::new(radius))),
+        }
+        self.dirty = true;
+    }
+
+    /// Draws the image into `span` instead of the area passed to [`WallpaperState::run`],
+    /// only showing the part overlapping the area.
+    ///
+    /// `span` is relative to the output, like the area. This allows a single image to continue across outputs.
+    pub fn set_span(&mut self, span: Option<Rectangle<i32, Logical>>) {
+        if self.span != span {
+            self.span = span;
+            self.dirty = true;
+        }
+    }
+
+    /// Only shows the part of the image inside `viewport`, in pixels of the image, `None` shows all of it.
+    ///
+    /// The viewport is fitted into the area by the [`ScalingMode`] like a whole image,
+    /// so moving it over time pans across the image. It is rounded to whole pixels.
+    pub fn set_viewport(&mut self, viewport: Option<Rectangle<f64, Logical>>) {
+        if self.viewport != viewport {
+            self.viewport = viewport;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the viewport set by [`WallpaperState::set_viewport`]
+    pub fn viewport(&self) -> Option<Rectangle<f64, Logical>> {
+        self.viewport
+    }
+
+    /// Updates the pointer location relative to the output
+    pub fn handle_pointer(&mut self, location: Point<f64, Logical>) {
+        if self.parallax > 0.0 && self.pointer != Some(location) {
+            self.dirty = true;
+        }
+        self.pointer = Some(location);
+    }
+
+    /// Returns the strength of the pointer parallax set by [`WallpaperState::set_parallax`]
+    pub fn parallax(&self) -> f64 {
+        self.parallax
+    }
+
+    /// Zooms in by `strength` and shifts the image against the pointer passed to
+    /// [`WallpaperState::handle_pointer`], so the wallpaper seems to lie behind the windows.
+    ///
+    /// `0.05` moves the image by up to 5% of the area, `0.0` disables the effect.
+    pub fn set_parallax(&mut self, strength: f64) {
+        let strength = strength.max(0.0);
+        if self.parallax != strength {
+            self.parallax = strength;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the position set by [`WallpaperState::set_workspace_position`]
+    pub fn workspace_position(&self) -> Option<f64> {
+        self.workspace_position
+    }
+
+    /// Moves the parallax horizontally to `position`, instead of following the pointer.
+    ///
+    /// `0.0` is the first and `1.0` the last workspace, animating it while switching workspaces
+    /// makes the layers of layered wallpapers slide at different speeds. They move by the strength
+    /// set with [`WallpaperState::set_parallax`], scaled by their depth.
+    pub fn set_workspace_position(&mut self, position: Option<f64>) {
+        if self.workspace_position != position {
+            self.workspace_position = position;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the zooms applied to the layout of an image at `depth`, in order
+    fn views(&self, area: Rectangle<i32, Logical>, depth: f64) -> Vec<motion::View> {
+        let mut views = Vec::new();
+        if let Some(motion) = &self.motion {
+            views.push(motion.view(self.now()));
+        }
+        if self.parallax <= 0.0 || depth <= 0.0 {
+            return views;
+        }
+        let area = area.to_f64();
+        let pointer = self.pointer.map(|pointer| {
+            (
+                (pointer.x - area.loc.x) / area.size.w,
+                (pointer.y - area.loc.y) / area.size.h,
+            )
+        });
+        let focus = match (self.workspace_position, pointer) {
+            (Some(position), pointer) => Some((position, pointer.map_or(0.5, |p| p.1))),
+            (None, pointer) => pointer,
+        };
+        if let Some(focus) = focus {
+            views.push(motion::View::parallax(self.parallax * depth, focus));
+        }
+        views
+    }
+
+    /// Stops any pending loads, slideshows and animations
+    fn stop_sources(&mut self) {
+        #[cfg(feature = "async_loading")]
+        {
+            self.loading = None;
+            self.generation += 1;
+            self.slideshow = None;
+            self.directory = None;
+            self.next_transition = None;
+            self.variants = None;
+            self.next_fallback = 0;
+            self.attempts = 0;
+            self.retry = None;
+        }
+        #[cfg(feature = "video")]
+        {
+            self.video = None;
+        }
+        #[cfg(feature = "mpv")]
+        {
+            self.mpv = None;
+        }
+        self.dmabuf = None;
+        #[cfg(feature = "compress")]
+        {
+            self.compression_job = None;
+            self.compressed = None;
+        }
+        #[cfg(feature = "svg")]
+        {
+            self.svg = None;
+        }
+        #[cfg(feature = "watch")]
+        {
+            self.watch = None;
+        }
+        #[cfg(feature = "remote")]
+        {
+            self.provider = None;
+        }
+        #[cfg(feature = "timeline")]
+        {
+            self.timeline = None;
+        }
+        #[cfg(feature = "gnome")]
+        {
+            self.gnome = None;
+        }
+        #[cfg(feature = "heic")]
+        {
+            self.heic = None;
+        }
+        #[cfg(feature = "kde")]
+        {
+            self.kde = None;
+        }
+        self.animation = None;
+    }
+
+    /// Shows the image at `light` or `dark` depending on the [`ColorScheme`].
+    ///
+    /// Both paths may be anything [`WallpaperState::set`] accepts.
+    #[cfg(feature = "async_loading")]
+    pub fn set_variants<P: AsRef<Path>>(
+        &mut self,
+        light: P,
+        dark: P,
+    ) -> Result<(), WallpaperError> {
+        let variants = (PathBuf::from(light.as_ref()), PathBuf::from(dark.as_ref()));
+        match self.color_scheme {
+            ColorScheme::Light => self.set(&variants.0)?,
+            ColorScheme::Dark => self.set(&variants.1)?,
+        }
+        self.variants = Some(variants);
+        Ok(())
+    }
+
+    /// Returns the `ColorScheme` picking the variant set by [`WallpaperState::set_variants`]
+    pub fn color_scheme(&self) -> ColorScheme {
+        self.color_scheme
+    }
+
+    /// Switches to the variant of `scheme`, cross-fading even if no `Transition` is set
+    pub fn set_color_scheme(&mut self, scheme: ColorScheme) {
+        if self.color_scheme == scheme {
+            return;
+        }
+        self.color_scheme = scheme;
+
+        #[cfg(feature = "async_loading")]
+        if let Some((light, dark)) = self.variants.clone() {
+            let path = match scheme {
+                ColorScheme::Light => &light,
+                ColorScheme::Dark => &dark,
+            };
+            match self.set(path) {
+                Ok(()) => {
+                    self.variants = Some((light.clone(), dark.clone()));
+                    self.next_transition = Some(match self.transition {
+                        Transition::None => Transition::Fade(VARIANT_FADE),
+                        transition => transition,
+                    });
+                }
+                Err(err) => self.set_error(err),
+            }
+        }
+    }
+
+    /// Cycles through `paths`, showing each image for `interval`.
+    ///
+    /// The next image is decoded in the background while the current one is displayed.
+    #[cfg(feature = "async_loading")]
+    pub fn set_playlist(
+        &mut self,
+        paths: Vec<PathBuf>,
+        interval: Duration,
+    ) -> Result<(), WallpaperError> {
+        if let Some(first) = paths.first() {
+            self.set(first)?;
+            #[cfg(feature = "watch")]
+            {
+                self.watch = None;
+            }
+            self.slideshow = Some(slideshow::Slideshow::new(
+                paths,
+                interval,
+                self.notify.clone(),
+                self.now(),
+            ));
+        } else {
+            self.slideshow = None;
+        }
+        Ok(())
+    }
+
+    /// Cycles through the images in the directory at `path` selected by `policy`.
+    ///
+    /// The directory is scanned on a separate thread once the output size is known,
+    /// and again whenever it changes, to filter images by resolution and aspect ratio.
+    #[cfg(feature = "async_loading")]
+    pub fn set_directory<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        policy: SelectionPolicy,
+    ) -> Result<(), WallpaperError> {
+        let path = PathBuf::from(path.as_ref());
+        std::fs::read_dir(&path).map_err(|err| WallpaperError::Io(path.clone(), err))?;
+        self.stop_sources();
+        self.directory = Some(directory::Directory::new(path, policy));
+        Ok(())
+    }
+
+    /// Skips to the next image of the playlist, returns `false` if no playlist is running
+    #[cfg(feature = "async_loading")]
+    pub fn next(&mut self) -> bool {
+        match &mut self.slideshow {
+            Some(slideshow) => {
+                slideshow.skip();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl WallpaperFrame {
+    /// Darkens the edges of the wallpaper, from `0.0` (none) to `1.0` (black corners)
+    ///
+    /// Like all color adjustments it is only supported by the `Gles2Renderer`
+    /// and not applied to shader wallpapers.
+    /// The frame is not damaged by it, changing it between frames requires damaging the output.
+    pub fn vignette(mut self, strength: f32) -> Self {
+        self.params.vignette = strength;
+        self
+    }
+
+    /// Rounds the corners of the wallpaper by `radius` in logical pixels, leaving them transparent
+    ///
+    /// Like [`WallpaperFrame::vignette`] it is only supported by the `Gles2Renderer`.
+    pub fn corner_radius(mut self, radius: f64) -> Self {
+        self.params.corner_radius = (radius * self.scale) as f32;
+        self
+    }
+
+    /// Forgets every texture and program this frame can reach, and all cached textures,
+    /// after the renderer they belong to was replaced.
+    ///
+    /// Fails if mpv could not be started again.
+    fn invalidate(&self) -> Result<(), WallpaperError> {
+        cache::clear();
+        context::clear();
+        *self.texture.borrow_mut() = None;
+        *self.dim_texture.borrow_mut() = None;
+        for (_, texture, _) in &self.layers {
+            *texture.borrow_mut() = None;
+        }
+        if let Some(fading) = &self.fading {
+            *fading.texture.borrow_mut() = None;
+        }
+        if let Some((_, texture, _)) = &self.held {
+            *texture.borrow_mut() = None;
+        }
+        if let Some(lut) = &self.params.lut {
+            *lut.texture.borrow_mut() = None;
+        }
+        self.filter.forget();
+        if let Some(blur) = &self.blur {
+            blur.forget();
+        }
+        if let Some((shader, _)) = &self.shader {
+            shader.forget();
+        }
+        if let Some(uploader) = &self.uploader {
+            uploader.forget();
+        }
+        #[cfg(feature = "mpv")]
+        if let Some(player) = &self.mpv {
+            player.forget()?;
+        }
+        Ok(())
+    }
+
+    /// Puts the textures `context` imported into their slots, the renderers of several GPUs
+    /// draw the same frame with textures of their own
+    fn select(&self, context: usize) {
+        let layers = self.layers.iter().map(|(_, texture, _)| texture);
+        let fading = self.fading.iter().map(|fading| &fading.texture);
+        let held = self.held.iter().map(|(_, texture, _)| texture);
+        let lut = self.params.lut.iter().map(|lut| &lut.texture);
+        for texture in [&self.texture, &self.dim_texture]
+            .into_iter()
+            .chain(layers)
+            .chain(fading)
+            .chain(held)
+            .chain(lut)
+        {
+            context::select(texture, context);
+        }
+    }
+
+    /// Returns `true` if images are drawn with the filter shader
+    fn filtered(&self) -> bool {
+        !self.params.is_identity() || self.filter.effect().is_some()
+    }
+
+    /// Returns the parts of the wallpaper without transparency, relative to its geometry,
+    /// so the compositor can skip clearing and drawing what is behind them.
+    ///
+    /// Empty for images with transparent pixels, rounded corners, shaders, dmabufs and during transitions.
+    pub fn opaque_regions(&self) -> Vec<Rectangle<i32, Logical>> {
+        let size = match self.opaque {
+            Some(size) if self.params.corner_radius <= 0.0 => size,
+            _ => return Vec::new(),
+        };
+        let geometry = tools::to_logical(self.area, self.scale);
+        let area = self.area.to_f64();
+        let (x1, y1) = (area.loc.x + area.size.w, area.loc.y + area.size.h);
+        // shrunk to whole logical pixels, unless they reach the edge of the area
+        let start = |dst: f64, edge: f64, logical: i32| {
+            if dst <= edge {
+                logical
+            } else {
+                (dst / self.scale).ceil() as i32
+            }
+        };
+        let end = |dst: f64, edge: f64, logical: i32| {
+            if dst >= edge {
+                logical
+            } else {
+                (dst / self.scale).floor() as i32
+            }
+        };
+        let (layout, _) = self.layout(size, &self.views, true);
+        layout
+            .into_iter()
+            .filter_map(|(_, dst)| {
+                let left = start(dst.loc.x, area.loc.x, geometry.loc.x);
+                let top = start(dst.loc.y, area.loc.y, geometry.loc.y);
+                let right = end(dst.loc.x + dst.size.w, x1, geometry.loc.x + geometry.size.w);
+                let bottom = end(dst.loc.y + dst.size.h, y1, geometry.loc.y + geometry.size.h);
+                (right > left && bottom > top).then(|| {
+                    Rectangle::from_loc_and_size(
+                        (left - geometry.loc.x, top - geometry.loc.y),
+                        (right - left, bottom - top),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Returns `true` if the wallpaper covers its whole geometry without transparency
+    pub fn is_opaque(&self) -> bool {
+        let geometry = tools::to_logical(self.area, self.scale);
+        let covered: i64 = self
+            .opaque_regions()
+            .iter()
+            .map(|region| region.size.w as i64 * region.size.h as i64)
+            .sum();
+        covered >= geometry.size.w as i64 * geometry.size.h as i64
+    }
+
+    /// Draw this frame using the given renderer
+    ///
+    /// Shader wallpapers are only supported by the `Gles2Renderer` and draw nothing otherwise.
+    pub fn draw<R>(
+        &self,
+        r: &mut R,
+        frame: &mut <R as Renderer>::Frame,
+    ) -> Result<(), WallpaperError>
+    where
+        R: Renderer + ImportMem + 'static,
+        <R as Renderer>::TextureId: 'static,
+        <R as Renderer>::Error: Send + Sync,
+    {
+        self.draw_damage(r, frame, &[self.area])
+    }
+
+    /// Draws only the parts of this frame within `damage`, in physical coordinates of the output,
+    /// leaving the rest of the output alone
+    pub fn draw_damage<R>(
+        &self,
+        r: &mut R,
+        frame: &mut <R as Renderer>::Frame,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), WallpaperError>
+    where
+        R: Renderer + ImportMem + 'static,
+        <R as Renderer>::TextureId: 'static,
+        <R as Renderer>::Error: Send + Sync,
+    {
+        let area = self.area;
+        let damage: Vec<Rectangle<i32, Physical>> = damage
+            .iter()
+            .filter_map(|rect| {
+                let x0 = rect.loc.x.max(area.loc.x);
+                let y0 = rect.loc.y.max(area.loc.y);
+                let x1 = (rect.loc.x + rect.size.w).min(area.loc.x + area.size.w);
+                let y1 = (rect.loc.y + rect.size.h).min(area.loc.y + area.size.h);
+                (x1 > x0 && y1 > y0)
+                    .then(|| Rectangle::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0)))
+            })
+            .collect();
+        if damage.is_empty() {
+            return Ok(());
+        }
+        let damage = &damage[..];
+        self.stats.record_frame();
+        // textures and programs of a replaced renderer are gone, they are created again below
+        if self.context.invalidated() {
+            self.invalidate()?;
+        }
+        self.select(context::id(&*r));
+        if let Some((shader, uniforms)) = &self.shader {
+            if let Some(r) = (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+                shader.draw(r, uniforms, damage)?;
+            }
+        } else {
+            let alpha = match &self.fading {
+                Some(fading) => {
+                    self.draw_image(
+                        r,
+                        frame,
+                        &fading.image,
+                        &fading.texture,
+                        fading.frame,
+                        1.0,
+                        &self.views,
+                        None,
+                        damage,
+                    )?;
+                    fading.progress
+                }
+                None => 1.0,
+            };
+            if let Some(dmabuf) = &self.dmabuf {
+                prepare_dmabuf(r, dmabuf, &self.texture, self.frame)?;
+            }
+            // swaps the texture for the smaller compressed one once it is transcoded
+            #[cfg(feature = "compress")]
+            if let Some(compressed) = &self.compressed {
+                compress::upload(r, compressed, &self.texture, self.params.linear)?;
+            }
+            // animations only upload what changed since the previous frame
+            if let (Some(image), Some((previous, region))) = (&*self.image, self.changed) {
+                update_region(
+                    r,
+                    image,
+                    &self.texture,
+                    previous,
+                    self.frame,
+                    region,
+                    &self.stats,
+                )?;
+            }
+            #[cfg(feature = "mpv")]
+            if let Some(player) = &self.mpv {
+                player.render(r, &self.texture)?;
+            }
+            let streaming = match (&*self.image, &self.uploader) {
+                (Some(image), Some(uploader)) => {
+                    uploader.stream(r, image, &self.texture, self.frame, self.params.linear)?
+                }
+                _ => false,
+            };
+            let views = &self.views;
+            let mut filtered = match &self.held {
+                // the image shown before stays until the new one is uploaded
+                Some((image, texture, index)) if streaming => {
+                    self.draw_image(r, frame, image, texture, *index, 1.0, views, None, damage)?
+                }
+                _ if streaming => false,
+                _ => self.draw_image(
+                    r,
+                    frame,
+                    &self.image,
+                    &self.texture,
+                    self.frame,
+                    alpha,
+                    views,
+                    self.fading.as_ref(),
+                    damage,
+                )?,
+            };
+            if !self.layers.is_empty() {
+                filtered = true;
+                for (image, texture, views) in &self.layers {
+                    filtered &=
+                        self.draw_image(r, frame, image, texture, 0, 1.0, views, None, damage)?;
+                }
+            }
+            // the filter dims the image itself, keeping the rounded corners transparent
+            if filtered {
+                return Ok(());
+            }
+        }
+        self.draw_dim(r, frame, damage)
+    }
+
+    /// Draws this frame into `buffer` on the CPU, for compositors without a GL renderer,
+    /// e.g. headless or in virtual machines.
+    ///
+    /// `buffer` holds an output of `size` in the ARGB8888 format of `wl_shm`, with rows of `stride` bytes.
+    /// Images are scaled to the nearest pixels, without color adjustments or blur,
+    /// and shaders, videos and dmabufs draw nothing.
+    #[cfg(feature = "software")]
+    pub fn draw_software(&self, buffer: &mut [u8], stride: usize, size: Size<i32, Physical>) {
+        if self.shader.is_some() {
+            return;
+        }
+        self.stats.record_frame();
+        let mut target = software::Target {
+            buffer,
+            stride,
+            size,
+        };
+        let mut draw = |image: &Option<DynamicImage>, views: &[motion::View], alpha: f32| {
+            if let Some(image) = image {
+                let image_size = Size::from((image.width() as i32, image.height() as i32));
+                let (layout, _) = self.layout(image_size, views, false);
+                software::draw_image(&mut target, image, &layout, alpha);
+            }
+        };
+        let alpha = match &self.fading {
+            Some(fading) => {
+                draw(&fading.image, &self.views, 1.0);
+                fading.progress
+            }
+            None => 1.0,
+        };
+        draw(&self.image, &self.views, alpha);
+        for (image, _, views) in &self.layers {
+            draw(image, views, 1.0);
+        }
+        if self.dim > 0.0 {
+            software::dim(&mut target, self.area.to_f64(), self.dim);
+        }
+    }
+
+    /// Darkens the wallpaper by drawing black over it with the dimming as alpha
+    fn draw_dim<R>(
+        &self,
+        r: &mut R,
+        frame: &mut <R as Renderer>::Frame,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), WallpaperError>
+    where
+        R: Renderer + ImportMem + 'static,
+        <R as Renderer>::TextureId: 'static,
+        <R as Renderer>::Error: Send + Sync,
+    {
+        if self.dim <= 0.0 {
+            return Ok(());
+        }
+        prepare_texture(
+            r,
+            &background::color([0.0, 0.0, 0.0, 1.0]),
+            &self.dim_texture,
+            0,
+            false,
+            &self.stats,
+        )?;
+        let cached_texture = self.dim_texture.borrow();
+        let texture = cached_texture
+            .as_ref()
+            .unwrap()
+            .texture
+            .downcast_ref::<<R as Renderer>::TextureId>()
+            .unwrap();
+        frame
+            .render_texture_from_to(
+                texture,
+                Rectangle::from_loc_and_size((0, 0), (1, 1)),
+                self.area.to_f64(),
+                &tools::damage_in(damage, self.area.to_f64()),
+                Transform::Normal,
+                self.dim,
+            )
+            .map_err(|err| WallpaperError::Render(Box::new(err)))
+    }
+
+    /// Draws `image` zoomed by `views`, returns `true` if it was drawn with the filter,
+    /// which dims it as well
+    ///
+    /// `fade` is the previous image while `image` fades in with `alpha`, which was drawn already.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_image<R>(
+        &self,
+        r: &mut R,
+        frame: &mut <R as Renderer>::Frame,
+        image: &Option<DynamicImage>,
+        texture: &RefCell<Option<CachedTexture>>,
+        index: usize,
+        alpha: f32,
+        views: &[motion::View],
+        fade: Option<&transition::Fading>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<bool, WallpaperError>
+    where
+        R: Renderer + ImportMem + 'static,
+        <R as Renderer>::TextureId: 'static,
+        <R as Renderer>::Error: Send + Sync,
+    {
+        let mut filtered = false;
+        let image_size = match image {
+            Some(image) => {
+                prepare_texture(r, image, texture, index, self.params.linear, &self.stats)?;
+                Size::from((image.width() as i32, image.height() as i32))
+            }
+            // dropped after the upload, the texture is all that is left
+            None => match &*texture.borrow() {
+                Some(cached) if cached.is::<<R as Renderer>::TextureId>() => cached.size,
+                _ => return Ok(false),
+            },
+        };
+        let cached_texture = texture.borrow();
+        let cached = cached_texture.as_ref().unwrap();
+        let single = cached.texture.downcast_ref::<<R as Renderer>::TextureId>();
+
+        let blurred = match (&self.blur, single) {
+            (Some(blur), Some(texture)) if !cached.external => {
+                blur::apply(r, blur, texture, image_size, index)?
+            }
+            _ => None,
+        };
+        let mipmaps = cached.mipmaps && blurred.is_none();
+        // the blur renders into a texture of 8 bits per channel
+        let hdr = cached.hdr && blurred.is_none();
+        let tiles: Vec<(Rectangle<i32, Buffer>, &<R as Renderer>::TextureId)> = match single {
+            Some(texture) => {
+                let texture = blurred
+                    .as_ref()
+                    .and_then(|blurred| (blurred as &dyn Any).downcast_ref())
+                    .unwrap_or(texture);
+                vec![(Rectangle::from_loc_and_size((0, 0), image_size), texture)]
+            }
+            // too large for a single texture, the parts are drawn side by side
+            None => cached
+                .texture
+                .downcast_ref::<tools::Tiles<<R as Renderer>::TextureId>>()
+                .unwrap()
+                .iter()
+                .map(|(part, texture)| (*part, texture))
+                .collect(),
+        };
+        let can_repeat = tiles.len() == 1 && !cached.external && tools::can_repeat(r, tiles[0].1);
+        let (layout, repeat) = self.layout(image_size, views, can_repeat);
+        // cross-fades in linear light by mixing the previous image in, instead of blending over it
+        let previous_texture = fade
+            .filter(|_| self.params.linear)
+            .map(|fading| (fading.texture.borrow(), fading.frame));
+        let previous_cached = previous_texture.as_ref().and_then(|(cached, frame)| {
+            let cached = cached.as_ref().filter(|cached| !cached.external)?;
+            let texture = cached
+                .texture
+                .downcast_ref::<<R as Renderer>::TextureId>()?;
+            Some((cached, texture, *frame))
+        });
+        let previous_blurred = match (&self.blur, previous_cached) {
+            (Some(blur), Some((cached, texture, frame))) => {
+                blur::apply(r, blur, texture, cached.size, frame)?
+            }
+            _ => None,
+        };
+        let previous = previous_cached.and_then(|(cached, texture, _)| {
+            let texture = previous_blurred
+                .as_ref()
+                .and_then(|blurred| (blurred as &dyn Any).downcast_ref())
+                .unwrap_or(texture);
+            match self.layout(cached.size, views, false).0[..] {
+                [(src, dst)] => Some(filter::Previous {
+                    texture,
+                    srgb: cached.srgb,
+                    src,
+                    dst,
+                    progress: alpha,
+                }),
+                _ => None,
+            }
+        });
+        if let Some(lut) = &self.params.lut {
+            prepare_texture(r, lut.image(), &lut.texture, 0, false, &self.stats)?;
+        }
+        let target = filter::Target {
+            size: self.size,
+            transform: self.transform,
+            area: self.area,
+            mipmaps,
+            nearest: self.scaling == ScalingMode::PixelArt,
+            repeat,
+            hdr,
+            srgb: cached.srgb,
+            damage,
+        };
+        for (part, texture) in &tiles {
+            let pairs: Vec<_> = match tiles.len() {
+                1 => layout.clone(),
+                _ => layout
+                    .iter()
+                    .filter_map(|pair| scaling::split(*pair, *part))
+                    .collect(),
+            };
+            for (src, dst) in pairs {
+                // drawn smaller, the filter samples the mipmaps trilinearly
+                let downscaled = dst.size.w < src.size.w as f64 || dst.size.h < src.size.h as f64;
+                let effects =
+                    self.filtered() || (mipmaps && downscaled) || target.nearest || repeat || hdr;
+                if effects && !cached.external {
+                    let params = &self.params;
+                    // mixed with the previous image instead of blended over it
+                    let alpha = if previous.is_some() { 1.0 } else { alpha };
+                    if filter::apply(
+                        r,
+                        &self.filter,
+                        *texture,
+                        src,
+                        dst,
+                        target,
+                        alpha,
+                        params,
+                        previous,
+                    )? {
+                        filtered = true;
+                        continue;
+                    }
+                }
+                frame
+                    .render_texture_from_to(
+                        *texture,
+                        src,
+                        dst,
+                        &tools::damage_in(damage, dst),
+                        Transform::Normal,
+                        alpha,
+                    )
+                    .map_err(|err| WallpaperError::Render(Box::new(err)))?;
+            }
+        }
+        Ok(filtered)
+    }
+
+    /// Returns which parts of an image of `image_size` are drawn where, moved by `views`,
+    /// and if a tiled image is drawn as a single quad repeating its texture, which needs `can_repeat`
+    fn layout(
+        &self,
+        image_size: Size<i32, Buffer>,
+        views: &[motion::View],
+        can_repeat: bool,
+    ) -> (
+        Vec<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)>,
+        bool,
+    ) {
+        let bounds = self.span.unwrap_or(self.area);
+        let viewport = self.viewport.and_then(|viewport| {
+            // rounded to whole pixels and clamped to the image
+            let x = |x: f64| (x.round() as i32).clamp(0, image_size.w);
+            let y = |y: f64| (y.round() as i32).clamp(0, image_size.h);
+            let (x0, y0) = (x(viewport.loc.x), y(viewport.loc.y));
+            let (x1, y1) = (
+                x(viewport.loc.x + viewport.size.w),
+                y(viewport.loc.y + viewport.size.h),
+            );
+            (x1 > x0 && y1 > y0)
+                .then(|| Rectangle::<i32, Buffer>::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0)))
+        });
+        // a single quad repeating the texture instead of one per tile
+        let repeat = self.scaling == ScalingMode::Tile && viewport.is_none() && can_repeat;
+        let mut layout = if repeat {
+            vec![(
+                Rectangle::from_loc_and_size((0, 0), (bounds.size.w, bounds.size.h)),
+                bounds.to_f64(),
+            )]
+        } else if let Some(viewport) = viewport {
+            // laid out like an image of the size of the viewport, then moved onto it
+            scaling::layout(self.scaling, self.anchor, viewport.size, bounds)
+                .into_iter()
+                .map(|(mut src, dst)| {
+                    src.loc += viewport.loc;
+                    (src, dst)
+                })
+                .collect()
+        } else {
+            scaling::layout(self.scaling, self.anchor, image_size, bounds)
+        };
+        for view in views {
+            layout = view.apply(layout, bounds);
+        }
+        if self.span.is_some() {
+            layout = scaling::clip(layout, self.area);
+        }
+        (layout, repeat)
+    }
+}
+
+/// Imports `dmabuf` as frame `index` into `texture`, unless it already holds it.
+///
+/// Only the `Gles2Renderer` imports dmabufs, for other renderers `texture` is left alone.
+fn prepare_dmabuf<R>(
+    r: &mut R,
+    dmabuf: &Dmabuf,
+    texture: &RefCell<Option<CachedTexture>>,
+    index: usize,
+) -> Result<(), WallpaperError>
+where
+    R: Renderer + 'static,
+{
+    let r = match (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let mut cached_texture = texture.borrow_mut();
+    if let Some(cached) = &*cached_texture {
+        if cached.frame == index && cached.is::<Gles2Texture>() {
+            return Ok(());
+        }
+    }
+    // formats the renderer cannot render to are sampled as external images
+    let external = !r
+        .egl_context()
+        .dmabuf_render_formats()
+        .contains(&dmabuf.format());
+    let imported = r
+        .import_dmabuf(dmabuf)
+        .map_err(|err| WallpaperError::Import(Box::new(err)))?;
+    *cached_texture = Some(CachedTexture {
+        texture: Box::new(imported),
+        frame: index,
+        size: dmabuf.size(),
+        mipmaps: false,
+        hdr: false,
+        srgb: false,
+        linear: false,
+        external,
+        compressed: false,
+        context: context::id(&*r),
+        bytes: tools::dmabuf_bytes(dmabuf),
+    });
+    Ok(())
+}
+
+/// Imports frame `index` of `image` into `texture`, unless it already holds it.
+///
+/// With `linear`, the image is imported into an sRGB texture if the renderer supports it.
+/// Imports are counted in `stats`.
+fn prepare_texture<R>(
+    r: &mut R,
+    image: &DynamicImage,
+    texture: &RefCell<Option<CachedTexture>>,
+    index: usize,
+    linear: bool,
+    stats: &stats::Counters,
+) -> Result<(), WallpaperError>
+where
+    R: Renderer + ImportMem + 'static,
+    <R as Renderer>::TextureId: 'static,
+    <R as Renderer>::Error: Send + Sync,
+{
+    let mut cached_texture = texture.borrow_mut();
+    let started = Instant::now();
+
+    // import again if there is no texture yet, it was created by another kind of renderer
+    // or for the other kind of blending
+    let reusable = match &*cached_texture {
+        Some(cached) => cached.is::<<R as Renderer>::TextureId>() && cached.linear == linear,
+        None => false,
+    };
+    if !reusable {
+        let size = Size::from((image.width() as i32, image.height() as i32));
+        let context = context::id(&*r);
+        // a freed texture of the same size is filled instead of allocating a new one,
+        // floats are imported into textures of their own
+        let spare = match image.as_rgba32f() {
+            Some(_) => None,
+            None => cache::take_spare(|spare| {
+                spare.size == size
+                    && spare.linear == linear
+                    && !spare.hdr
+                    && !spare.external
+                    && !spare.compressed
+                    && spare.context == context
+                    && spare.is::<<R as Renderer>::TextureId>()
+            }),
+        };
+        if let Some(mut spare) = spare {
+            spare.frame = index;
+            update_texture(r, &spare, image)?;
+            *cached_texture = Some(spare);
+            stats.record_upload(started);
+            return Ok(());
+        }
+        let max =
+            tools::max_texture_size(r).filter(|max| image.width() > *max || image.height() > *max);
+        *cached_texture = Some(match max {
+            // split into tiles the renderer can hold, drawn without mipmaps
+            Some(max) => CachedTexture {
+                texture: Box::new(
+                    tools::import_tiled(r, &*tools::rgba8(image), max)
+                        .map_err(|err| WallpaperError::Import(Box::new(err)))?,
+                ),
+                frame: index,
+                size,
+                mipmaps: false,
+                hdr: false,
+                srgb: false,
+                linear,
+                external: false,
+                compressed: false,
+                context,
+                bytes: tools::texture_bytes(size, 4, false),
+            },
+            None => match image
+                .as_rgba32f()
+                .and_then(|image| tools::import_float(r, image))
+            {
+                // GLES 3 only generates mipmaps of half floats with `GL_EXT_color_buffer_half_float`
+                Some(imported) => CachedTexture {
+                    texture: Box::new(imported),
+                    frame: index,
+                    size,
+                    mipmaps: false,
+                    hdr: true,
+                    srgb: false,
+                    linear,
+                    external: false,
+                    compressed: false,
+                    context,
+                    // RGBA16F
+                    bytes: tools::texture_bytes(size, 8, false),
+                },
+                None => {
+                    let rgba = tools::rgba8(image);
+                    let srgb = if linear {
+                        tools::import_srgb(r, &rgba)
+                    } else {
+                        None
+                    };
+                    let (imported, srgb) = match srgb {
+                        Some(imported) => (imported, true),
+                        None => (
+                            tools::import_bitmap(r, &*rgba)
+                                .map_err(|err| WallpaperError::Import(Box::new(err)))?,
+                            false,
+                        ),
+                    };
+                    let mipmaps = tools::generate_mipmaps(r, &imported);
+                    CachedTexture {
+                        texture: Box::new(imported),
+                        frame: index,
+                        size,
+                        mipmaps,
+                        hdr: false,
+                        srgb,
+                        linear,
+                        external: false,
+                        compressed: false,
+                        context,
+                        bytes: tools::texture_bytes(size, 4, mipmaps),
+                    }
+                }
+            },
+        });
+        stats.record_upload(started);
+        // the cache looks at every texture, so it must not be borrowed anymore
+        drop(cached_texture);
+        cache::trim();
+        return Ok(());
+    }
+
+    let cached_texture = cached_texture.as_mut().unwrap();
+    if cached_texture.frame != index {
+        cached_texture.frame = index;
+        update_texture(r, cached_texture, image)?;
+        stats.record_upload(started);
+    }
+    Ok(())
+}
+
+/// Updates `texture` from animation frame `previous` to frame `index`, which only differ in `region`.
+///
+/// Textures holding another frame are left alone, like tiled ones which are updated whole.
+fn update_region<R>(
+    r: &mut R,
+    image: &DynamicImage,
+    texture: &RefCell<Option<CachedTexture>>,
+    previous: usize,
+    index: usize,
+    region: Rectangle<i32, Buffer>,
+    stats: &stats::Counters,
+) -> Result<(), WallpaperError>
+where
+    R: Renderer + ImportMem + 'static,
+    <R as Renderer>::TextureId: 'static,
+    <R as Renderer>::Error: Send + Sync,
+{
+    let mut cached_texture = texture.borrow_mut();
+    let cached = match &mut *cached_texture {
+        Some(cached) if cached.frame == previous && !cached.hdr => cached,
+        _ => return Ok(()),
+    };
+    let texture = match cached.texture.downcast_ref::<<R as Renderer>::TextureId>() {
+        Some(texture) => texture,
+        None => return Ok(()),
+    };
+    if region.size.w > 0 && region.size.h > 0 {
+        let started = Instant::now();
+        tools::update_region(r, texture, &*tools::rgba8(image), region)
+            .map_err(|err| WallpaperError::Import(Box::new(err)))?;
+        if cached.mipmaps {
+            tools::generate_mipmaps(r, texture);
+        }
+        stats.record_upload(started);
+    }
+    cached.frame = index;
+    Ok(())
+}
+
+/// Replaces the contents of `cached` with `image`, which must have the same size
+fn update_texture<R>(
+    r: &mut R,
+    cached: &CachedTexture,
+    image: &DynamicImage,
+) -> Result<(), WallpaperError>
+where
+    R: Renderer + ImportMem + 'static,
+    <R as Renderer>::TextureId: 'static,
+    <R as Renderer>::Error: Send + Sync,
+{
+    if let Some(tiles) = cached
+        .texture
+        .downcast_ref::<tools::Tiles<<R as Renderer>::TextureId>>()
+    {
+        return tools::update_tiled(r, tiles, &*tools::rgba8(image))
+            .map_err(|err| WallpaperError::Import(Box::new(err)));
+    }
+    let texture = cached
+        .texture
+        .downcast_ref::<<R as Renderer>::TextureId>()
+        .unwrap();
+    tools::update_bitmap(r, texture, &*tools::rgba8(image))
+        .map_err(|err| WallpaperError::Import(Box::new(err)))?;
+    if cached.mipmaps {
+        tools::generate_mipmaps(r, texture);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "render_element")]
+impl<R, F, E, T> RenderElement<R, F, E, T> for WallpaperFrame
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportMem + 'static,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error + Send + Sync + 'static,
+    T: Texture + 'static,
+{
+    fn id(&self) -> usize {
+        self.state_id
+    }
+
+    fn geometry(&self) -> Rectangle<i32, Logical> {
+        tools::to_logical(self.area, self.scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        _for_values: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Logical>> {
+        if self.damaged {
+            vec![Rectangle::from_loc_and_size(
+                (0, 0),
+                tools::to_logical(self.area, self.scale).size,
+            )]
+        } else {
+            vec![]
+        }
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut R,
+        frame: &mut F,
+        _scale: f64,
+        damage: &[Rectangle<i32, Logical>],
+        log: &slog::Logger,
+    ) -> Result<(), E> {
+        // the damage is relative to the geometry
+        let origin = tools::to_logical(self.area, self.scale).loc;
+        let damage = tools::damage_to_physical(damage, origin, self.scale);
+        match self.draw_damage(renderer, frame, &damage) {
+            Ok(()) => Ok(()),
+            // failures of the renderer are the compositor's to handle, anything else only
+            // leaves the wallpaper out of this frame
+            Err(err) => match err.into_renderer_error::<E>() {
+                Ok(err) => Err(err),
+                Err(err) => {
+                    slog::error!(log, "wallpaper rendering error: {}", err);
+                    Ok(())
+                }
+            },
         }
-        Ok(())
     }
 
     fn z_index(&self) -> u8 {
-        0
+        self.z_index
     }
 }
@@ -0,0 +1,826 @@
+#[cfg(feature = "async_loading")]
+use std::cell::RefCell;
+#[cfg(feature = "async_loading")]
+use std::fmt;
+use std::io::{BufRead, Cursor, Seek};
+use std::path::Path;
+#[cfg(feature = "async_loading")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+#[cfg(feature = "async_loading")]
+use std::sync::{
+    atomic::AtomicBool,
+    mpsc::{self, TryRecvError},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+use image::{
+    codecs::{gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, webp::WebPDecoder},
+    error::{ImageFormatHint, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind},
+    imageops::FilterType,
+    io::{Limits, Reader},
+    AnimationDecoder, DynamicImage, GenericImageView, ImageDecoder, ImageError, ImageFormat,
+    ImageResult,
+};
+
+#[cfg(feature = "icc")]
+use image::error::DecodingError;
+
+use crate::cache::Key;
+#[cfg(feature = "async_loading")]
+use crate::WallpaperError;
+use crate::{stats, ScalingMode};
+
+/// Frames with a shorter delay are shown for 100ms, like browsers do
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+/// JPEGs with more pixels are shown as a low-resolution preview while they are decoded
+#[cfg(feature = "async_loading")]
+const PREVIEW_MIN_PIXELS: u64 = 3840 * 2160;
+
+#[cfg(feature = "async_loading")]
+thread_local! {
+    /// Errors a load on this thread recovered from since the last [`Completer::complete`]
+    static WARNINGS: RefCell<Vec<WallpaperError>> = RefCell::new(Vec::new());
+}
+
+static MAX_WIDTH: AtomicU32 = AtomicU32::new(DecodeLimits::DEFAULT.max_width);
+static MAX_HEIGHT: AtomicU32 = AtomicU32::new(DecodeLimits::DEFAULT.max_height);
+static MAX_BYTES: AtomicU64 = AtomicU64::new(DecodeLimits::DEFAULT.max_bytes);
+
+/// Largest images decoded, so a corrupted file or a decompression bomb
+/// fails to load instead of exhausting the memory of the compositor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Decoded size of an image, all frames of an animation together
+    pub max_bytes: u64,
+}
+
+impl DecodeLimits {
+    /// 16384x16384 pixels and 1 GiB, far beyond any display
+    const DEFAULT: Self = Self {
+        max_width: 16384,
+        max_height: 16384,
+        max_bytes: 1024 * 1024 * 1024,
+    };
+
+    /// Accepts images of any size
+    pub fn unlimited() -> Self {
+        Self {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_bytes: u64::MAX,
+        }
+    }
+
+    fn check_dimensions(&self, (width, height): (u32, u32)) -> ImageResult<()> {
+        if width > self.max_width || height > self.max_height {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_bytes(&self, bytes: u64) -> ImageResult<()> {
+        if bytes > self.max_bytes {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::InsufficientMemory,
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks the size of an RGBA image of `dimensions`, for decoders outside of the image crate
+    pub(crate) fn check_rgba(&self, dimensions: (u32, u32)) -> ImageResult<()> {
+        self.check_dimensions(dimensions)?;
+        self.check_bytes(dimensions.0 as u64 * dimensions.1 as u64 * 4)
+    }
+
+    /// Checks the size of the image `decoder` would produce, before decoding it
+    fn check<'a, D: ImageDecoder<'a>>(&self, decoder: &D) -> ImageResult<()> {
+        self.check_dimensions(decoder.dimensions())?;
+        self.check_bytes(decoder.total_bytes())
+    }
+
+    fn limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_bytes);
+        limits
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Sets the largest images decoded by all wallpapers, larger ones fail with [`image::ImageError::Limits`].
+///
+/// Defaults to 16384x16384 pixels and 1 GiB.
+pub fn set_decode_limits(limits: DecodeLimits) {
+    MAX_WIDTH.store(limits.max_width, Ordering::Relaxed);
+    MAX_HEIGHT.store(limits.max_height, Ordering::Relaxed);
+    MAX_BYTES.store(limits.max_bytes, Ordering::Relaxed);
+}
+
+/// Returns the limits set by [`set_decode_limits`]
+pub fn decode_limits() -> DecodeLimits {
+    DecodeLimits {
+        max_width: MAX_WIDTH.load(Ordering::Relaxed),
+        max_height: MAX_HEIGHT.load(Ordering::Relaxed),
+        max_bytes: MAX_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Decoded contents of a wallpaper file
+#[derive(Debug)]
+pub(crate) enum Loaded {
+    Image(DynamicImage),
+    Animation(Vec<(DynamicImage, Duration)>),
+    /// Images of a layered wallpaper from back to front, with their depth
+    Layers(Vec<(DynamicImage, f64)>),
+    /// A wallpaper picking its image over time, whose images are loaded once it is set
+    #[cfg(feature = "heic")]
+    Source(Source),
+}
+
+/// A wallpaper opened on the loader threads that picks its image over time
+#[cfg(feature = "heic")]
+#[derive(Debug)]
+pub(crate) enum Source {
+    Heic(crate::heic::DynamicHeic),
+    #[cfg(feature = "kde")]
+    Package(crate::kde::PlasmaPackage),
+}
+
+impl Loaded {
+    /// Key to share the texture of a still image, animations are updated in place and never shared
+    pub fn key(&self) -> Option<Key> {
+        match self {
+            Loaded::Image(image) => Some(Key::of(image)),
+            Loaded::Animation(_) | Loaded::Layers(_) => None,
+            #[cfg(feature = "heic")]
+            Loaded::Source(_) => None,
+        }
+    }
+
+    /// Applies `f` to every image, frame and layer
+    pub fn map<F: Fn(DynamicImage) -> DynamicImage>(self, f: F) -> Self {
+        match self {
+            Loaded::Image(image) => Loaded::Image(f(image)),
+            Loaded::Animation(frames) => Loaded::Animation(
+                frames
+                    .into_iter()
+                    .map(|(image, delay)| (f(image), delay))
+                    .collect(),
+            ),
+            Loaded::Layers(layers) => Loaded::Layers(
+                layers
+                    .into_iter()
+                    .map(|(image, depth)| (f(image), depth))
+                    .collect(),
+            ),
+            #[cfg(feature = "heic")]
+            Loaded::Source(source) => Loaded::Source(source),
+        }
+    }
+
+    /// Like [`Loaded::map`], stopping at the first image `f` fails for
+    #[cfg(feature = "icc")]
+    pub fn try_map<E, F: Fn(DynamicImage) -> Result<DynamicImage, E>>(
+        self,
+        f: F,
+    ) -> Result<Self, E> {
+        Ok(match self {
+            Loaded::Image(image) => Loaded::Image(f(image)?),
+            Loaded::Animation(frames) => Loaded::Animation(
+                frames
+                    .into_iter()
+                    .map(|(image, delay)| Ok((f(image)?, delay)))
+                    .collect::<Result<_, E>>()?,
+            ),
+            Loaded::Layers(layers) => Loaded::Layers(
+                layers
+                    .into_iter()
+                    .map(|(image, depth)| Ok((f(image)?, depth)))
+                    .collect::<Result<_, E>>()?,
+            ),
+            #[cfg(feature = "heic")]
+            Loaded::Source(source) => Loaded::Source(source),
+        })
+    }
+}
+
+/// Filter used to scale images down on the loader thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resampling {
+    /// Bilinear filtering, fast but softening fine detail
+    Bilinear,
+    /// Lanczos with a window of 3, the sharpest but several times slower
+    Lanczos3,
+}
+
+impl Default for Resampling {
+    fn default() -> Self {
+        Resampling::Bilinear
+    }
+}
+
+/// Size an image is drawn at, to scale it down to before it is uploaded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Prescale {
+    /// Physical size of the area the image is drawn into, times the largest zoom
+    pub size: (u32, u32),
+    pub mode: ScalingMode,
+    pub resampling: Resampling,
+}
+
+impl Prescale {
+    /// Scales `image` down to the size it is drawn at, never up
+    fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let (width, height) = image.dimensions();
+        let (target_w, target_h) = (self.size.0.max(1) as f64, self.size.1.max(1) as f64);
+        let (scale_x, scale_y) = (target_w / width as f64, target_h / height as f64);
+        let (scale_x, scale_y) = match self.mode {
+            ScalingMode::Fill => (scale_x.max(scale_y), scale_x.max(scale_y)),
+            ScalingMode::Fit => (scale_x.min(scale_y), scale_x.min(scale_y)),
+            ScalingMode::Stretch => (scale_x, scale_y),
+            // drawn unscaled
+            ScalingMode::Center | ScalingMode::Tile | ScalingMode::PixelArt => return image,
+        };
+        if scale_x >= 1.0 && scale_y >= 1.0 {
+            return image;
+        }
+        let size =
+            |length: u32, scale: f64| ((length as f64 * scale.min(1.0)).round() as u32).max(1);
+        let filter = match self.resampling {
+            Resampling::Bilinear => FilterType::Triangle,
+            Resampling::Lanczos3 => FilterType::Lanczos3,
+        };
+        image.resize_exact(size(width, scale_x), size(height, scale_y), filter)
+    }
+}
+
+impl Loaded {
+    /// Converts all images to RGBA, the format textures are imported from,
+    /// so this does not stall the render thread
+    fn into_rgba8(self) -> Self {
+        self.map(rgba8)
+    }
+
+    /// Scales all images down to the size they are drawn at
+    fn prescaled(self, prescale: &Prescale) -> Self {
+        match self {
+            Loaded::Image(image) => Loaded::Image(prescale.apply(image)),
+            Loaded::Animation(frames) => Loaded::Animation(
+                frames
+                    .into_iter()
+                    .map(|(image, delay)| (prescale.apply(image), delay))
+                    .collect(),
+            ),
+            // layers move by their depth and are shown larger than the area
+            Loaded::Layers(layers) => Loaded::Layers(layers),
+            #[cfg(feature = "heic")]
+            Loaded::Source(source) => Loaded::Source(source),
+        }
+    }
+}
+
+/// Converts `image` to the format textures are imported from.
+///
+/// With the `hdr` feature, images with more than 8 bits per channel are kept in floats,
+/// so they are uploaded without banding and colors brighter than white can be tone mapped.
+fn rgba8(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgba8(_) => image,
+        #[cfg(feature = "hdr")]
+        DynamicImage::ImageRgba32F(_) => image,
+        #[cfg(feature = "hdr")]
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_)
+        | DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgba32F(image.into_rgba32f()),
+        image => DynamicImage::ImageRgba8(image.into_rgba8()),
+    }
+}
+
+/// Called on the loader thread once a load finished, to wake up the compositor
+#[cfg(feature = "async_loading")]
+#[derive(Clone, Default)]
+pub(crate) struct Notify(Option<Arc<dyn Fn() + Send + Sync>>);
+
+#[cfg(feature = "async_loading")]
+impl Notify {
+    #[cfg(feature = "calloop")]
+    pub fn new<F: Fn() + Send + Sync + 'static>(notify: F) -> Self {
+        Self(Some(Arc::new(notify)))
+    }
+
+    /// Wakes up the compositor, if it asked for it
+    pub fn notify(&self) {
+        if let Some(notify) = &self.0 {
+            notify();
+        }
+    }
+}
+
+#[cfg(feature = "async_loading")]
+impl fmt::Debug for Notify {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Notify").field(&self.0.is_some()).finish()
+    }
+}
+
+/// Hands an error the load on this thread recovered from to the state, which reports it once the load completes
+#[cfg(feature = "async_loading")]
+pub(crate) fn warn(err: WallpaperError) {
+    WARNINGS.with(|warnings| warnings.borrow_mut().push(err));
+}
+
+/// What a load hands to its `Pending` once it finished
+#[cfg(feature = "async_loading")]
+#[derive(Debug)]
+pub(crate) struct Completed {
+    pub result: ImageResult<(Loaded, Option<Key>)>,
+    /// Decodes of the loader thread, to add to the stats of the state
+    pub decodes: stats::Decodes,
+    /// Errors the load recovered from, see [`warn`]
+    pub warnings: Vec<WallpaperError>,
+}
+
+/// A load running on a separate thread, cancelled when dropped
+#[cfg(feature = "async_loading")]
+#[derive(Debug)]
+pub(crate) struct Pending {
+    result: mpsc::Receiver<Completed>,
+    preview: mpsc::Receiver<DynamicImage>,
+    cancelled: Arc<AtomicBool>,
+    /// Generation of the state this load was started in
+    generation: u64,
+    /// File being decoded, if the load was started by [`Pending::open`]
+    path: Option<PathBuf>,
+    prescale: Arc<Mutex<Option<Prescale>>>,
+}
+
+/// Sending half of a `Pending` load
+#[cfg(feature = "async_loading")]
+#[derive(Debug)]
+pub(crate) struct Completer {
+    tx: mpsc::Sender<Completed>,
+    preview: mpsc::Sender<DynamicImage>,
+    cancelled: Arc<AtomicBool>,
+    prescale: Arc<Mutex<Option<Prescale>>>,
+    notify: Notify,
+}
+
+#[cfg(feature = "async_loading")]
+impl Completer {
+    /// Returns `true` once the `Pending` was dropped, the load should stop as early as possible
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Hands a low-resolution version of the image to the `Pending`, to show until it completes
+    pub fn preview(&self, image: DynamicImage) {
+        if self.is_cancelled() {
+            return;
+        }
+        let _ = self.preview.send(rgba8(image));
+        self.notify.notify();
+    }
+
+    /// Scales the result down if the `Pending` asked for it, converts it to RGBA,
+    /// computes the texture cache key, hands the result, the decodes and the warnings of this thread
+    /// to the `Pending` and calls `notify`
+    pub fn complete(self, result: ImageResult<Loaded>) {
+        // taken even if cancelled, so they are not counted for the next load on this thread
+        let decodes = stats::take_decodes();
+        let warnings = WARNINGS.with(|warnings| warnings.take());
+        if self.is_cancelled() {
+            return;
+        }
+        let prescale = *self.prescale.lock().unwrap();
+        let result = result.map(|loaded| {
+            let loaded = match prescale {
+                Some(prescale) => loaded.prescaled(&prescale),
+                None => loaded,
+            }
+            .into_rgba8();
+            let key = loaded.key();
+            (loaded, key)
+        });
+        let _ = self.tx.send(Completed {
+            result,
+            decodes,
+            warnings,
+        });
+        self.notify.notify();
+    }
+
+    /// Runs `job` on the shared decode threads, completing the load with the error if it was rejected
+    fn execute<F: FnOnce(Self) + Send + 'static>(self, job: F) {
+        let cancelled = self.cancelled.clone();
+        self.start(job, |job| crate::pool::execute(cancelled, job));
+    }
+
+    /// Runs `job` on a thread of its own, completing the load with the error if it could not be started
+    #[cfg(feature = "remote")]
+    fn spawn<F: FnOnce(Self) + Send + 'static>(self, job: F) {
+        self.start(job, |job| {
+            std::thread::Builder::new()
+                .name("wallpaper-download".into())
+                .spawn(job)
+                .map(|_| ())
+        });
+    }
+
+    /// Hands `job` to `start`, completing the load with the error of `start`
+    fn start<F, S>(self, job: F, start: S)
+    where
+        F: FnOnce(Self) + Send + 'static,
+        S: FnOnce(Box<dyn FnOnce() + Send>) -> std::io::Result<()>,
+    {
+        let tx = self.tx.clone();
+        let notify = self.notify.clone();
+        if let Err(err) = start(Box::new(move || job(self))) {
+            let _ = tx.send(Completed {
+                result: Err(err.into()),
+                decodes: stats::Decodes::default(),
+                warnings: Vec::new(),
+            });
+            notify.notify();
+        }
+    }
+}
+
+#[cfg(feature = "async_loading")]
+impl Pending {
+    /// Creates a load completed by whoever owns the returned `Completer`
+    pub fn new(notify: Notify) -> (Completer, Self) {
+        let (tx, result) = mpsc::channel();
+        let (preview_tx, preview) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let prescale = Arc::new(Mutex::new(None));
+        (
+            Completer {
+                tx,
+                preview: preview_tx,
+                cancelled: cancelled.clone(),
+                prescale: prescale.clone(),
+                notify,
+            },
+            Self {
+                result,
+                preview,
+                cancelled,
+                generation: 0,
+                path: None,
+                prescale,
+            },
+        )
+    }
+
+    /// Runs `load` on the shared decode threads
+    pub fn spawn<F>(load: F, notify: Notify) -> Self
+    where
+        F: FnOnce() -> ImageResult<Loaded> + Send + 'static,
+    {
+        let (completer, pending) = Self::new(notify);
+        completer.execute(move |completer| {
+            if !completer.is_cancelled() {
+                completer.complete(load());
+            }
+        });
+        pending
+    }
+
+    /// Runs `load` on a thread of its own instead of the decode threads,
+    /// for downloads that would hold them up for as long as they take
+    #[cfg(feature = "remote")]
+    pub fn spawn_download<F>(load: F, notify: Notify) -> Self
+    where
+        F: FnOnce() -> ImageResult<Loaded> + Send + 'static,
+    {
+        let (completer, pending) = Self::new(notify);
+        completer.spawn(move |completer| {
+            if !completer.is_cancelled() {
+                completer.complete(load());
+            }
+        });
+        pending
+    }
+
+    /// Decodes the file at `path` on the shared decode threads, handing out a preview of large JPEGs first
+    pub fn open(path: PathBuf, notify: Notify) -> Self {
+        let (completer, mut pending) = Self::new(notify);
+        pending.path = Some(path.clone());
+        completer.execute(move |completer| {
+            if completer.is_cancelled() {
+                return;
+            }
+            if let Ok(Some(image)) = preview(&path) {
+                completer.preview(image);
+            }
+            if !completer.is_cancelled() {
+                completer.complete(open(path));
+            }
+        });
+        pending
+    }
+
+    /// Tags the load as decoding the file at `path`, so it can be retried
+    #[cfg(feature = "async")]
+    pub fn with_path(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Tags the load with the `generation` of the state starting it
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Scales the result down to `prescale` once decoded, `None` keeps its size
+    pub fn with_prescale(self, prescale: Option<Prescale>) -> Self {
+        *self.prescale.lock().unwrap() = prescale;
+        self
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the preview of the image if one was decoded since the last call
+    pub fn poll_preview(&self) -> Option<DynamicImage> {
+        self.preview.try_recv().ok()
+    }
+
+    /// Returns the result once the load finished, `None` while it is still running
+    pub fn poll(&self) -> Option<Completed> {
+        match self.result.try_recv() {
+            Ok(completed) => Some(completed),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Completed {
+                result: Err(std::io::Error::new(std::io::ErrorKind::Other, "load panicked").into()),
+                decodes: stats::Decodes::default(),
+                warnings: Vec::new(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "async_loading")]
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Decodes the file at `path`, keeping all frames of animated GIF, APNG and WebP images
+pub(crate) fn open<P: AsRef<Path>>(path: P) -> ImageResult<Loaded> {
+    let started = Instant::now();
+    let loaded = read(path.as_ref());
+    stats::record_decode(started);
+    loaded
+}
+
+/// Decodes the file at `path` like [`open`], without counting it in the stats,
+/// for files read as part of another one
+pub(crate) fn read(path: &Path) -> ImageResult<Loaded> {
+    #[cfg(feature = "svg")]
+    if crate::svg::is_svg(path) {
+        return crate::svg::render(path, None, crate::ScalingMode::Center).map(Loaded::Image);
+    }
+    #[cfg(feature = "heif")]
+    if crate::heif::is_heif(path) {
+        return crate::heif::decode_file(path, None).map(Loaded::Image);
+    }
+    #[cfg(feature = "jxl")]
+    if crate::jxl::is_jxl(path) {
+        return crate::jxl::open(path);
+    }
+    if crate::layers::is_layers(path) {
+        return crate::layers::load(path).map(Loaded::Layers);
+    }
+
+    let reader = Reader::open(path)?.with_guessed_format()?;
+    let format = reader.format();
+    decode_with(reader.into_inner(), format)
+}
+
+/// Decodes a large JPEG at `path` at an eighth of its size, returns `None` for other images.
+///
+/// Scaling is done while decoding from the DCT coefficients, which is many times faster
+/// than decoding the full image.
+#[cfg(feature = "async_loading")]
+fn preview(path: &Path) -> ImageResult<Option<DynamicImage>> {
+    let reader = Reader::open(path)?.with_guessed_format()?;
+    if reader.format() != Some(ImageFormat::Jpeg) {
+        return Ok(None);
+    }
+    #[allow(unused_mut)]
+    let mut reader = reader.into_inner();
+    #[cfg(feature = "exif")]
+    let orientation = orientation(&mut reader)?;
+
+    let mut decoder = JpegDecoder::new(reader)?;
+    decode_limits().check(&decoder)?;
+    let (width, height) = decoder.dimensions();
+    if (width as u64) * (height as u64) < PREVIEW_MIN_PIXELS {
+        return Ok(None);
+    }
+    decoder.scale((width / 8) as u16, (height / 8) as u16)?;
+    let image = DynamicImage::from_decoder(decoder)?;
+    #[cfg(feature = "exif")]
+    let image = orient(image, orientation);
+    Ok(Some(image))
+}
+
+/// Decodes an encoded image from memory, like [`open`] does for files
+pub(crate) fn decode(data: &[u8]) -> ImageResult<Loaded> {
+    let started = Instant::now();
+    let loaded = decode_data(data);
+    stats::record_decode(started);
+    loaded
+}
+
+fn decode_data(data: &[u8]) -> ImageResult<Loaded> {
+    #[cfg(feature = "heif")]
+    if crate::heif::is_heif_data(data) {
+        return crate::heif::decode_bytes(data).map(Loaded::Image);
+    }
+    #[cfg(feature = "jxl")]
+    if crate::jxl::is_jxl_data(data) {
+        return crate::jxl::decode(data);
+    }
+    decode_with(Cursor::new(data), image::guess_format(data).ok())
+}
+
+fn decode_with<R: BufRead + Seek>(
+    #[allow(unused_mut)] mut reader: R,
+    format: Option<ImageFormat>,
+) -> ImageResult<Loaded> {
+    #[cfg(feature = "exif")]
+    let orientation = orientation(&mut reader)?;
+
+    let loaded = decode_format(reader, format)?;
+    #[cfg(feature = "exif")]
+    if let Loaded::Image(image) = loaded {
+        return Ok(Loaded::Image(orient(image, orientation)));
+    }
+    Ok(loaded)
+}
+
+/// Reads the EXIF orientation tag, `1` if there is none, and rewinds `reader`
+#[cfg(feature = "exif")]
+fn orientation<R: BufRead + Seek>(reader: &mut R) -> ImageResult<u32> {
+    let start = reader.stream_position()?;
+    let orientation = exif::Reader::new()
+        .read_from_container(reader)
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1);
+    reader.seek(std::io::SeekFrom::Start(start))?;
+    Ok(orientation)
+}
+
+/// Applies the EXIF `orientation` to `image`, so it is displayed upright
+#[cfg(feature = "exif")]
+fn orient(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn decode_format<R: BufRead + Seek>(reader: R, format: Option<ImageFormat>) -> ImageResult<Loaded> {
+    let limits = decode_limits();
+    match format {
+        Some(ImageFormat::Gif) => {
+            let decoder = GifDecoder::new(reader)?;
+            limits.check(&decoder)?;
+            animated(decoder, &limits)
+        }
+        Some(ImageFormat::Png) => {
+            let mut decoder = PngDecoder::new(reader)?;
+            limits.check(&decoder)?;
+            let icc = decoder.icc_profile();
+            let loaded = if decoder.is_apng() {
+                animated(decoder.apng(), &limits)?
+            } else {
+                Loaded::Image(DynamicImage::from_decoder(decoder)?)
+            };
+            to_srgb(loaded, icc, ImageFormat::Png)
+        }
+        Some(ImageFormat::Jpeg) => {
+            let mut decoder = JpegDecoder::new(reader)?;
+            limits.check(&decoder)?;
+            let icc = decoder.icc_profile();
+            let loaded = Loaded::Image(DynamicImage::from_decoder(decoder)?);
+            to_srgb(loaded, icc, ImageFormat::Jpeg)
+        }
+        Some(ImageFormat::WebP) => {
+            let mut decoder = WebPDecoder::new(reader)?;
+            limits.check(&decoder)?;
+            let icc = decoder.icc_profile();
+            let loaded = if decoder.has_animation() {
+                animated(decoder, &limits)?
+            } else {
+                Loaded::Image(DynamicImage::from_decoder(decoder)?)
+            };
+            to_srgb(loaded, icc, ImageFormat::WebP)
+        }
+        Some(format) => {
+            let mut reader = Reader::with_format(reader, format);
+            reader.limits(limits.limits());
+            reader.decode().map(Loaded::Image)
+        }
+        None => Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::Format(ImageFormatHint::Unknown),
+            ),
+        )),
+    }
+}
+
+/// Converts the colors of `loaded` from the embedded ICC profile `icc` to sRGB with the `icc` feature,
+/// otherwise images are shown as if they were sRGB.
+///
+/// Images of `format` with an invalid profile fail to decode.
+#[allow(unused_variables)]
+fn to_srgb(loaded: Loaded, icc: Option<Vec<u8>>, format: ImageFormat) -> ImageResult<Loaded> {
+    #[cfg(feature = "icc")]
+    if let Some(icc) = icc {
+        let invalid = |err| ImageError::Decoding(DecodingError::new(format.into(), err));
+        if let Some(profile) = crate::icc::parse(&icc).map_err(invalid)? {
+            return loaded
+                .try_map(|image| crate::icc::to_srgb(image, &profile))
+                .map_err(invalid);
+        }
+    }
+    Ok(loaded)
+}
+
+/// Returns how long a frame with `delay` is shown
+pub(crate) fn frame_delay(delay: Duration) -> Duration {
+    if delay < MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+/// Collects all frames of an animated image, failing once they exceed `limits`
+fn animated<'a, D: AnimationDecoder<'a>>(decoder: D, limits: &DecodeLimits) -> ImageResult<Loaded> {
+    let mut bytes = 0u64;
+    let mut frames = decoder
+        .into_frames()
+        .map(|frame| {
+            let frame = frame?;
+            let buffer = frame.buffer();
+            limits.check_dimensions(buffer.dimensions())?;
+            bytes = bytes.saturating_add(buffer.as_raw().len() as u64);
+            limits.check_bytes(bytes)?;
+            Ok(frame)
+        })
+        .collect::<ImageResult<Vec<_>>>()?
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_secs_f64(numer as f64 / denom.max(1) as f64 / 1000.0);
+            (
+                DynamicImage::ImageRgba8(frame.into_buffer()),
+                frame_delay(delay),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Ok(if frames.len() == 1 {
+        Loaded::Image(frames.remove(0).0)
+    } else {
+        Loaded::Animation(frames)
+    })
+}
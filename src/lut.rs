@@ -0,0 +1,222 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{CachedTexture, WallpaperError};
+
+/// Largest LUT size accepted, to keep the texture within common limits
+const MAX_SIZE: u32 = 128;
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// A 3D color lookup table, used to color-grade the wallpaper
+///
+/// The table is stored in a 2D texture of `size` slices of `size * size` pixels side by side,
+/// one slice per blue value.
+#[derive(Clone)]
+pub struct Lut {
+    size: u32,
+    domain: ([f32; 3], [f32; 3]),
+    image: Rc<DynamicImage>,
+    pub(crate) texture: Rc<RefCell<Option<CachedTexture>>>,
+}
+
+impl fmt::Debug for Lut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lut")
+            .field("size", &self.size)
+            .field("domain", &self.domain)
+            .finish()
+    }
+}
+
+impl PartialEq for Lut {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.image, &other.image)
+    }
+}
+
+impl Lut {
+    /// Reads the `.cube` file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, WallpaperError> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .and_then(|data| Self::parse(&data))
+            .map_err(|err| WallpaperError::Io(path.to_path_buf(), err))
+    }
+
+    /// Parses a 3D LUT in the `.cube` format
+    pub fn parse(data: &str) -> io::Result<Self> {
+        let mut size = None;
+        let mut domain = ([0.0; 3], [1.0; 3]);
+        let mut entries = Vec::new();
+
+        let triple = |values: &[&str]| -> io::Result<[f32; 3]> {
+            match values {
+                [r, g, b] => {
+                    let parse = |v: &str| v.parse::<f32>().map_err(invalid);
+                    Ok([parse(r)?, parse(g)?, parse(b)?])
+                }
+                _ => Err(invalid(format!("expected 3 values, got {}", values.len()))),
+            }
+        };
+        for line in data.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let values = words.collect::<Vec<_>>();
+            match keyword {
+                "LUT_3D_SIZE" => {
+                    let n = values
+                        .first()
+                        .and_then(|n| n.parse::<u32>().ok())
+                        .filter(|n| (2..=MAX_SIZE).contains(n))
+                        .ok_or_else(|| invalid("invalid LUT_3D_SIZE"))?;
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => return Err(invalid("1D LUTs are not supported")),
+                "DOMAIN_MIN" => domain.0 = triple(&values)?,
+                "DOMAIN_MAX" => domain.1 = triple(&values)?,
+                "TITLE" | "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {}
+                _ => {
+                    let mut all = vec![keyword];
+                    all.extend(values);
+                    entries.push(triple(&all)?);
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| invalid("missing LUT_3D_SIZE"))?;
+        if entries.len() != (size * size * size) as usize {
+            return Err(invalid(format!(
+                "expected {} entries, got {}",
+                size * size * size,
+                entries.len()
+            )));
+        }
+
+        // red changes fastest, then green, then blue
+        let image = RgbaImage::from_fn(size * size, size, |x, y| {
+            let (r, b, g) = (x % size, x / size, y);
+            let [r, g, b] = entries[(r + g * size + b * size * size) as usize];
+            let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            Rgba([channel(r), channel(g), channel(b), 255])
+        });
+        Ok(Self {
+            size,
+            domain,
+            image: Rc::new(DynamicImage::ImageRgba8(image)),
+            texture: Rc::new(RefCell::new(None)),
+        })
+    }
+
+    /// Number of entries along each axis
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Input values mapped to the first and last entries
+    pub(crate) fn domain(&self) -> ([f32; 3], [f32; 3]) {
+        self.domain
+    }
+
+    pub(crate) fn image(&self) -> &DynamicImage {
+        &self.image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &str = "\
+# identity
+TITLE \"identity\"
+LUT_3D_SIZE 2
+
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1 # white is next
+1 1 1
+";
+
+    fn pixel(lut: &Lut, x: u32, y: u32) -> [u8; 4] {
+        lut.image().as_rgba8().unwrap().get_pixel(x, y).0
+    }
+
+    #[test]
+    fn parses_an_identity_lut() {
+        let lut = Lut::parse(IDENTITY).unwrap();
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.domain(), ([0.0; 3], [1.0; 3]));
+        assert_eq!(lut.image().width(), 4);
+        assert_eq!(lut.image().height(), 2);
+    }
+
+    #[test]
+    fn slices_are_stored_side_by_side_by_blue() {
+        let lut = Lut::parse(IDENTITY).unwrap();
+        // x is red within a slice and the slice is blue, y is green
+        assert_eq!(pixel(&lut, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&lut, 1, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&lut, 0, 1), [0, 255, 0, 255]);
+        assert_eq!(pixel(&lut, 2, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&lut, 3, 1), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn reads_the_domain() {
+        let data = IDENTITY.replace(
+            "LUT_3D_SIZE 2",
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0.5\nDOMAIN_MAX 2 2 2",
+        );
+        let lut = Lut::parse(&data).unwrap();
+        assert_eq!(lut.domain(), ([0.0, 0.0, 0.5], [2.0; 3]));
+    }
+
+    #[test]
+    fn clamps_entries() {
+        let data = IDENTITY.replace("1 1 1\n", "1.5 -0.5 0.5\n");
+        let lut = Lut::parse(&data).unwrap();
+        assert_eq!(pixel(&lut, 3, 1), [255, 0, 128, 255]);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let invalid = |data: &str| Lut::parse(data).unwrap_err().kind();
+        assert_eq!(
+            invalid(&IDENTITY.replace("LUT_3D_SIZE 2\n", "")),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            invalid(&IDENTITY.replace("1 1 1\n", "")),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            invalid(&IDENTITY.replace("1 1 1\n", "1 1\n")),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            invalid(&IDENTITY.replace("1 1 1\n", "1 one 1\n")),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            invalid(&IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 256")),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(invalid("LUT_1D_SIZE 2"), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+#[cfg(any(feature = "async_loading", feature = "config"))]
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use smithay::{
+    utils::{Logical, Physical, Rectangle, Size},
+    wayland::output::Output,
+};
+
+#[cfg(feature = "config")]
+use crate::config::WallpaperConfig;
+#[cfg(any(feature = "async_loading", feature = "config"))]
+use crate::WallpaperError;
+use crate::{
+    ColorScheme, PowerSource, ScalingMode, WallpaperFrame, WallpaperState, WallpaperStats,
+};
+
+/// Keeps track of one `WallpaperState` per output
+///
+/// Outputs showing the same image at the same size share the decoded image and its texture.
+#[derive(Debug, Default)]
+pub struct WallpaperManager {
+    outputs: HashMap<String, ManagedOutput>,
+    default: Option<PathBuf>,
+    scaling: ScalingMode,
+    span: bool,
+    power_source: PowerSource,
+    #[cfg(feature = "config")]
+    config: WallpaperConfig,
+}
+
+#[derive(Debug)]
+struct ManagedOutput {
+    state: WallpaperState,
+    path: Option<PathBuf>,
+    size: Option<Size<i32, Physical>>,
+    leader: Option<String>,
+    loaded: Option<(PathBuf, Size<i32, Physical>)>,
+    geometry: Option<Rectangle<i32, Logical>>,
+}
+
+impl WallpaperManager {
+    /// Creates a new `WallpaperManager` without any outputs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a `WallpaperManager` from the TOML configuration file at `path`
+    #[cfg(feature = "config")]
+    pub fn from_config<P: AsRef<Path>>(path: P) -> Result<Self, WallpaperError> {
+        WallpaperConfig::load(path).map(Self::with_config)
+    }
+
+    /// Creates a `WallpaperManager` applying `config` to every output added
+    ///
+    /// A `location` in the config is set as the location provider of this thread.
+    #[cfg(feature = "config")]
+    pub fn with_config(config: WallpaperConfig) -> Self {
+        #[cfg(feature = "timeline")]
+        if let Some(location) = config.location {
+            crate::set_location_provider(location);
+        }
+        Self {
+            outputs: HashMap::new(),
+            default: config.default.path.clone(),
+            scaling: config.default.scaling.unwrap_or_default(),
+            span: false,
+            power_source: PowerSource::default(),
+            config,
+        }
+    }
+
+    /// Starts tracking `output`, using the default wallpaper if one was set
+    pub fn add_output(&mut self, output: &Output) {
+        let name = output.name();
+        if self.outputs.contains_key(&name) {
+            return;
+        }
+
+        let mut state = WallpaperState::new();
+        state.set_scaling_mode(self.scaling);
+        state.set_power_source(self.power_source);
+        #[allow(unused_mut)]
+        let mut path = self.default.clone();
+        #[cfg(feature = "config")]
+        {
+            let config = self.config.output(&name);
+            if let Some(scaling) = config.scaling {
+                state.set_scaling_mode(scaling);
+            }
+            if let Some(anchor) = config.anchor {
+                state.set_anchor(anchor);
+            }
+            if let Some(color) = config.color {
+                state.set_color(color);
+            }
+            if let Some(hash) = &config.blurhash {
+                if let Err(err) = state.set_blurhash(hash) {
+                    state.set_error(err);
+                }
+            }
+            if config.path.is_some() {
+                path = config.path.clone();
+            }
+            #[cfg(feature = "async_loading")]
+            if !config.playlist.is_empty() {
+                path = None;
+                let interval = config.interval();
+                if let Err(err) = state.set_playlist(config.playlist, interval) {
+                    state.set_error(err);
+                }
+            }
+        }
+        self.outputs.insert(
+            name.clone(),
+            ManagedOutput {
+                state,
+                path,
+                size: output.current_mode().map(|mode| mode.size),
+                leader: None,
+                loaded: None,
+                geometry: None,
+            },
+        );
+        self.load(&name);
+    }
+
+    /// Stops tracking `output` and drops its wallpaper
+    pub fn remove_output(&mut self, output: &Output) {
+        self.remove(&output.name());
+    }
+
+    /// Stops tracking the output called `name`, one of its followers takes over its wallpaper
+    fn remove(&mut self, name: &str) {
+        if self.outputs.remove(name).is_none() {
+            return;
+        }
+
+        let followers = self
+            .outputs
+            .iter()
+            .filter(|(_, managed)| managed.leader.as_deref() == Some(name))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for follower in followers {
+            self.load(&follower);
+        }
+    }
+
+    /// Sets the wallpaper of a single output
+    #[cfg(feature = "async_loading")]
+    pub fn set<P: AsRef<Path>>(&mut self, output: &Output, path: P) -> Result<(), WallpaperError> {
+        let path = path.as_ref();
+        std::fs::metadata(path).map_err(|err| WallpaperError::Io(path.to_path_buf(), err))?;
+        self.add_output(output);
+        self.set_named(&output.name(), path);
+        Ok(())
+    }
+
+    /// Sets the wallpaper of the tracked output called `name`, returns `false` if there is none
+    #[cfg(feature = "async_loading")]
+    fn set_named(&mut self, name: &str, path: &Path) -> bool {
+        match self.outputs.get_mut(name) {
+            Some(managed) => managed.path = Some(path.to_path_buf()),
+            None => return false,
+        }
+        self.reload(name);
+        true
+    }
+
+    /// Sets the wallpaper of all current outputs and uses it for outputs added later on
+    #[cfg(feature = "async_loading")]
+    pub fn set_all<P: AsRef<Path>>(&mut self, path: P) -> Result<(), WallpaperError> {
+        let path = path.as_ref().to_path_buf();
+        std::fs::metadata(&path).map_err(|err| WallpaperError::Io(path.clone(), err))?;
+        self.default = Some(path.clone());
+        for managed in self.outputs.values_mut() {
+            managed.path = Some(path.clone());
+        }
+
+        let names = self.outputs.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            self.load(&name);
+        }
+        Ok(())
+    }
+
+    /// Sets the `ScalingMode` of every output
+    pub fn set_scaling_mode(&mut self, mode: ScalingMode) {
+        self.scaling = mode;
+        for managed in self.outputs.values_mut() {
+            managed.state.set_scaling_mode(mode);
+        }
+    }
+
+    /// Spans each image across all outputs showing it, based on their position in the global space,
+    /// so a panorama continues across monitors
+    pub fn set_span(&mut self, span: bool) {
+        self.span = span;
+        if !span {
+            for managed in self.outputs.values_mut() {
+                managed.state.set_span(None);
+            }
+        }
+        let names = self.outputs.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            self.reload(&name);
+        }
+    }
+
+    /// Switches every output to the variant of `scheme`, see [`WallpaperState::set_variants`]
+    pub fn set_color_scheme(&mut self, scheme: ColorScheme) {
+        for managed in self.outputs.values_mut() {
+            managed.state.set_color_scheme(scheme);
+        }
+    }
+
+    /// Tells every output whether the system runs on battery, see [`WallpaperState::set_power_source`]
+    pub fn set_power_source(&mut self, source: PowerSource) {
+        self.power_source = source;
+        for managed in self.outputs.values_mut() {
+            managed.state.set_power_source(source);
+        }
+    }
+
+    /// Returns what decoding, uploading and drawing the wallpapers of the tracked outputs cost so far,
+    /// e.g. to show in a debug overlay, the sum of [`WallpaperState::stats`].
+    ///
+    /// Textures shared by several outputs, like the image of outputs showing the same wallpaper,
+    /// are counted once.
+    pub fn stats(&self) -> WallpaperStats {
+        let mut textures = Vec::new();
+        let mut stats = WallpaperStats::default();
+        for managed in self.outputs.values() {
+            let state = &managed.state;
+            // shared by followers and through the texture cache, so summed below
+            textures.extend(state.textures());
+            stats = stats.add(state.stats.snapshot(state.own_texture_bytes()));
+        }
+        stats.texture_bytes += crate::texture_bytes(&textures);
+        stats
+    }
+
+    /// Returns the `WallpaperState` of `output`, if it is tracked
+    pub fn state(&self, output: &Output) -> Option<&WallpaperState> {
+        self.outputs
+            .get(&output.name())
+            .map(|managed| &managed.state)
+    }
+
+    /// Returns the mutable `WallpaperState` of `output`, if it is tracked
+    pub fn state_mut(&mut self, output: &Output) -> Option<&mut WallpaperState> {
+        self.outputs
+            .get_mut(&output.name())
+            .map(|managed| &mut managed.state)
+    }
+
+    /// Executes a request received by an [`IpcSource`](crate::IpcSource)
+    #[cfg(feature = "ipc")]
+    pub fn handle_ipc(&mut self, request: crate::IpcRequest) -> crate::IpcReply {
+        use crate::IpcRequest;
+
+        match request {
+            IpcRequest::Set { output: None, path } => {
+                self.set_all(&path).map_err(|err| err.to_string())?;
+                Ok(vec![])
+            }
+            IpcRequest::Set {
+                output: Some(name),
+                path,
+            } => {
+                std::fs::metadata(&path)
+                    .map_err(|err| WallpaperError::Io(path.clone(), err).to_string())?;
+                if self.set_named(&name, &path) {
+                    Ok(vec![])
+                } else {
+                    Err(format!("unknown output {}", name))
+                }
+            }
+            IpcRequest::Next { output } => {
+                let mut skipped = false;
+                for (name, managed) in &mut self.outputs {
+                    if output.as_deref().map_or(true, |output| output == name) {
+                        skipped |= managed.state.next();
+                    }
+                }
+                if skipped {
+                    Ok(vec![])
+                } else {
+                    Err("no playlist running".into())
+                }
+            }
+            IpcRequest::Query => {
+                let mut lines = self
+                    .outputs
+                    .iter()
+                    .map(|(name, managed)| match &managed.path {
+                        Some(path) => format!("{} {}", name, path.display()),
+                        None => format!("{} -", name),
+                    })
+                    .collect::<Vec<_>>();
+                lines.sort();
+                Ok(lines)
+            }
+        }
+    }
+
+    /// Produce the frame to draw onto `output`.
+    ///
+    /// The output is added automatically if it is not tracked yet and drawn at its current scale and transform.
+    /// Returns `None` if the output has no mode set.
+    pub fn run(
+        &mut self,
+        output: &Output,
+        area: Rectangle<i32, Logical>,
+    ) -> Option<WallpaperFrame> {
+        let size = output.current_mode()?.size;
+        let name = output.name();
+        self.add_output(output);
+
+        if self.outputs.get(&name)?.size != Some(size) {
+            self.outputs.get_mut(&name)?.size = Some(size);
+            self.reload(&name);
+        }
+
+        self.follow(&name);
+
+        let scale = output.current_scale() as f64;
+        let transform = output.current_transform();
+
+        if self.span {
+            let geometry = Rectangle::from_loc_and_size(
+                output.current_location(),
+                transform
+                    .transform_size(size)
+                    .to_logical(output.current_scale()),
+            );
+            self.outputs.get_mut(&name)?.geometry = Some(geometry);
+            let path = self.outputs.get(&name)?.path.clone();
+            // the bounding box of all outputs showing the same image
+            let bounds = self
+                .outputs
+                .values()
+                .filter(|managed| managed.path == path)
+                .filter_map(|managed| managed.geometry)
+                .reduce(|a, b| a.merge(b))?;
+            self.outputs
+                .get_mut(&name)?
+                .state
+                .set_span(Some(Rectangle::from_loc_and_size(
+                    bounds.loc - geometry.loc,
+                    bounds.size,
+                )));
+        }
+
+        Some(
+            self.outputs
+                .get_mut(&name)?
+                .state
+                .run(area, size, scale, transform),
+        )
+    }
+
+    /// Shows the image and texture of the leader of `name` on it, if it shares the wallpaper of another output
+    fn follow(&mut self, name: &str) -> Option<()> {
+        let leader = self.outputs.get(name)?.leader.clone()?;
+        let (image, texture, frame, fading) = {
+            let leader = &mut self.outputs.get_mut(&leader)?.state;
+            #[cfg(feature = "async_loading")]
+            leader.check();
+            (
+                leader.image.clone(),
+                leader.texture.clone(),
+                leader.frame,
+                leader.fading.clone(),
+            )
+        };
+        let state = &mut self.outputs.get_mut(name)?.state;
+        if !Rc::ptr_eq(&state.image, &image) {
+            state.dirty = true;
+        }
+        state.image = image;
+        state.texture = texture;
+        state.frame = frame;
+        state.fading = fading;
+        Some(())
+    }
+
+    /// Reloads `name` and every output sharing its wallpaper
+    fn reload(&mut self, name: &str) {
+        if let Some(managed) = self.outputs.get_mut(name) {
+            managed.loaded = None;
+        }
+        let followers = self
+            .outputs
+            .iter()
+            .filter(|(_, managed)| managed.leader.as_deref() == Some(name))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        self.load(name);
+        for follower in followers {
+            self.load(&follower);
+        }
+    }
+
+    /// Starts loading the wallpaper of `name`, or shares it with an output already showing the same image
+    fn load(&mut self, name: &str) {
+        let target = match self.outputs.get(name) {
+            // spanned images are the same on every output, regardless of its size
+            Some(ManagedOutput {
+                path: Some(path), ..
+            }) if self.span => (path.clone(), Size::default()),
+            Some(ManagedOutput {
+                path: Some(path),
+                size: Some(size),
+                ..
+            }) => (path.clone(), *size),
+            _ => return,
+        };
+
+        let leader = self
+            .outputs
+            .iter()
+            .find(|(other, managed)| *other != name && managed.loaded.as_ref() == Some(&target))
+            .map(|(other, _)| other.clone());
+
+        let managed = self.outputs.get_mut(name).unwrap();
+        if leader.is_none() && managed.loaded.as_ref() == Some(&target) {
+            return;
+        }
+        managed.leader = leader;
+        if managed.leader.is_some() {
+            managed.loaded = None;
+        } else {
+            #[cfg(feature = "async_loading")]
+            if let Err(err) = managed.state.set(&target.0) {
+                managed.state.set_error(err);
+            }
+            managed.loaded = Some(target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adds outputs called `names` of `size`, all showing the same missing file
+    fn add(manager: &mut WallpaperManager, names: &[&str], size: (i32, i32)) {
+        for name in names {
+            manager.outputs.insert(
+                name.to_string(),
+                ManagedOutput {
+                    state: WallpaperState::new(),
+                    path: Some(PathBuf::from("/nonexistent/wallpaper.png")),
+                    size: Some(size.into()),
+                    leader: None,
+                    loaded: None,
+                    geometry: None,
+                },
+            );
+            manager.load(name);
+        }
+    }
+
+    fn leader<'a>(manager: &'a WallpaperManager, name: &str) -> Option<&'a str> {
+        manager.outputs[name].leader.as_deref()
+    }
+
+    #[test]
+    fn followers_share_the_texture_of_the_leader() {
+        let mut manager = WallpaperManager::new();
+        add(&mut manager, &["DP-1", "DP-2"], (1920, 1080));
+        add(&mut manager, &["HDMI-1"], (1280, 720));
+        assert_eq!(leader(&manager, "DP-1"), None);
+        assert_eq!(leader(&manager, "DP-2"), Some("DP-1"));
+        // the same image at another size is loaded again
+        assert_eq!(leader(&manager, "HDMI-1"), None);
+
+        let texture =
+            |manager: &WallpaperManager, name: &str| manager.outputs[name].state.texture.clone();
+        assert!(!Rc::ptr_eq(
+            &texture(&manager, "DP-1"),
+            &texture(&manager, "DP-2")
+        ));
+        manager.follow("DP-2");
+        manager.follow("HDMI-1");
+        assert!(Rc::ptr_eq(
+            &texture(&manager, "DP-1"),
+            &texture(&manager, "DP-2")
+        ));
+        assert!(Rc::ptr_eq(
+            &manager.outputs["DP-1"].state.image,
+            &manager.outputs["DP-2"].state.image
+        ));
+        assert!(!Rc::ptr_eq(
+            &texture(&manager, "DP-1"),
+            &texture(&manager, "HDMI-1")
+        ));
+    }
+
+    #[test]
+    fn removing_the_leader_elects_a_follower() {
+        let mut manager = WallpaperManager::new();
+        add(&mut manager, &["DP-1", "DP-2", "DP-3"], (1920, 1080));
+        assert_eq!(leader(&manager, "DP-2"), Some("DP-1"));
+        assert_eq!(leader(&manager, "DP-3"), Some("DP-1"));
+
+        manager.remove("DP-1");
+        let (new, other) = match leader(&manager, "DP-2") {
+            None => ("DP-2", "DP-3"),
+            Some(_) => ("DP-3", "DP-2"),
+        };
+        assert_eq!(leader(&manager, new), None);
+        assert!(manager.outputs[new].loaded.is_some());
+        assert_eq!(leader(&manager, other), Some(new));
+
+        manager.follow(other);
+        assert!(Rc::ptr_eq(
+            &manager.outputs[new].state.texture,
+            &manager.outputs[other].state.texture
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+use smithay::utils::{Buffer, Physical, Point, Rectangle};
+
+use crate::scaling;
+
+/// How the progress of an animation is spread over its duration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed
+    Linear,
+    /// Starts and ends slowly, smoothing the turn at either end
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(&self, progress: f64) -> f64 {
+        match self {
+            Easing::Linear => progress,
+            Easing::EaseInOut => progress * progress * (3.0 - 2.0 * progress),
+        }
+    }
+}
+
+/// A slow pan and zoom across the image, the Ken Burns effect
+///
+/// The view moves from the start to the end over `duration` and back again, for as long as it is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KenBurns {
+    /// Time to move from the start to the end
+    pub duration: Duration,
+    /// Zoom at the start and the end, `1.0` shows the image as laid out by the [`ScalingMode`](crate::ScalingMode)
+    pub zoom: (f64, f64),
+    /// Point staying in place while zooming at the start and the end, relative to the area.
+    /// `(0.0, 0.0)` is the top-left and `(1.0, 1.0)` the bottom-right corner.
+    pub focus: ((f64, f64), (f64, f64)),
+    pub easing: Easing,
+}
+
+impl Default for KenBurns {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(30),
+            zoom: (1.0, 1.15),
+            focus: ((0.35, 0.4), (0.65, 0.6)),
+            easing: Easing::EaseInOut,
+        }
+    }
+}
+
+impl KenBurns {
+    /// Returns the view `elapsed` after the motion started
+    pub(crate) fn view(&self, elapsed: Duration) -> View {
+        let progress = if self.duration.is_zero() {
+            0.0
+        } else {
+            // back and forth, so the motion never jumps
+            let cycle = (elapsed.as_secs_f64() / self.duration.as_secs_f64()) % 2.0;
+            self.easing
+                .apply(if cycle <= 1.0 { cycle } else { 2.0 - cycle })
+        };
+        let mix = |a: f64, b: f64| a + (b - a) * progress;
+        let (from, to) = self.focus;
+        View {
+            zoom: mix(self.zoom.0, self.zoom.1).max(f64::EPSILON),
+            focus: (mix(from.0, to.0), mix(from.1, to.1)),
+        }
+    }
+}
+
+/// A running Ken Burns motion
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Motion {
+    pub ken_burns: KenBurns,
+    started: Instant,
+}
+
+impl Motion {
+    pub fn new(ken_burns: KenBurns, now: Instant) -> Self {
+        Self {
+            ken_burns,
+            started: now,
+        }
+    }
+
+    /// Returns the view at `now`
+    pub fn view(&self, now: Instant) -> View {
+        self.ken_burns
+            .view(now.saturating_duration_since(self.started))
+    }
+}
+
+/// Zoom around a point of the area at one moment of the motion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct View {
+    zoom: f64,
+    focus: (f64, f64),
+}
+
+impl View {
+    /// Zooms in by `strength` towards `pointer`, relative to the area like the focus.
+    ///
+    /// The image moves against the pointer, showing more of the side it moves to.
+    pub fn parallax(strength: f64, pointer: (f64, f64)) -> Self {
+        Self {
+            zoom: 1.0 + strength,
+            focus: (pointer.0.clamp(0.0, 1.0), pointer.1.clamp(0.0, 1.0)),
+        }
+    }
+
+    /// Zooms the `(src, dst)` pairs of [`scaling::layout`] around the focus and clips them to `area`
+    pub fn apply(
+        &self,
+        pairs: Vec<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)>,
+        area: Rectangle<i32, Physical>,
+    ) -> Vec<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)> {
+        let area_f = area.to_f64();
+        let focus = Point::<f64, Physical>::from((
+            area_f.loc.x + area_f.size.w * self.focus.0,
+            area_f.loc.y + area_f.size.h * self.focus.1,
+        ));
+        let zoomed = pairs
+            .into_iter()
+            .map(|(src, dst)| {
+                let loc = Point::<f64, Physical>::from((
+                    focus.x + (dst.loc.x - focus.x) * self.zoom,
+                    focus.y + (dst.loc.y - focus.y) * self.zoom,
+                ));
+                let size = (dst.size.w * self.zoom, dst.size.h * self.zoom);
+                (src, Rectangle::from_loc_and_size(loc, size))
+            })
+            .collect();
+        scaling::clip(zoomed, area)
+    }
+}
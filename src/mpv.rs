@@ -0,0 +1,372 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use libmpv::render::{OpenGLInitParams, RenderContext, RenderParam, RenderParamApiType};
+use libmpv::{FileState, Mpv};
+use smithay::{
+    backend::{
+        egl,
+        renderer::{
+            gles2::{ffi, Gles2Renderer, Gles2Texture},
+            Renderer,
+        },
+    },
+    utils::{Buffer, Size},
+};
+
+use crate::{context, tools, CachedTexture, WallpaperError};
+
+/// Playback options of videos played with mpv
+#[derive(Debug, Clone, PartialEq)]
+pub struct MpvOptions {
+    /// Restarts the video at its end
+    pub looping: bool,
+    /// Playback speed, `1.0` is normal
+    pub speed: f64,
+    /// Hardware decoding API passed to mpv's `hwdec` option, e.g. `"auto-safe"` or `"no"`
+    pub hwdec: String,
+}
+
+impl Default for MpvOptions {
+    /// Loops at normal speed, decoding on the GPU where mpv considers it safe
+    fn default() -> Self {
+        Self {
+            looping: true,
+            speed: 1.0,
+            hwdec: String::from("auto-safe"),
+        }
+    }
+}
+
+/// An mpv instance and its render context, which has to be freed first
+struct Session {
+    render: Option<RenderContext>,
+    /// EGL context the render context was created in, mpv renders with only one
+    context: usize,
+    /// Framebuffer rendering into the texture of the wallpaper
+    fbo: ffi::types::GLuint,
+    /// Creating the render context failed, it is not tried again
+    failed: bool,
+    mpv: Mpv,
+}
+
+impl Session {
+    /// Starts playing `path`, the render context is created on the first draw
+    fn open(path: &Path) -> Result<Self, String> {
+        let mpv = Mpv::with_initializer(|init| {
+            init.set_property("vo", "libmpv")?;
+            // wallpapers are silent
+            init.set_property("aid", "no")?;
+            Ok(())
+        })
+        .map_err(|err| format!("{:?}", err))?;
+        let path = path.to_str().ok_or("path is not valid UTF-8")?;
+        mpv.playlist_load_files(&[(path, FileState::Replace, None)])
+            .map_err(|err| format!("{:?}", err))?;
+        Ok(Self {
+            render: None,
+            context: 0,
+            fbo: 0,
+            failed: false,
+            mpv,
+        })
+    }
+}
+
+thread_local! {
+    /// Sessions with a render context dropped without the GL context current,
+    /// freed on the next draw
+    static RETIRED: RefCell<Vec<Session>> = RefCell::new(Vec::new());
+}
+
+fn get_proc_address(_: &(), name: &str) -> *mut c_void {
+    egl::get_proc_address(name) as *mut c_void
+}
+
+/// A video played by mpv, rendered into a texture with its OpenGL render API
+pub(crate) struct Player {
+    path: PathBuf,
+    options: RefCell<MpvOptions>,
+    paused: Cell<bool>,
+    // only empty once dropped, or if mpv failed to start again after the context was lost
+    session: RefCell<Option<Session>>,
+    /// Set by mpv when a new frame is ready
+    update: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for Player {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Player")
+            .field("update", &self.update)
+            .finish()
+    }
+}
+
+impl Player {
+    pub fn new(path: &Path, options: &MpvOptions) -> Result<Self, WallpaperError> {
+        let session = Session::open(path).map_err(|err| error(path, err))?;
+        let player = Self {
+            path: path.to_path_buf(),
+            options: RefCell::new(options.clone()),
+            paused: Cell::new(false),
+            session: RefCell::new(Some(session)),
+            update: Arc::new(AtomicBool::new(true)),
+        };
+        player.set_options(options)?;
+        Ok(player)
+    }
+
+    /// Applies `options` to the playing video
+    pub fn set_options(&self, options: &MpvOptions) -> Result<(), WallpaperError> {
+        *self.options.borrow_mut() = options.clone();
+        let session = self.session.borrow();
+        let mpv = match &*session {
+            Some(session) => &session.mpv,
+            None => return Ok(()),
+        };
+        mpv.set_property("loop-file", if options.looping { "inf" } else { "no" })
+            .and_then(|_| mpv.set_property("speed", options.speed))
+            .and_then(|_| mpv.set_property("hwdec", options.hwdec.as_str()))
+            .map_err(|err| error(&self.path, format!("{:?}", err)))
+    }
+
+    /// Pauses or resumes playback
+    pub fn set_paused(&self, paused: bool) -> Result<(), WallpaperError> {
+        self.paused.set(paused);
+        match &*self.session.borrow() {
+            Some(session) => session
+                .mpv
+                .set_property("pause", paused)
+                .map_err(|err| error(&self.path, format!("{:?}", err))),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `true` if mpv has a frame that was not rendered yet
+    pub fn updated(&self) -> bool {
+        self.update.load(Ordering::Acquire)
+    }
+
+    /// Renders the current frame of the video into `texture`, if mpv has a new one
+    ///
+    /// Only the `Gles2Renderer` is supported, for other renderers `texture` is left alone.
+    /// With several GPUs, the video is only rendered by the renderer that drew it first.
+    /// If mpv fails to create its render context the error is returned once and nothing is drawn.
+    pub fn render<R>(
+        &self,
+        r: &mut R,
+        texture: &RefCell<Option<CachedTexture>>,
+    ) -> Result<(), WallpaperError>
+    where
+        R: Renderer + 'static,
+    {
+        let r = match (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        free_retired(r);
+
+        let context = context::id(&*r);
+        let mut session = self.session.borrow_mut();
+        let session = match session.as_mut() {
+            Some(session)
+                if !session.failed && (session.render.is_none() || session.context == context) =>
+            {
+                session
+            }
+            _ => return Ok(()),
+        };
+        let size: Size<i32, Buffer> = match (
+            session.mpv.get_property::<i64>("dwidth"),
+            session.mpv.get_property::<i64>("dheight"),
+        ) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => Size::from((w as i32, h as i32)),
+            // nothing decoded yet
+            _ => return Ok(()),
+        };
+        let mut cached_texture = texture.borrow_mut();
+        let reusable = match &*cached_texture {
+            Some(cached) => cached.is::<Gles2Texture>() && cached.size == size,
+            None => false,
+        };
+        if reusable && !self.update.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let update = self.update.clone();
+        let path = &self.path;
+        r.with_context(|renderer, gl| unsafe {
+            if session.render.is_none() {
+                let params = vec![
+                    RenderParam::ApiType(RenderParamApiType::OpenGl),
+                    RenderParam::InitParams(OpenGLInitParams {
+                        get_proc_address,
+                        ctx: (),
+                    }),
+                ];
+                match RenderContext::new(&mut *session.mpv.ctx.as_ptr(), params) {
+                    Ok(mut render) => {
+                        render.set_update_callback(move || update.store(true, Ordering::Release));
+                        session.render = Some(render);
+                        session.context = context;
+                    }
+                    Err(err) => {
+                        session.failed = true;
+                        return Err(error(path, format!("{:?}", err)));
+                    }
+                }
+            }
+
+            // mpv renders in the middle of a frame, so restore what it changes afterwards
+            let mut framebuffer = 0;
+            gl.GetIntegerv(ffi::FRAMEBUFFER_BINDING, &mut framebuffer);
+            let mut viewport = [0; 4];
+            gl.GetIntegerv(ffi::VIEWPORT, viewport.as_mut_ptr());
+            let blend = gl.IsEnabled(ffi::BLEND) == ffi::TRUE;
+            let scissor = gl.IsEnabled(ffi::SCISSOR_TEST) == ffi::TRUE;
+
+            if !reusable {
+                let mut tex = 0;
+                gl.GenTextures(1, &mut tex);
+                gl.BindTexture(ffi::TEXTURE_2D, tex);
+                gl.TexImage2D(
+                    ffi::TEXTURE_2D,
+                    0,
+                    ffi::RGBA as i32,
+                    size.w,
+                    size.h,
+                    0,
+                    ffi::RGBA,
+                    ffi::UNSIGNED_BYTE,
+                    ptr::null(),
+                );
+                gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+                gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+                if session.fbo == 0 {
+                    gl.GenFramebuffers(1, &mut session.fbo);
+                }
+                gl.BindFramebuffer(ffi::FRAMEBUFFER, session.fbo);
+                gl.FramebufferTexture2D(
+                    ffi::FRAMEBUFFER,
+                    ffi::COLOR_ATTACHMENT0,
+                    ffi::TEXTURE_2D,
+                    tex,
+                    0,
+                );
+                *cached_texture = Some(CachedTexture {
+                    texture: Box::new(Gles2Texture::from_raw(renderer, tex, size)),
+                    frame: 0,
+                    size,
+                    mipmaps: false,
+                    hdr: false,
+                    srgb: false,
+                    linear: false,
+                    external: false,
+                    compressed: false,
+                    context,
+                    bytes: tools::texture_bytes(size, 4, false),
+                });
+            }
+
+            // rows from the top, like the textures of images
+            let rendered = match &session.render {
+                Some(render) => render
+                    .render::<()>(session.fbo as i32, size.w, size.h, false)
+                    .map_err(|err| error(path, format!("{:?}", err))),
+                None => Ok(()),
+            };
+
+            gl.BindFramebuffer(ffi::FRAMEBUFFER, framebuffer as u32);
+            gl.Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            if blend {
+                gl.Enable(ffi::BLEND);
+            } else {
+                gl.Disable(ffi::BLEND);
+            }
+            if scissor {
+                gl.Enable(ffi::SCISSOR_TEST);
+            } else {
+                gl.Disable(ffi::SCISSOR_TEST);
+            }
+            gl.BindTexture(ffi::TEXTURE_2D, 0);
+            gl.UseProgram(0);
+            rendered
+        })
+        .map_err(|err| WallpaperError::Render(Box::new(err)))?
+    }
+
+    /// Frees the render context and framebuffer, they are created again on the next draw
+    pub fn destroy(&self, r: &mut Gles2Renderer) {
+        free_retired(r);
+        let context = context::id(&*r);
+        if let Some(session) = self
+            .session
+            .borrow_mut()
+            .as_mut()
+            .filter(|session| session.context == context)
+        {
+            let _ = r.with_context(|_renderer, gl| unsafe {
+                session.render = None;
+                if session.fbo != 0 {
+                    gl.DeleteFramebuffers(1, &session.fbo);
+                    session.fbo = 0;
+                }
+            });
+        }
+    }
+
+    /// Starts the video over with a new render context, after the GL context was lost.
+    ///
+    /// The old render context cannot be freed without its GL context,
+    /// and mpv must not be destroyed before it, so both are leaked.
+    /// If mpv fails to start again the video stays blank.
+    pub fn forget(&self) -> Result<(), WallpaperError> {
+        if let Some(session) = self.session.borrow_mut().take() {
+            std::mem::forget(session);
+        }
+        let session = Session::open(&self.path).map_err(|err| error(&self.path, err))?;
+        *self.session.borrow_mut() = Some(session);
+        self.update.store(true, Ordering::Release);
+        let options = self.options.borrow().clone();
+        self.set_options(&options)?;
+        self.set_paused(self.paused.get())
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        // mpv frees its GL resources with the render context, which needs the GL context
+        if let Some(session) = self.session.borrow_mut().take() {
+            if session.render.is_some() {
+                RETIRED.with(|retired| retired.borrow_mut().push(session));
+            }
+        }
+    }
+}
+
+/// Wraps `err` of playing the video at `path`
+fn error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(path: &Path, err: E) -> WallpaperError {
+    WallpaperError::Video(path.to_path_buf(), err.into())
+}
+
+/// Frees the sessions of players dropped since the last draw
+fn free_retired(r: &mut Gles2Renderer) {
+    let retired = RETIRED.with(|retired| std::mem::take(&mut *retired.borrow_mut()));
+    if retired.is_empty() {
+        return;
+    }
+    let _ = r.with_context(|_renderer, gl| unsafe {
+        for session in retired {
+            if session.fbo != 0 {
+                gl.DeleteFramebuffers(1, &session.fbo);
+            }
+            drop(session);
+        }
+    });
+}
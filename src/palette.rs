@@ -0,0 +1,233 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use image::{DynamicImage, GenericImageView};
+
+/// Number of colors in a palette
+const COLORS: usize = 8;
+/// Pixels sampled along each axis, enough for the dominant colors of any image
+const SAMPLES: u32 = 64;
+/// Rows or columns of samples averaged for the color of an edge, an eighth of the image
+const STRIP: usize = SAMPLES as usize / 8;
+
+/// An edge of the wallpaper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// Colors computed from an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Analysis {
+    /// Dominant colors, the most common first
+    pub palette: Vec<[f32; 4]>,
+    pub average: Option<[f32; 4]>,
+    /// Average colors of the strips along the edges, in the order of [`Edge`]
+    pub edges: [Option<[f32; 4]>; 4],
+}
+
+impl Analysis {
+    fn new(rows: Vec<Vec<[u8; 3]>>) -> Self {
+        let columns = rows.first().map_or(0, |row| row.len());
+        Self {
+            average: average(&mut rows.iter().flatten()),
+            edges: [
+                average(&mut rows.iter().take(STRIP).flatten()),
+                average(&mut rows.iter().rev().take(STRIP).flatten()),
+                average(&mut rows.iter().flat_map(|row| row.iter().take(STRIP))),
+                average(
+                    &mut rows
+                        .iter()
+                        .flat_map(|row| row.iter().skip(columns.saturating_sub(STRIP))),
+                ),
+            ],
+            palette: median_cut(rows.into_iter().flatten().collect(), COLORS),
+        }
+    }
+
+    pub fn edge(&self, edge: Edge) -> Option<[f32; 4]> {
+        self.edges[edge as usize]
+    }
+}
+
+/// Returns the average of `pixels` in RGBA, `None` if there are none
+fn average(pixels: &mut dyn Iterator<Item = &[u8; 3]>) -> Option<[f32; 4]> {
+    let (sum, count) = pixels.fold(([0u32; 3], 0), |(sum, count), pixel| {
+        ([0, 1, 2].map(|c| sum[c] + pixel[c] as u32), count + 1)
+    });
+    if count == 0 {
+        return None;
+    }
+    let [r, g, b] = sum.map(|c| c as f32 / count as f32 / 255.0);
+    Some([r, g, b, 1.0])
+}
+
+/// Called with the new palette whenever it changed
+pub(crate) struct Callback(pub Option<Box<dyn FnMut(&[[f32; 4]])>>);
+
+impl Default for Callback {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl fmt::Debug for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Callback").field(&self.0.is_some()).finish()
+    }
+}
+
+/// Picks rows of pixels of `image` on an even grid, skipping transparent ones.
+///
+/// This is cheap enough for the compositor thread, unlike looking at every pixel of a large image.
+pub(crate) fn sample(image: &DynamicImage) -> Vec<Vec<[u8; 3]>> {
+    let (width, height) = image.dimensions();
+    let (columns, rows) = (SAMPLES.min(width), SAMPLES.min(height));
+    (0..rows)
+        .map(|j| {
+            let y = (j * 2 + 1) * height / (rows * 2);
+            (0..columns)
+                .map(|i| image.get_pixel((i * 2 + 1) * width / (columns * 2), y).0)
+                .filter(|[_, _, _, a]| *a >= 128)
+                .map(|[r, g, b, _]| [r, g, b])
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the channel with the largest range of values in `pixels` and that range
+fn widest(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, 0), |(min, max), pixel| {
+                (min.min(pixel[channel]), max.max(pixel[channel]))
+            });
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+/// Reduces `pixels` to at most `count` colors by median cut, the most common first
+pub(crate) fn median_cut(pixels: Vec<[u8; 3]>, count: usize) -> Vec<[f32; 4]> {
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        // split the box spanning the widest range of a channel at its median
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, pixels)| pixels.len() > 1)
+            .map(|(index, pixels)| {
+                let (channel, range) = widest(pixels);
+                (index, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap_or((0, 0, 0));
+        if range == 0 {
+            break;
+        }
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|pixels| Reverse(pixels.len()));
+    boxes
+        .iter()
+        .filter_map(|pixels| average(&mut pixels.iter()))
+        .collect()
+}
+
+/// Colors computed on a separate thread
+#[derive(Debug)]
+pub(crate) struct Extraction(Receiver<Analysis>);
+
+impl Extraction {
+    /// Analyzes the colors of `image` on a new thread and calls `wake` once it is done
+    pub fn spawn<F: FnOnce() + Send + 'static>(image: &DynamicImage, wake: F) -> Self {
+        let rows = sample(image);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(Analysis::new(rows));
+            wake();
+        });
+        Self(rx)
+    }
+
+    /// Returns the colors once they are computed
+    pub fn poll(&self) -> Option<Analysis> {
+        self.0.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+    fn image<F: Fn(u32, u32) -> [u8; 4]>(width: u32, height: u32, pixel: F) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| Rgba(pixel(x, y))))
+    }
+
+    #[test]
+    fn samples_an_even_grid() {
+        let rows = sample(&image(256, 128, |_, _| [1, 2, 3, 255]));
+        assert_eq!(rows.len(), SAMPLES as usize);
+        assert!(rows.iter().all(|row| row.len() == SAMPLES as usize));
+        assert_eq!(rows[0][0], [1, 2, 3]);
+
+        // small images are sampled at every pixel
+        let rows = sample(&image(3, 2, |_, _| [0, 0, 0, 255]));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), 3);
+    }
+
+    #[test]
+    fn skips_transparent_pixels() {
+        let rows = sample(&image(4, 4, |x, _| {
+            [255, 0, 0, if x < 2 { 0 } else { 255 }]
+        }));
+        assert!(rows.iter().all(|row| row == &vec![[255, 0, 0]; 2]));
+        let analysis = Analysis::new(sample(&image(4, 4, |_, _| [255, 0, 0, 0])));
+        assert_eq!(analysis, Analysis::default());
+    }
+
+    #[test]
+    fn most_common_color_first() {
+        let pixels = vec![[0, 0, 255], [255, 0, 0], [255, 0, 0], [255, 0, 0]];
+        // the red pixels end up in two boxes, the larger one first
+        assert_eq!(median_cut(pixels, 8), vec![RED, RED, BLUE]);
+        assert_eq!(median_cut(vec![[255, 0, 0]; 10], 8), vec![RED]);
+        assert!(median_cut(Vec::new(), 8).is_empty());
+    }
+
+    #[test]
+    fn averages_edges() {
+        let analysis = Analysis::new(sample(&image(64, 64, |_, y| {
+            if y < 32 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            }
+        })));
+        assert_eq!(analysis.edge(Edge::Top), Some(RED));
+        assert_eq!(analysis.edge(Edge::Bottom), Some(BLUE));
+        assert_eq!(analysis.edge(Edge::Left), Some([0.5, 0.0, 0.5, 1.0]));
+        assert_eq!(analysis.edge(Edge::Right), Some([0.5, 0.0, 0.5, 1.0]));
+        assert_eq!(analysis.average, Some([0.5, 0.0, 0.5, 1.0]));
+        assert_eq!(analysis.palette, vec![RED, BLUE]);
+    }
+}
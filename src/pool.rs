@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Most threads decoding at once, more only compete for memory bandwidth
+const MAX_WORKERS: usize = 4;
+/// Jobs waiting for a worker, cancelled ones are dropped to make room for more
+const QUEUE: usize = 32;
+
+struct Job {
+    run: Box<dyn FnOnce() + Send>,
+    /// Set once nobody waits for the job anymore
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Job {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: Mutex<VecDeque<Job>>,
+    ready: Condvar,
+    workers: AtomicUsize,
+    /// Why the last thread failed to start
+    error: Mutex<Option<(io::ErrorKind, String)>>,
+}
+
+lazy_static::lazy_static! {
+    static ref POOL: Arc<Queue> = start();
+}
+
+fn start() -> Arc<Queue> {
+    let workers = thread::available_parallelism()
+        .map_or(2, |n| n.get())
+        .min(MAX_WORKERS);
+    let queue = Arc::new(Queue::default());
+    for i in 0..workers {
+        let worker = queue.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("wallpaper-decode-{}", i))
+            .spawn(move || work(&worker));
+        match spawned {
+            Ok(_) => {
+                queue.workers.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => *queue.error.lock().unwrap() = Some((err.kind(), err.to_string())),
+        }
+    }
+    queue
+}
+
+fn work(queue: &Queue) {
+    loop {
+        let job = {
+            let mut jobs = queue.jobs.lock().unwrap();
+            loop {
+                match jobs.pop_front() {
+                    Some(job) => break job,
+                    None => jobs = queue.ready.wait(jobs).unwrap(),
+                }
+            }
+        };
+        if job.is_cancelled() {
+            continue;
+        }
+        // a panicking decoder must not take the worker down with it
+        let _ = panic::catch_unwind(AssertUnwindSafe(job.run));
+    }
+}
+
+/// Runs `job` on the decode threads shared by all `WallpaperState`s, unless `cancelled` is set
+/// before a thread picks it up.
+///
+/// Never blocks the caller. If the queue is full the cancelled jobs are dropped to make room,
+/// if all of them are still waited for the job is rejected with [`io::ErrorKind::WouldBlock`].
+/// If no thread could be started the error starting them is returned.
+pub(crate) fn execute<F: FnOnce() + Send + 'static>(
+    cancelled: Arc<AtomicBool>,
+    job: F,
+) -> io::Result<()> {
+    if POOL.workers.load(Ordering::Relaxed) == 0 {
+        let (kind, message) = POOL
+            .error
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or((io::ErrorKind::Other, "no decode thread".into()));
+        return Err(io::Error::new(kind, message));
+    }
+    let mut jobs = POOL.jobs.lock().unwrap();
+    if jobs.len() >= QUEUE {
+        jobs.retain(|job| !job.is_cancelled());
+    }
+    if jobs.len() >= QUEUE {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "too many images are decoded at once",
+        ));
+    }
+    jobs.push_back(Job {
+        run: Box::new(job),
+        cancelled,
+    });
+    drop(jobs);
+    POOL.ready.notify_one();
+    Ok(())
+}
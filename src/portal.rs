@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+use zbus::{
+    dbus_interface,
+    zvariant::{ObjectPath, Value},
+};
+
+use crate::dbus::{call, Call};
+use crate::IpcRequest;
+
+/// Bus name of the portal backend, which has to be listed in a `smithay.portal` file
+pub const PORTAL_NAME: &str = "org.freedesktop.impl.portal.desktop.smithay";
+/// Object path xdg-desktop-portal expects backends at
+pub const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_OTHER: u32 = 2;
+
+/// The `org.freedesktop.impl.portal.Wallpaper` backend interface
+pub(crate) struct Portal {
+    pub calls: Mutex<Sender<Call>>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Wallpaper")]
+impl Portal {
+    /// Sets the wallpaper of all outputs to the file at `uri`, lock screens are not supported
+    #[dbus_interface(name = "SetWallpaperURI")]
+    fn set_wallpaper_uri(
+        &self,
+        _handle: ObjectPath<'_>,
+        _app_id: &str,
+        _parent_window: &str,
+        uri: &str,
+        options: HashMap<&str, Value<'_>>,
+    ) -> u32 {
+        let set_on = match options.get("set-on") {
+            Some(Value::Str(set_on)) => set_on.as_str(),
+            _ => "both",
+        };
+        if set_on == "lockscreen" {
+            return RESPONSE_OTHER;
+        }
+
+        // unsupported uris and failed loads are only reported to the portal, the state reports
+        // load errors with `WallpaperEvent::LoadFailed`
+        let path = match file_path(uri) {
+            Some(path) => path,
+            None => return RESPONSE_OTHER,
+        };
+        match call(&self.calls, IpcRequest::Set { output: None, path }) {
+            Ok(_) => RESPONSE_SUCCESS,
+            Err(_) => RESPONSE_OTHER,
+        }
+    }
+}
+
+/// Converts a `file://` uri to a path, decoding percent-escapes
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // skip the host, which is empty or localhost for local files
+    let path = &path[path.find('/')?..];
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
@@ -0,0 +1,243 @@
+#[cfg(feature = "upower")]
+use std::collections::HashMap;
+#[cfg(feature = "upower")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "upower")]
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "upower")]
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::OwnedValue,
+};
+
+/// Frames shown this much earlier than the interval allows still count as due,
+/// so a frame rate dividing the refresh rate is not missed by the jitter of vblanks
+const SLACK: Duration = Duration::from_millis(2);
+
+/// Whether the system runs on AC power or on battery
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+impl Default for PowerSource {
+    fn default() -> Self {
+        PowerSource::Ac
+    }
+}
+
+/// How animated wallpapers are drawn on battery, see [`WallpaperState::set_battery_policy`](crate::WallpaperState::set_battery_policy)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerPolicy {
+    /// Animates like on AC power
+    Full,
+    /// Draws animations, videos, transitions and shaders at most this many frames per second
+    MaxFps(f64),
+    /// Stops at the current frame until the system is on AC power again
+    Freeze,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        PowerPolicy::Full
+    }
+}
+
+impl PowerPolicy {
+    /// Returns the time between two frames of animated content, if it is limited
+    pub(crate) fn interval(&self) -> Option<Duration> {
+        match self {
+            PowerPolicy::MaxFps(fps) => interval(*fps),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the time between two frames at `fps`, unlimited if it is not positive
+pub(crate) fn interval(fps: f64) -> Option<Duration> {
+    if fps > 0.0 && fps.is_finite() {
+        Some(Duration::from_secs_f64(1.0 / fps))
+    } else {
+        None
+    }
+}
+
+/// Returns the time between two frames of animated content capped to `max_fps` and by `policy`,
+/// the lower frame rate wins
+pub(crate) fn frame_interval(max_fps: Option<f64>, policy: PowerPolicy) -> Option<Duration> {
+    max_fps.and_then(interval).max(policy.interval())
+}
+
+/// Limits how often animated content changes
+#[derive(Debug, Default)]
+pub(crate) struct Pacing {
+    shown: Option<Instant>,
+}
+
+impl Pacing {
+    /// Returns the earliest time from `at` on the next frame may be shown, one per `interval`
+    pub fn next(&self, interval: Option<Duration>, at: Instant) -> Instant {
+        match (self.shown, interval) {
+            (Some(shown), Some(interval)) => at.max(shown + interval),
+            _ => at,
+        }
+    }
+
+    /// Returns `true` if a frame may be shown at `now`
+    pub fn due(&self, interval: Option<Duration>, now: Instant) -> bool {
+        self.next(interval, now) <= now + SLACK
+    }
+
+    /// Remembers that a frame was shown at `now`
+    pub fn shown(&mut self, now: Instant) {
+        self.shown = Some(now);
+    }
+}
+
+/// Follows whether the system is on battery, as reported by UPower
+///
+/// Changes are received on a separate thread and picked up by [`PowerSourceWatcher::poll`].
+#[cfg(feature = "upower")]
+#[derive(Debug)]
+pub struct PowerSourceWatcher {
+    current: PowerSource,
+    changes: Receiver<PowerSource>,
+}
+
+#[cfg(feature = "upower")]
+fn from_on_battery(on_battery: bool) -> PowerSource {
+    if on_battery {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    }
+}
+
+#[cfg(feature = "upower")]
+impl PowerSourceWatcher {
+    /// Connects to the system bus and reads the current power source
+    pub fn new() -> zbus::Result<Self> {
+        let connection = Connection::system()?;
+        let upower = Proxy::new(
+            &connection,
+            "org.freedesktop.UPower",
+            "/org/freedesktop/UPower",
+            "org.freedesktop.UPower",
+        )?;
+        // without batteries UPower reports `false`
+        let current = upower
+            .get_property::<bool>("OnBattery")
+            .map(from_on_battery)
+            .unwrap_or_default();
+
+        let properties = Proxy::new(
+            &connection,
+            "org.freedesktop.UPower",
+            "/org/freedesktop/UPower",
+            "org.freedesktop.DBus.Properties",
+        )?;
+        let (tx, changes) = mpsc::channel();
+        let signals = properties.receive_signal("PropertiesChanged")?;
+        thread::spawn(move || {
+            // keeps the connection alive as long as the thread runs
+            let _proxy = properties;
+            for message in signals {
+                let (interface, changed, _) =
+                    match message.body::<(String, HashMap<String, OwnedValue>, Vec<String>)>() {
+                        Ok(body) => body,
+                        Err(_) => continue,
+                    };
+                if interface != "org.freedesktop.UPower" {
+                    continue;
+                }
+                let on_battery = match changed
+                    .get("OnBattery")
+                    .and_then(|value| bool::try_from(value.clone()).ok())
+                {
+                    Some(on_battery) => on_battery,
+                    None => continue,
+                };
+                if tx.send(from_on_battery(on_battery)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { current, changes })
+    }
+
+    /// Returns the current power source
+    pub fn power_source(&self) -> PowerSource {
+        self.current
+    }
+
+    /// Returns the new power source if it changed since the last call
+    pub fn poll(&mut self) -> Option<PowerSource> {
+        let source = self.changes.try_iter().last()?;
+        if source == self.current {
+            return None;
+        }
+        self.current = source;
+        Some(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn selects_the_lower_frame_rate() {
+        assert_eq!(frame_interval(None, PowerPolicy::Full), None);
+        assert_eq!(frame_interval(Some(0.0), PowerPolicy::Full), None);
+        assert_eq!(frame_interval(Some(f64::INFINITY), PowerPolicy::Full), None);
+        assert_eq!(
+            frame_interval(Some(10.0), PowerPolicy::Full),
+            Some(100 * MS)
+        );
+        assert_eq!(
+            frame_interval(None, PowerPolicy::MaxFps(4.0)),
+            Some(250 * MS)
+        );
+        assert_eq!(
+            frame_interval(Some(10.0), PowerPolicy::MaxFps(4.0)),
+            Some(250 * MS)
+        );
+        assert_eq!(
+            frame_interval(Some(4.0), PowerPolicy::MaxFps(10.0)),
+            Some(250 * MS)
+        );
+        // freezing is handled by the state, not by pacing
+        assert_eq!(
+            frame_interval(Some(10.0), PowerPolicy::Freeze),
+            Some(100 * MS)
+        );
+    }
+
+    #[test]
+    fn paces_frames_by_interval() {
+        let start = Instant::now();
+        let mut pacing = Pacing::default();
+        let interval = Some(100 * MS);
+        // nothing shown yet, so the first frame is due right away
+        assert!(pacing.due(interval, start));
+        assert_eq!(pacing.next(interval, start), start);
+
+        pacing.shown(start);
+        assert_eq!(pacing.next(interval, start), start + 100 * MS);
+        assert_eq!(pacing.next(interval, start + 500 * MS), start + 500 * MS);
+        assert!(!pacing.due(interval, start + 50 * MS));
+        assert!(pacing.due(interval, start + 100 * MS));
+        // vblanks a little early still count
+        assert!(pacing.due(interval, start + 99 * MS));
+        assert!(!pacing.due(interval, start + 97 * MS));
+
+        // unlimited frames are always due
+        assert!(pacing.due(None, start));
+        assert_eq!(pacing.next(None, start), start);
+    }
+}
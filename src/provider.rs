@@ -0,0 +1,196 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use image::ImageResult;
+
+use crate::loader::{self, Loaded};
+use crate::remote;
+use crate::WallpaperError;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A remote source of images changing over time, like a picture of the day
+///
+/// Providers run on a thread of their own, not on the decode threads, and may block.
+pub trait Provider: fmt::Debug + Send + Sync + 'static {
+    /// Unique name of the provider, used to name its cache file
+    fn name(&self) -> &str;
+
+    /// Looks up the url of the current image
+    fn image_url(&self) -> io::Result<String>;
+
+    /// How long an image is shown before the provider is asked again
+    fn refresh(&self) -> Duration {
+        DAY
+    }
+}
+
+fn error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+fn get_json(url: &str) -> io::Result<serde_json::Value> {
+    let data = remote::download(url)?;
+    serde_json::from_slice(&data).map_err(error)
+}
+
+/// The Bing image of the day
+#[derive(Debug, Clone)]
+pub struct Bing {
+    /// Market to get the image of, like `en-US`
+    pub market: String,
+}
+
+impl Default for Bing {
+    fn default() -> Self {
+        Self {
+            market: "en-US".into(),
+        }
+    }
+}
+
+impl Provider for Bing {
+    fn name(&self) -> &str {
+        "bing"
+    }
+
+    fn image_url(&self) -> io::Result<String> {
+        let json = get_json(&format!(
+            "https://www.bing.com/HPImageArchive.aspx?format=js&idx=0&n=1&mkt={}",
+            self.market
+        ))?;
+        json["images"][0]["url"]
+            .as_str()
+            .map(|url| format!("https://www.bing.com{}", url))
+            .ok_or_else(|| error("no image in the Bing response"))
+    }
+}
+
+/// The NASA Astronomy Picture of the Day
+///
+/// Some days have a video instead of an image, the previous image is kept then.
+#[derive(Debug, Clone)]
+pub struct NasaApod {
+    /// api.nasa.gov key, `DEMO_KEY` works with a low rate limit
+    pub api_key: String,
+}
+
+impl Default for NasaApod {
+    fn default() -> Self {
+        Self {
+            api_key: "DEMO_KEY".into(),
+        }
+    }
+}
+
+impl Provider for NasaApod {
+    fn name(&self) -> &str {
+        "nasa-apod"
+    }
+
+    fn image_url(&self) -> io::Result<String> {
+        let json = get_json(&format!(
+            "https://api.nasa.gov/planetary/apod?api_key={}",
+            self.api_key
+        ))?;
+        if json["media_type"].as_str() != Some("image") {
+            return Err(error("today's APOD is not an image"));
+        }
+        json["hdurl"]
+            .as_str()
+            .or_else(|| json["url"].as_str())
+            .map(String::from)
+            .ok_or_else(|| error("no image in the APOD response"))
+    }
+}
+
+/// `$XDG_CACHE_HOME/smithay-wallpaper/<name>`
+fn cache_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("smithay-wallpaper").join(name))
+}
+
+/// Returns the current image of `provider`, from the cache if it is recent enough.
+///
+/// Falls back to an outdated cached image if the download fails,
+/// failing to update or write the cache is handed to the state with [`loader::warn`].
+fn fetch(provider: &dyn Provider) -> io::Result<Vec<u8>> {
+    let cache = cache_path(provider.name());
+    let age = cache
+        .as_ref()
+        .and_then(|path| fs::metadata(path).ok())
+        .and_then(|meta| meta.modified().ok())
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    if let (Some(path), Some(age)) = (&cache, age) {
+        if age < provider.refresh() {
+            return fs::read(path);
+        }
+    }
+
+    match provider.image_url().and_then(|url| remote::download(&url)) {
+        Ok(data) => {
+            if let Some(path) = &cache {
+                let written = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(path, &data));
+                if let Err(err) = written {
+                    loader::warn(WallpaperError::Io(path.clone(), err));
+                }
+            }
+            Ok(data)
+        }
+        Err(err) => match cache.filter(|_| age.is_some()) {
+            Some(path) => {
+                loader::warn(WallpaperError::Provider(provider.name().to_string(), err));
+                fs::read(path)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// A `Provider` set on a `WallpaperState` and when to ask it next
+#[derive(Debug)]
+pub(crate) struct Refresh {
+    provider: Arc<dyn Provider>,
+    /// `None` until it was first polled
+    next: Option<Instant>,
+}
+
+impl Refresh {
+    pub fn new(provider: Arc<dyn Provider>) -> Self {
+        Self {
+            provider,
+            next: None,
+        }
+    }
+
+    /// When the provider is asked next, `now` if it was not asked yet
+    pub fn deadline(&self, now: Instant) -> Instant {
+        self.next.unwrap_or(now)
+    }
+
+    /// Returns the load to run once the refresh is due at `now`
+    pub fn poll(
+        &mut self,
+        now: Instant,
+    ) -> Option<impl FnOnce() -> ImageResult<Loaded> + Send + 'static> {
+        if self.next.map_or(false, |next| now < next) {
+            return None;
+        }
+        self.next = Some(now + self.provider.refresh());
+        let provider = self.provider.clone();
+        Some(move || {
+            fetch(&*provider)
+                .map_err(image::ImageError::IoError)
+                .and_then(|data| loader::decode(&data))
+        })
+    }
+}
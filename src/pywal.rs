@@ -0,0 +1,88 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::PathBuf;
+
+/// Directory pywal keeps the colors of the current wallpaper in
+fn cache_dir() -> io::Result<PathBuf> {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+    Ok(cache.join("wal"))
+}
+
+fn luminance(color: &[f32; 4]) -> f32 {
+    0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2]
+}
+
+fn mix(a: [f32; 4], b: [f32; 4], amount: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|c| a[c] + (b[c] - a[c]) * amount)
+}
+
+fn hex(color: [f32; 4]) -> String {
+    let [r, g, b, _] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Builds the 16 terminal colors from `palette` like pywal does:
+/// a dark background, six accents, a light foreground and their bright variants
+fn scheme(palette: &[[f32; 4]]) -> Option<[[f32; 4]; 16]> {
+    const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+    const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+    let mut sorted = palette.to_vec();
+    sorted.sort_by(|a, b| luminance(a).total_cmp(&luminance(b)));
+    let (darkest, lightest) = (*sorted.first()?, *sorted.last()?);
+    let accents = match sorted.len() {
+        0..=2 => sorted.clone(),
+        len => sorted[1..len - 1].to_vec(),
+    };
+
+    let mut colors = [BLACK; 16];
+    colors[0] = mix(darkest, BLACK, 0.6);
+    for (i, color) in colors[1..7].iter_mut().enumerate() {
+        *color = accents[i % accents.len()];
+    }
+    colors[7] = mix(lightest, WHITE, 0.6);
+    colors[8] = mix(colors[7], BLACK, 0.3);
+    colors.copy_within(1..8, 9);
+    Some(colors)
+}
+
+/// Writes `palette` as pywal color scheme to `~/.cache/wal`,
+/// so programs themed by pywal follow the wallpaper
+pub(crate) fn export(palette: &[[f32; 4]]) -> io::Result<()> {
+    let colors = match scheme(palette) {
+        Some(colors) => colors.map(hex),
+        None => return Ok(()),
+    };
+    let dir = cache_dir()?;
+    std::fs::create_dir_all(&dir)?;
+
+    let json = serde_json::json!({
+        "alpha": "100",
+        "special": {
+            "background": colors[0],
+            "foreground": colors[15],
+            "cursor": colors[15],
+        },
+        "colors": colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| (format!("color{}", i), serde_json::Value::from(color.as_str())))
+            .collect::<serde_json::Map<_, _>>(),
+    });
+    std::fs::write(dir.join("colors.json"), json.to_string())?;
+
+    let mut xresources = String::new();
+    let _ = writeln!(xresources, "*background: {}", colors[0]);
+    let _ = writeln!(xresources, "*foreground: {}", colors[15]);
+    let _ = writeln!(xresources, "*cursorColor: {}", colors[15]);
+    for (i, color) in colors.iter().enumerate() {
+        let _ = writeln!(xresources, "*color{}: {}", i, color);
+    }
+    std::fs::write(dir.join("colors.Xresources"), xresources)?;
+
+    std::fs::write(dir.join("colors"), colors.join("\n") + "\n")
+}
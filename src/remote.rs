@@ -0,0 +1,36 @@
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// Downloads taking longer are aborted
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Larger downloads are rejected, to not run out of memory on a malicious or broken server
+const MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+fn error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+/// Downloads the file at the http(s) `url`, blocking until it is complete
+pub(crate) fn download(url: &str) -> io::Result<Vec<u8>> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let response = agent.get(url).call().map_err(error)?;
+
+    if let Some(len) = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok())
+    {
+        if len > MAX_SIZE {
+            return Err(error(format!("{} is larger than {} bytes", url, MAX_SIZE)));
+        }
+    }
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_SIZE + 1)
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_SIZE {
+        return Err(error(format!("{} is larger than {} bytes", url, MAX_SIZE)));
+    }
+    Ok(data)
+}
@@ -0,0 +1,393 @@
+use smithay::utils::{Buffer, Physical, Point, Rectangle, Size};
+
+/// How an image is fitted into the area it is drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ScalingMode {
+    /// Scale the image to cover the whole area, cropping what does not fit
+    Fill,
+    /// Scale the image to fit inside the area, leaving the remaining space empty
+    Fit,
+    /// Scale the image to exactly the size of the area, ignoring its aspect ratio
+    Stretch,
+    /// Draw the image unscaled in the center of the area
+    Center,
+    /// Repeat the image unscaled starting at the top-left corner of the area.
+    ///
+    /// The `Gles2Renderer` draws a single quad repeating the texture, if it supports repeating its size.
+    Tile,
+    /// Scale the image by the largest whole factor that fits and center it,
+    /// sampling the nearest pixel so pixel art stays crisp
+    PixelArt,
+}
+
+impl Default for ScalingMode {
+    fn default() -> Self {
+        ScalingMode::Fill
+    }
+}
+
+/// Where the image is aligned in the area, deciding what is cropped when it does not fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Anchor {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Anchor::Center
+    }
+}
+
+impl Anchor {
+    /// Returns the horizontal and vertical alignment, `0.0` at the top-left and `1.0` at the bottom-right
+    fn alignment(&self) -> (f64, f64) {
+        match self {
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// Computes the `(src, dst)` rectangle pairs needed to draw an image of `image` size into `area`,
+/// aligned to `anchor`.
+pub(crate) fn layout(
+    mode: ScalingMode,
+    anchor: Anchor,
+    image: Size<i32, Buffer>,
+    area: Rectangle<i32, Physical>,
+) -> Vec<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)> {
+    if image.w <= 0 || image.h <= 0 || area.size.w <= 0 || area.size.h <= 0 {
+        return vec![];
+    }
+
+    let full = Rectangle::from_loc_and_size((0, 0), image);
+    let area_f = area.to_f64();
+    let (align_x, align_y) = anchor.alignment();
+
+    match mode {
+        ScalingMode::Stretch => vec![(full, area_f)],
+        ScalingMode::Fit | ScalingMode::Fill => {
+            let scale_x = area.size.w as f64 / image.w as f64;
+            let scale_y = area.size.h as f64 / image.h as f64;
+            if mode == ScalingMode::Fit {
+                let scale = scale_x.min(scale_y);
+                let size =
+                    Size::<f64, Physical>::from((image.w as f64 * scale, image.h as f64 * scale));
+                let loc = area_f.loc
+                    + Point::from((
+                        (area_f.size.w - size.w) * align_x,
+                        (area_f.size.h - size.h) * align_y,
+                    ));
+                vec![(full, Rectangle::from_loc_and_size(loc, size))]
+            } else {
+                let scale = scale_x.max(scale_y);
+                let size = Size::<i32, Buffer>::from((
+                    ((area.size.w as f64 / scale).round() as i32).clamp(1, image.w),
+                    ((area.size.h as f64 / scale).round() as i32).clamp(1, image.h),
+                ));
+                let loc = Point::from((
+                    ((image.w - size.w) as f64 * align_x).round() as i32,
+                    ((image.h - size.h) as f64 * align_y).round() as i32,
+                ));
+                vec![(Rectangle::from_loc_and_size(loc, size), area_f)]
+            }
+        }
+        ScalingMode::PixelArt => {
+            let factor = (area.size.w / image.w).min(area.size.h / image.h).max(1);
+            let (src_x, dst_x, w) = align_axis(image.w * factor, area.size.w, align_x);
+            let (src_y, dst_y, h) = align_axis(image.h * factor, area.size.h, align_y);
+            vec![(
+                Rectangle::from_loc_and_size(
+                    (src_x / factor, src_y / factor),
+                    (w / factor, h / factor),
+                ),
+                Rectangle::from_loc_and_size((area.loc.x + dst_x, area.loc.y + dst_y), (w, h))
+                    .to_f64(),
+            )]
+        }
+        ScalingMode::Center => {
+            let (src_x, dst_x, w) = align_axis(image.w, area.size.w, align_x);
+            let (src_y, dst_y, h) = align_axis(image.h, area.size.h, align_y);
+            vec![(
+                Rectangle::from_loc_and_size((src_x, src_y), (w, h)),
+                Rectangle::from_loc_and_size((area.loc.x + dst_x, area.loc.y + dst_y), (w, h))
+                    .to_f64(),
+            )]
+        }
+        ScalingMode::Tile => {
+            let mut tiles = Vec::new();
+            let mut y = 0;
+            while y < area.size.h {
+                let h = image.h.min(area.size.h - y);
+                let mut x = 0;
+                while x < area.size.w {
+                    let w = image.w.min(area.size.w - x);
+                    tiles.push((
+                        Rectangle::from_loc_and_size((0, 0), (w, h)),
+                        Rectangle::from_loc_and_size((area.loc.x + x, area.loc.y + y), (w, h))
+                            .to_f64(),
+                    ));
+                    x += image.w;
+                }
+                y += image.h;
+            }
+            tiles
+        }
+    }
+}
+
+/// Restricts the `(src, dst)` pairs of [`layout`] to `clip`, cropping the source by the same fraction
+pub(crate) fn clip(
+    pairs: Vec<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)>,
+    clip: Rectangle<i32, Physical>,
+) -> Vec<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)> {
+    let clip = clip.to_f64();
+    pairs
+        .into_iter()
+        .filter_map(|(src, dst)| {
+            let x0 = dst.loc.x.max(clip.loc.x);
+            let y0 = dst.loc.y.max(clip.loc.y);
+            let x1 = (dst.loc.x + dst.size.w).min(clip.loc.x + clip.size.w);
+            let y1 = (dst.loc.y + dst.size.h).min(clip.loc.y + clip.size.h);
+            if x1 <= x0 || y1 <= y0 {
+                return None;
+            }
+
+            // map the clipped edges back into the source rectangle
+            let scale_x = src.size.w as f64 / dst.size.w;
+            let scale_y = src.size.h as f64 / dst.size.h;
+            let src_x0 = src.loc.x as f64 + (x0 - dst.loc.x) * scale_x;
+            let src_y0 = src.loc.y as f64 + (y0 - dst.loc.y) * scale_y;
+            let src_x1 = src.loc.x as f64 + (x1 - dst.loc.x) * scale_x;
+            let src_y1 = src.loc.y as f64 + (y1 - dst.loc.y) * scale_y;
+            let src_loc =
+                Point::<i32, Buffer>::from((src_x0.round() as i32, src_y0.round() as i32));
+            let src_size = Size::<i32, Buffer>::from((
+                ((src_x1.round() as i32) - src_loc.x).max(1),
+                ((src_y1.round() as i32) - src_loc.y).max(1),
+            ));
+            Some((
+                Rectangle::from_loc_and_size(src_loc, src_size),
+                Rectangle::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0)),
+            ))
+        })
+        .collect()
+}
+
+/// Returns `(src offset, dst offset, length)` for aligning `image` pixels inside `area` pixels along one axis,
+/// `align` is `0.0` for the start, `0.5` for the center and `1.0` for the end.
+fn align_axis(image: i32, area: i32, align: f64) -> (i32, i32, i32) {
+    let offset = |excess: i32| (excess as f64 * align).round() as i32;
+    if image > area {
+        (offset(image - area), 0, area)
+    } else {
+        (0, offset(area - image), image)
+    }
+}
+
+/// Restricts the `(src, dst)` pair to the part of the source inside `tile`,
+/// returning the source relative to the tile and the matching part of the destination
+pub(crate) fn split(
+    (src, dst): (Rectangle<i32, Buffer>, Rectangle<f64, Physical>),
+    tile: Rectangle<i32, Buffer>,
+) -> Option<(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)> {
+    let x0 = src.loc.x.max(tile.loc.x);
+    let y0 = src.loc.y.max(tile.loc.y);
+    let x1 = (src.loc.x + src.size.w).min(tile.loc.x + tile.size.w);
+    let y1 = (src.loc.y + src.size.h).min(tile.loc.y + tile.size.h);
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+
+    let scale_x = dst.size.w / src.size.w as f64;
+    let scale_y = dst.size.h / src.size.h as f64;
+    let dst_x0 = dst.loc.x + (x0 - src.loc.x) as f64 * scale_x;
+    let dst_y0 = dst.loc.y + (y0 - src.loc.y) as f64 * scale_y;
+    Some((
+        Rectangle::from_loc_and_size((x0 - tile.loc.x, y0 - tile.loc.y), (x1 - x0, y1 - y0)),
+        Rectangle::from_loc_and_size(
+            (dst_x0, dst_y0),
+            ((x1 - x0) as f64 * scale_x, (y1 - y0) as f64 * scale_y),
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn src(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Buffer> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    fn dst(x: f64, y: f64, w: f64, h: f64) -> Rectangle<f64, Physical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    fn area(w: i32, h: i32) -> Rectangle<i32, Physical> {
+        Rectangle::from_loc_and_size((0, 0), (w, h))
+    }
+
+    #[test]
+    fn fill_crops_around_the_anchor() {
+        let image = Size::from((200, 100));
+        assert_eq!(
+            layout(ScalingMode::Fill, Anchor::Center, image, area(100, 100)),
+            vec![(src(50, 0, 100, 100), dst(0.0, 0.0, 100.0, 100.0))]
+        );
+        assert_eq!(
+            layout(ScalingMode::Fill, Anchor::Right, image, area(100, 100)),
+            vec![(src(100, 0, 100, 100), dst(0.0, 0.0, 100.0, 100.0))]
+        );
+    }
+
+    #[test]
+    fn fit_leaves_space_around_the_image() {
+        let image = Size::from((200, 100));
+        assert_eq!(
+            layout(ScalingMode::Fit, Anchor::Center, image, area(100, 100)),
+            vec![(src(0, 0, 200, 100), dst(0.0, 25.0, 100.0, 50.0))]
+        );
+        assert_eq!(
+            layout(ScalingMode::Fit, Anchor::Bottom, image, area(100, 100)),
+            vec![(src(0, 0, 200, 100), dst(0.0, 50.0, 100.0, 50.0))]
+        );
+    }
+
+    #[test]
+    fn stretch_uses_the_whole_image_and_area() {
+        assert_eq!(
+            layout(
+                ScalingMode::Stretch,
+                Anchor::Center,
+                Size::from((30, 10)),
+                area(100, 100)
+            ),
+            vec![(src(0, 0, 30, 10), dst(0.0, 0.0, 100.0, 100.0))]
+        );
+    }
+
+    #[test]
+    fn center_crops_or_pads_unscaled() {
+        assert_eq!(
+            layout(
+                ScalingMode::Center,
+                Anchor::Center,
+                Size::from((50, 50)),
+                area(100, 100)
+            ),
+            vec![(src(0, 0, 50, 50), dst(25.0, 25.0, 50.0, 50.0))]
+        );
+        assert_eq!(
+            layout(
+                ScalingMode::Center,
+                Anchor::Center,
+                Size::from((200, 200)),
+                area(100, 100)
+            ),
+            vec![(src(50, 50, 100, 100), dst(0.0, 0.0, 100.0, 100.0))]
+        );
+    }
+
+    #[test]
+    fn pixel_art_scales_by_whole_factors() {
+        assert_eq!(
+            layout(
+                ScalingMode::PixelArt,
+                Anchor::Center,
+                Size::from((30, 20)),
+                area(100, 100)
+            ),
+            vec![(src(0, 0, 30, 20), dst(5.0, 20.0, 90.0, 60.0))]
+        );
+    }
+
+    #[test]
+    fn tile_cuts_the_last_row_and_column() {
+        assert_eq!(
+            layout(
+                ScalingMode::Tile,
+                Anchor::Center,
+                Size::from((60, 60)),
+                area(100, 100)
+            ),
+            vec![
+                (src(0, 0, 60, 60), dst(0.0, 0.0, 60.0, 60.0)),
+                (src(0, 0, 40, 60), dst(60.0, 0.0, 40.0, 60.0)),
+                (src(0, 0, 60, 40), dst(0.0, 60.0, 60.0, 40.0)),
+                (src(0, 0, 40, 40), dst(60.0, 60.0, 40.0, 40.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_sizes_draw_nothing() {
+        assert!(layout(
+            ScalingMode::Fill,
+            Anchor::Center,
+            Size::from((0, 10)),
+            area(100, 100)
+        )
+        .is_empty());
+        assert!(layout(
+            ScalingMode::Fill,
+            Anchor::Center,
+            Size::from((10, 10)),
+            area(0, 100)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn clip_crops_the_source_by_the_same_fraction() {
+        let pairs = vec![(src(0, 0, 100, 100), dst(0.0, 0.0, 200.0, 200.0))];
+        assert_eq!(
+            clip(pairs.clone(), area(100, 200)),
+            vec![(src(0, 0, 50, 100), dst(0.0, 0.0, 100.0, 200.0))]
+        );
+        assert!(clip(pairs, Rectangle::from_loc_and_size((300, 0), (10, 10))).is_empty());
+    }
+
+    #[test]
+    fn split_maps_the_source_into_the_tile() {
+        assert_eq!(
+            split(
+                (src(0, 0, 100, 100), dst(0.0, 0.0, 200.0, 200.0)),
+                src(50, 0, 50, 100)
+            ),
+            Some((src(0, 0, 50, 100), dst(100.0, 0.0, 100.0, 200.0)))
+        );
+        assert_eq!(
+            split(
+                (src(0, 0, 100, 100), dst(0.0, 0.0, 200.0, 200.0)),
+                src(100, 0, 50, 100)
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(feature = "dbus")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "dbus")]
+use std::thread;
+
+#[cfg(feature = "dbus")]
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedValue, Value},
+};
+
+/// Whether the desktop uses a light or a dark theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        ColorScheme::Light
+    }
+}
+
+#[cfg(feature = "dbus")]
+const SETTINGS_NAMESPACE: &str = "org.freedesktop.appearance";
+#[cfg(feature = "dbus")]
+const SETTINGS_KEY: &str = "color-scheme";
+
+/// Reads the value of the `color-scheme` setting, `1` prefers dark and anything else light
+#[cfg(feature = "dbus")]
+fn color_scheme(value: &Value<'_>) -> Option<ColorScheme> {
+    match value {
+        // `Read` wraps the value in another variant
+        Value::Value(value) => color_scheme(value),
+        Value::U32(1) => Some(ColorScheme::Dark),
+        Value::U32(_) => Some(ColorScheme::Light),
+        _ => None,
+    }
+}
+
+/// Follows the color scheme of the settings portal
+///
+/// Changes are received on a separate thread and picked up by [`ColorSchemeWatcher::poll`].
+#[cfg(feature = "dbus")]
+#[derive(Debug)]
+pub struct ColorSchemeWatcher {
+    current: ColorScheme,
+    changes: Receiver<ColorScheme>,
+}
+
+#[cfg(feature = "dbus")]
+impl ColorSchemeWatcher {
+    /// Connects to the session bus and reads the current color scheme
+    pub fn new() -> zbus::Result<Self> {
+        let connection = Connection::session()?;
+        let proxy = Proxy::new(
+            &connection,
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Settings",
+        )?;
+        let current = proxy
+            .call::<_, _, OwnedValue>("Read", &(SETTINGS_NAMESPACE, SETTINGS_KEY))
+            .ok()
+            .and_then(|value| color_scheme(&value))
+            .unwrap_or_default();
+
+        let (tx, changes) = mpsc::channel();
+        let signals = proxy.receive_signal("SettingChanged")?;
+        thread::spawn(move || {
+            // keeps the connection alive as long as the thread runs
+            let _proxy = proxy;
+            for message in signals {
+                let (namespace, key, value) = match message.body::<(String, String, OwnedValue)>() {
+                    Ok(body) => body,
+                    Err(_) => continue,
+                };
+                if namespace != SETTINGS_NAMESPACE || key != SETTINGS_KEY {
+                    continue;
+                }
+                if let Some(scheme) = color_scheme(&value) {
+                    if tx.send(scheme).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { current, changes })
+    }
+
+    /// Returns the current color scheme
+    pub fn color_scheme(&self) -> ColorScheme {
+        self.current
+    }
+
+    /// Returns the new color scheme if it changed since the last call
+    pub fn poll(&mut self) -> Option<ColorScheme> {
+        let scheme = self.changes.try_iter().last()?;
+        if scheme == self.current {
+            return None;
+        }
+        self.current = scheme;
+        Some(scheme)
+    }
+}
@@ -0,0 +1,354 @@
+use std::ffi::CString;
+use std::ptr;
+use std::time::Instant;
+
+use smithay::{
+    backend::renderer::gles2::{ffi, Gles2Renderer},
+    utils::{Logical, Physical, Point, Rectangle, Size, Transform},
+};
+
+use crate::context::{self, PerContext};
+use crate::WallpaperError;
+
+/// Length of the `iAudio` uniform array
+pub(crate) const AUDIO_BANDS: usize = 64;
+
+const VERTEX_SHADER: &str = r#"
+attribute vec2 position;
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_HEADER: &str = r#"
+precision highp float;
+uniform vec3 iResolution;
+uniform float iTime;
+uniform vec4 iMouse;
+uniform vec2 wallpaperOffset;
+uniform float iAudio[64];
+"#;
+
+const FRAGMENT_FOOTER: &str = r#"
+void main() {
+    vec4 color = vec4(0.0);
+    mainImage(color, gl_FragCoord.xy - wallpaperOffset);
+    gl_FragColor = vec4(color.rgb, 1.0);
+}
+"#;
+
+/// A linked shader program and its uniform locations
+#[derive(Debug)]
+struct Program {
+    program: ffi::types::GLuint,
+    position: ffi::types::GLint,
+    resolution: ffi::types::GLint,
+    time: ffi::types::GLint,
+    mouse: ffi::types::GLint,
+    offset: ffi::types::GLint,
+    audio: ffi::types::GLint,
+}
+
+#[derive(Debug)]
+enum Compiled {
+    Pending,
+    Ready(Program),
+    Failed,
+}
+
+impl Default for Compiled {
+    fn default() -> Self {
+        Compiled::Pending
+    }
+}
+
+/// Calls `draw` once for every rectangle of `damage` with the scissor test clipping to it.
+///
+/// The rectangles are laid out on an output of `size`, before its `transform` is undone.
+pub(crate) unsafe fn clipped<F: FnMut()>(
+    gl: &ffi::Gles2,
+    damage: &[Rectangle<i32, Physical>],
+    size: Size<i32, Physical>,
+    transform: Transform,
+    mut draw: F,
+) {
+    let enabled = gl.IsEnabled(ffi::SCISSOR_TEST) == ffi::TRUE;
+    let mut scissor = [0; 4];
+    gl.GetIntegerv(ffi::SCISSOR_BOX, scissor.as_mut_ptr());
+    gl.Enable(ffi::SCISSOR_TEST);
+    let buffer = transform.transform_size(size);
+    for rect in damage {
+        let rect = transform.invert().transform_rect_in(*rect, &size);
+        // GL window coordinates start at the bottom-left corner
+        let bottom = buffer.h - rect.loc.y - rect.size.h;
+        gl.Scissor(rect.loc.x, bottom, rect.size.w, rect.size.h);
+        draw();
+    }
+    gl.Scissor(scissor[0], scissor[1], scissor[2], scissor[3]);
+    if !enabled {
+        gl.Disable(ffi::SCISSOR_TEST);
+    }
+}
+
+/// Values passed to the shader uniforms for a single frame
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Uniforms {
+    time: f32,
+    area: Rectangle<i32, Physical>,
+    size: Size<i32, Physical>,
+    transform: Transform,
+    mouse: Point<f64, Physical>,
+    audio: [f32; AUDIO_BANDS],
+}
+
+impl Uniforms {
+    /// Passes the spectrum of the captured audio to `iAudio`, silence without it
+    pub fn with_audio(mut self, audio: [f32; AUDIO_BANDS]) -> Self {
+        self.audio = audio;
+        self
+    }
+}
+
+/// A Shadertoy-style fragment shader, compiled on first use
+#[derive(Debug)]
+pub(crate) struct Shader {
+    source: String,
+    started: Instant,
+    program: PerContext<Compiled>,
+}
+
+impl Shader {
+    /// Creates the shader, its time starts at `now`
+    pub fn new(source: String, now: Instant) -> Self {
+        Self {
+            source,
+            started: now,
+            program: PerContext::default(),
+        }
+    }
+
+    /// Computes the uniforms for drawing at `now` into `area` of a buffer of `size`, with the pointer at `pointer`.
+    ///
+    /// `area` and `size` are in the space of the output `transform`.
+    pub fn uniforms(
+        &self,
+        area: Rectangle<i32, Physical>,
+        size: Size<i32, Physical>,
+        scale: f64,
+        transform: Transform,
+        pointer: Option<Point<f64, Logical>>,
+        now: Instant,
+    ) -> Uniforms {
+        // Shadertoy uses a bottom-left origin
+        let mouse = pointer
+            .map(|pointer| pointer.to_physical(scale))
+            .map(|pointer| {
+                Point::from((
+                    pointer.x - area.loc.x as f64,
+                    (area.loc.y + area.size.h) as f64 - pointer.y,
+                ))
+            })
+            .unwrap_or_else(|| Point::from((0.0, 0.0)));
+        Uniforms {
+            time: now.saturating_duration_since(self.started).as_secs_f32(),
+            area,
+            size,
+            transform,
+            mouse,
+            audio: [0.0; AUDIO_BANDS],
+        }
+    }
+}
+
+impl Shader {
+    /// Deletes the program compiled for `r`, it is compiled again on the next draw
+    pub fn destroy(&self, r: &mut Gles2Renderer) {
+        if let Some(Compiled::Ready(compiled)) = self.program.take(context::id(&*r)) {
+            let _ = r.with_context(|_renderer, gl| unsafe { gl.DeleteProgram(compiled.program) });
+        }
+    }
+
+    /// Forgets the programs without deleting them, after the GL contexts were lost
+    pub fn forget(&self) {
+        self.program.clear();
+    }
+
+    /// Draws the shader into the area given by `uniforms`, only where it is within `damage`.
+    ///
+    /// If the shader fails to compile the error is returned once, later draws draw nothing.
+    pub fn draw(
+        &self,
+        r: &mut Gles2Renderer,
+        uniforms: &Uniforms,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), WallpaperError> {
+        let mut program = self.program.get(context::id(&*r));
+        r.with_context(|_renderer, gl| unsafe {
+            if let Compiled::Pending = *program {
+                match compile(gl, &self.source) {
+                    Ok(compiled) => *program = Compiled::Ready(compiled),
+                    Err(err) => {
+                        *program = Compiled::Failed;
+                        return Err(WallpaperError::Shader(err));
+                    }
+                }
+            }
+
+            let program = match &*program {
+                Compiled::Ready(program) => program,
+                _ => return Ok(()),
+            };
+
+            // raw GL draws bypass the projection of the renderer, so undo the output transform here
+            let area = uniforms
+                .transform
+                .invert()
+                .transform_rect_in(uniforms.area, &uniforms.size);
+            let size = uniforms.transform.transform_size(uniforms.size);
+            let (area, size) = (area.to_f64(), size.to_f64());
+            // GL window coordinates start at the bottom-left corner
+            let bottom = size.h - area.loc.y - area.size.h;
+            let (x0, x1) = (
+                (area.loc.x / size.w * 2.0 - 1.0) as f32,
+                ((area.loc.x + area.size.w) / size.w * 2.0 - 1.0) as f32,
+            );
+            let (y0, y1) = (
+                (bottom / size.h * 2.0 - 1.0) as f32,
+                ((bottom + area.size.h) / size.h * 2.0 - 1.0) as f32,
+            );
+            let quad: [f32; 8] = [x0, y0, x1, y0, x0, y1, x1, y1];
+
+            gl.UseProgram(program.program);
+            gl.Uniform3f(
+                program.resolution,
+                area.size.w as f32,
+                area.size.h as f32,
+                1.0,
+            );
+            gl.Uniform2f(program.offset, area.loc.x as f32, bottom as f32);
+            gl.Uniform1f(program.time, uniforms.time);
+            gl.Uniform1fv(program.audio, AUDIO_BANDS as i32, uniforms.audio.as_ptr());
+            gl.Uniform4f(
+                program.mouse,
+                uniforms.mouse.x as f32,
+                uniforms.mouse.y as f32,
+                0.0,
+                0.0,
+            );
+
+            gl.BindBuffer(ffi::ARRAY_BUFFER, 0);
+            gl.EnableVertexAttribArray(program.position as u32);
+            gl.VertexAttribPointer(
+                program.position as u32,
+                2,
+                ffi::FLOAT,
+                ffi::FALSE,
+                0,
+                quad.as_ptr() as *const _,
+            );
+            clipped(gl, damage, uniforms.size, uniforms.transform, || {
+                gl.DrawArrays(ffi::TRIANGLE_STRIP, 0, 4)
+            });
+            gl.DisableVertexAttribArray(program.position as u32);
+            gl.UseProgram(0);
+            Ok(())
+        })
+        .map_err(|err| WallpaperError::Render(Box::new(err)))?
+    }
+}
+
+unsafe fn compile_shader(
+    gl: &ffi::Gles2,
+    kind: ffi::types::GLenum,
+    source: &str,
+) -> Result<ffi::types::GLuint, String> {
+    let shader = gl.CreateShader(kind);
+    let source = CString::new(source).map_err(|err| err.to_string())?;
+    gl.ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+    gl.CompileShader(shader);
+
+    let mut status = ffi::FALSE as i32;
+    gl.GetShaderiv(shader, ffi::COMPILE_STATUS, &mut status as *mut _);
+    if status == ffi::FALSE as i32 {
+        let mut len = 0;
+        gl.GetShaderiv(shader, ffi::INFO_LOG_LENGTH, &mut len as *mut _);
+        let mut log = vec![0u8; len.max(1) as usize];
+        gl.GetShaderInfoLog(shader, len, ptr::null_mut(), log.as_mut_ptr() as *mut _);
+        gl.DeleteShader(shader);
+        return Err(String::from_utf8_lossy(&log)
+            .trim_end_matches('\0')
+            .to_string());
+    }
+
+    Ok(shader)
+}
+
+/// Compiles and links a program from the `vertex` and `fragment` shader sources
+pub(crate) unsafe fn link(
+    gl: &ffi::Gles2,
+    vertex: &str,
+    fragment: &str,
+) -> Result<ffi::types::GLuint, String> {
+    let vertex = compile_shader(gl, ffi::VERTEX_SHADER, vertex)?;
+    let fragment = match compile_shader(gl, ffi::FRAGMENT_SHADER, fragment) {
+        Ok(fragment) => fragment,
+        Err(err) => {
+            gl.DeleteShader(vertex);
+            return Err(err);
+        }
+    };
+
+    let program = gl.CreateProgram();
+    gl.AttachShader(program, vertex);
+    gl.AttachShader(program, fragment);
+    gl.LinkProgram(program);
+    gl.DetachShader(program, vertex);
+    gl.DetachShader(program, fragment);
+    gl.DeleteShader(vertex);
+    gl.DeleteShader(fragment);
+
+    let mut status = ffi::FALSE as i32;
+    gl.GetProgramiv(program, ffi::LINK_STATUS, &mut status as *mut _);
+    if status == ffi::FALSE as i32 {
+        gl.DeleteProgram(program);
+        return Err("failed to link shader program".into());
+    }
+    Ok(program)
+}
+
+/// Returns the location of the uniform `name` of `program`
+pub(crate) unsafe fn uniform(
+    gl: &ffi::Gles2,
+    program: ffi::types::GLuint,
+    name: &str,
+) -> ffi::types::GLint {
+    let name = CString::new(name).unwrap();
+    gl.GetUniformLocation(program, name.as_ptr() as *const ffi::types::GLchar)
+}
+
+/// Returns the location of the vertex attribute `name` of `program`
+pub(crate) unsafe fn attribute(
+    gl: &ffi::Gles2,
+    program: ffi::types::GLuint,
+    name: &str,
+) -> ffi::types::GLint {
+    let name = CString::new(name).unwrap();
+    gl.GetAttribLocation(program, name.as_ptr() as *const ffi::types::GLchar)
+}
+
+unsafe fn compile(gl: &ffi::Gles2, source: &str) -> Result<Program, String> {
+    let program = link(
+        gl,
+        VERTEX_SHADER,
+        &format!("{}{}{}", FRAGMENT_HEADER, source, FRAGMENT_FOOTER),
+    )?;
+    Ok(Program {
+        program,
+        position: attribute(gl, program, "position"),
+        resolution: uniform(gl, program, "iResolution"),
+        time: uniform(gl, program, "iTime"),
+        mouse: uniform(gl, program, "iMouse"),
+        offset: uniform(gl, program, "wallpaperOffset"),
+        audio: uniform(gl, program, "iAudio"),
+    })
+}
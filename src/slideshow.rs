@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::loader::{self, Completed, Notify, Pending, Prescale};
+
+/// Cycles through a list of images, decoding the next one ahead of time
+#[derive(Debug)]
+pub(crate) struct Slideshow {
+    paths: Vec<PathBuf>,
+    interval: Duration,
+    index: usize,
+    switched: Instant,
+    skip: bool,
+    next: Option<Pending>,
+    notify: Notify,
+}
+
+impl Slideshow {
+    /// Starts showing the first of `paths` at `now`
+    pub fn new(paths: Vec<PathBuf>, interval: Duration, notify: Notify, now: Instant) -> Self {
+        Self {
+            paths,
+            interval,
+            index: 0,
+            switched: now,
+            skip: false,
+            next: None,
+            notify,
+        }
+    }
+
+    fn next_index(&self) -> usize {
+        (self.index + 1) % self.paths.len()
+    }
+
+    /// Switches to the next image as soon as it finished decoding
+    pub fn skip(&mut self) {
+        self.skip = true;
+    }
+
+    /// Returns when the next image is due, `None` if the playlist has a single image
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.paths.len() < 2 {
+            None
+        } else if self.skip {
+            // already passed
+            Some(self.switched)
+        } else {
+            Some(self.switched + self.interval)
+        }
+    }
+
+    /// Returns the next image once the interval has passed and it finished decoding,
+    /// or the error decoding it, with the time `now`.
+    /// The next image is decoded ahead of time scaled down to `prescale`.
+    pub fn poll(&mut self, prescale: Option<Prescale>, now: Instant) -> Option<Completed> {
+        if self.paths.len() < 2 {
+            return None;
+        }
+
+        if self.next.is_none() {
+            let path = self.paths[self.next_index()].clone();
+            self.next = Some(
+                Pending::spawn(move || loader::open(path), self.notify.clone())
+                    .with_prescale(prescale),
+            );
+        }
+
+        if !self.skip && now.saturating_duration_since(self.switched) < self.interval {
+            return None;
+        }
+
+        let result = self.next.as_ref()?.poll()?;
+        self.next = None;
+        self.index = self.next_index();
+        self.switched = now;
+        self.skip = false;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// Polls until the image decoded ahead of time was handed out, without the system clock moving much
+    fn poll_decoded(slideshow: &mut Slideshow, now: Instant) -> Completed {
+        for _ in 0..500 {
+            if let Some(completed) = slideshow.poll(None, now) {
+                return completed;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("image was not decoded");
+    }
+
+    #[test]
+    fn switches_by_the_ticked_time() {
+        let dir = std::env::temp_dir().join(format!("wallpaper-slideshow-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = (0..2u8)
+            .map(|i| {
+                let path = dir.join(format!("{}.png", i));
+                RgbaImage::from_pixel(2, 2, Rgba([i, 0, 0, 255]))
+                    .save(&path)
+                    .unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut slideshow = Slideshow::new(paths, interval, Notify::default(), start);
+        assert_eq!(slideshow.deadline(), Some(start + interval));
+
+        // decoded ahead of time, but not shown before the interval passed
+        assert!(slideshow.poll(None, start).is_none());
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(slideshow
+            .poll(None, start + interval - Duration::from_secs(1))
+            .is_none());
+
+        let later = start + interval;
+        assert!(poll_decoded(&mut slideshow, later).result.is_ok());
+        assert_eq!(slideshow.index, 1);
+        assert_eq!(slideshow.deadline(), Some(later + interval));
+
+        let later = later + interval;
+        assert!(poll_decoded(&mut slideshow, later).result.is_ok());
+        assert_eq!(slideshow.index, 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
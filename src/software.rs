@@ -0,0 +1,76 @@
+use image::DynamicImage;
+use smithay::utils::{Buffer, Physical, Rectangle, Size};
+
+use crate::tools;
+
+/// An output buffer in the ARGB8888 format of `wl_shm`, drawn into on the CPU
+#[derive(Debug)]
+pub(crate) struct Target<'a> {
+    pub buffer: &'a mut [u8],
+    /// Bytes per row
+    pub stride: usize,
+    pub size: Size<i32, Physical>,
+}
+
+impl Target<'_> {
+    /// Calls `f` with every pixel of the buffer in `dst` and its position relative to `dst`
+    fn for_each<F: FnMut(&mut [u8], f64, f64)>(&mut self, dst: Rectangle<f64, Physical>, mut f: F) {
+        let x0 = dst.loc.x.round().max(0.0) as usize;
+        let y0 = dst.loc.y.round().max(0.0) as usize;
+        let x1 = ((dst.loc.x + dst.size.w).round() as i32).clamp(0, self.size.w) as usize;
+        let y1 = ((dst.loc.y + dst.size.h).round() as i32).clamp(0, self.size.h) as usize;
+        for y in y0..y1 {
+            let row = match self
+                .buffer
+                .get_mut(y * self.stride..y * self.stride + x1 * 4)
+            {
+                Some(row) => row,
+                None => return,
+            };
+            // sampled at the center of each pixel
+            let v = (y as f64 + 0.5 - dst.loc.y) / dst.size.h;
+            for x in x0..x1 {
+                let u = (x as f64 + 0.5 - dst.loc.x) / dst.size.w;
+                f(&mut row[x * 4..x * 4 + 4], u, v);
+            }
+        }
+    }
+}
+
+/// Blends the straight `rgba` over the premultiplied BGRA `pixel`
+fn blend(pixel: &mut [u8], rgba: [u8; 4], alpha: f32) {
+    let a = (rgba[3] as f32 * alpha).round() as u32;
+    let keep = 255 - a;
+    for (out, c) in [(0, rgba[2]), (1, rgba[1]), (2, rgba[0])] {
+        pixel[out] = ((c as u32 * a + pixel[out] as u32 * keep) / 255) as u8;
+    }
+    pixel[3] = (a + pixel[3] as u32 * keep / 255) as u8;
+}
+
+/// Draws the parts of `image` given by `layout` into `target`, scaled to the nearest pixels
+pub(crate) fn draw_image(
+    target: &mut Target<'_>,
+    image: &DynamicImage,
+    layout: &[(Rectangle<i32, Buffer>, Rectangle<f64, Physical>)],
+    alpha: f32,
+) {
+    let rgba = tools::rgba8(image);
+    for (src, dst) in layout {
+        if src.size.w <= 0 || src.size.h <= 0 {
+            continue;
+        }
+        let x =
+            |u: f64| (src.loc.x + (u * src.size.w as f64) as i32).clamp(0, rgba.width() as i32 - 1);
+        let y = |v: f64| {
+            (src.loc.y + (v * src.size.h as f64) as i32).clamp(0, rgba.height() as i32 - 1)
+        };
+        target.for_each(*dst, |pixel, u, v| {
+            blend(pixel, rgba.get_pixel(x(u) as u32, y(v) as u32).0, alpha);
+        });
+    }
+}
+
+/// Darkens `area` of `target` by `dim`
+pub(crate) fn dim(target: &mut Target<'_>, area: Rectangle<f64, Physical>, dim: f32) {
+    target.for_each(area, |pixel, _, _| blend(pixel, [0, 0, 0, 255], dim));
+}
@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "config")]
+use serde::Deserialize;
+
+/// Sun elevation in degrees at sunrise and sunset, accounting for refraction and the sun's radius
+const HORIZON: f64 = -0.833;
+/// Sun elevation in degrees where dawn starts and dusk ends, civil twilight
+const TWILIGHT: f64 = -6.0;
+/// Resolution used to search for the next sunrise or sunset
+const STEP: Duration = Duration::from_secs(5 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A position on earth in degrees, north and east being positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+/// Source of the location used to follow the sun, e.g. geoclue, a config file or a fixed value
+pub trait LocationProvider: fmt::Debug {
+    /// Returns the current location, `None` if it is not known (yet)
+    fn location(&self) -> Option<Coordinates>;
+}
+
+impl LocationProvider for Coordinates {
+    fn location(&self) -> Option<Coordinates> {
+        Some(*self)
+    }
+}
+
+/// Position of the sun in the sky, in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    /// Angle above the horizon, negative at night
+    pub elevation: f64,
+    /// Angle from north towards east
+    pub azimuth: f64,
+}
+
+/// Part of the day, with the progress of dawn and dusk from `0.0` (night) to `1.0` (day)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DayPhase {
+    Night,
+    Dawn(f64),
+    Day,
+    Dusk(f64),
+}
+
+impl DayPhase {
+    /// How much of the day variant to show, `0.0` at night and `1.0` at day
+    pub fn daylight(&self) -> f64 {
+        match self {
+            DayPhase::Night => 0.0,
+            DayPhase::Dawn(amount) | DayPhase::Dusk(amount) => *amount,
+            DayPhase::Day => 1.0,
+        }
+    }
+}
+
+/// Computes the position of the sun at `coordinates` and `time`, accurate to about a degree
+pub fn sun_position(coordinates: Coordinates, time: DateTime<Utc>) -> SunPosition {
+    // days since J2000.0
+    let n = time.timestamp() as f64 / 86400.0 + 2440587.5 - 2451545.0;
+    let mean_longitude = (280.460 + 0.9856474 * n).rem_euclid(360.0);
+    let anomaly = (357.528 + 0.9856003 * n).rem_euclid(360.0).to_radians();
+    let ecliptic =
+        (mean_longitude + 1.915 * anomaly.sin() + 0.020 * (2.0 * anomaly).sin()).to_radians();
+    let obliquity = (23.439 - 0.0000004 * n).to_radians();
+
+    let right_ascension = (obliquity.cos() * ecliptic.sin()).atan2(ecliptic.cos());
+    let declination = (obliquity.sin() * ecliptic.sin()).asin();
+    let sidereal = (18.697374558 + 24.06570982441908 * n).rem_euclid(24.0) * 15.0;
+    let hour_angle = (sidereal + coordinates.longitude).to_radians() - right_ascension;
+
+    let latitude = coordinates.latitude.to_radians();
+    let elevation = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos())
+    .asin();
+    let azimuth = (-hour_angle.sin() * declination.cos()).atan2(
+        declination.sin() * latitude.cos() - declination.cos() * hour_angle.cos() * latitude.sin(),
+    );
+    SunPosition {
+        elevation: elevation.to_degrees(),
+        azimuth: azimuth.to_degrees().rem_euclid(360.0),
+    }
+}
+
+/// Returns the part of the day at `coordinates` and `time`
+pub fn day_phase(coordinates: Coordinates, time: DateTime<Utc>) -> DayPhase {
+    let elevation = sun_position(coordinates, time).elevation;
+    if elevation <= TWILIGHT {
+        return DayPhase::Night;
+    }
+    if elevation >= -TWILIGHT {
+        return DayPhase::Day;
+    }
+    let amount = (elevation - TWILIGHT) / (-2.0 * TWILIGHT);
+    let later = sun_position(coordinates, time + chrono::Duration::minutes(1)).elevation;
+    if later > elevation {
+        DayPhase::Dawn(amount)
+    } else {
+        DayPhase::Dusk(amount)
+    }
+}
+
+/// Returns whether the sun is up at `coordinates` now and how long until that changes
+///
+/// Near the poles the sun may not rise or set for days, then a day is returned.
+pub(crate) fn daylight(coordinates: Coordinates) -> (bool, Duration) {
+    let now = Utc::now();
+    let is_day = |time| sun_position(coordinates, time).elevation > HORIZON;
+    let day = is_day(now);
+    let step = chrono::Duration::from_std(STEP).unwrap();
+    let mut until = STEP;
+    while until <= DAY {
+        if is_day(now + chrono::Duration::from_std(until).unwrap()) != day {
+            // the change happened within the last step, narrowing it down to a minute is enough
+            let mut time = now + chrono::Duration::from_std(until).unwrap() - step;
+            while is_day(time) == day {
+                time = time + chrono::Duration::minutes(1);
+            }
+            return (day, (time - now).to_std().unwrap_or(STEP));
+        }
+        until += STEP;
+    }
+    (day, DAY)
+}
+
+thread_local! {
+    static LOCATION: RefCell<Option<Box<dyn LocationProvider>>> = RefCell::new(None);
+}
+
+/// Sets where dynamic wallpapers follow the sun from.
+///
+/// Without a location they switch between day and night at fixed hours.
+/// Like the texture cache, the provider is per thread.
+pub fn set_location_provider<L: LocationProvider + 'static>(provider: L) {
+    LOCATION.with(|location| *location.borrow_mut() = Some(Box::new(provider)));
+}
+
+/// Returns the location of the provider set by [`set_location_provider`]
+pub fn location() -> Option<Coordinates> {
+    LOCATION.with(|location| location.borrow().as_ref().and_then(|p| p.location()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const BERLIN: Coordinates = Coordinates {
+        latitude: 52.52,
+        longitude: 13.40,
+    };
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn sun_is_overhead_at_the_equator_on_equinox_noon() {
+        let sun = sun_position(Coordinates::new(0.0, 0.0), utc(3, 20, 12, 7));
+        assert!(sun.elevation > 85.0, "{:?}", sun);
+        let sun = sun_position(Coordinates::new(0.0, 0.0), utc(3, 20, 0, 7));
+        assert!(sun.elevation < -85.0, "{:?}", sun);
+    }
+
+    #[test]
+    fn sun_is_south_at_noon_in_the_north() {
+        let sun = sun_position(BERLIN, utc(6, 21, 11, 7));
+        assert!((sun.elevation - 60.9).abs() < 1.5, "{:?}", sun);
+        assert!((sun.azimuth - 180.0).abs() < 5.0, "{:?}", sun);
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_sets_in_the_west() {
+        assert!(sun_position(BERLIN, utc(3, 20, 8, 0)).azimuth < 180.0);
+        assert!(sun_position(BERLIN, utc(3, 20, 15, 0)).azimuth > 180.0);
+    }
+
+    #[test]
+    fn sun_never_sets_at_the_pole_in_summer() {
+        let pole = Coordinates::new(90.0, 0.0);
+        for hour in 0..24 {
+            let sun = sun_position(pole, utc(6, 21, hour, 0));
+            assert!((sun.elevation - 23.4).abs() < 1.0, "{:?}", sun);
+        }
+    }
+
+    #[test]
+    fn day_phases_follow_the_sun() {
+        assert_eq!(day_phase(BERLIN, utc(6, 21, 12, 0)), DayPhase::Day);
+        assert_eq!(day_phase(BERLIN, utc(6, 21, 23, 0)), DayPhase::Night);
+        assert!(matches!(
+            day_phase(BERLIN, utc(6, 21, 2, 43)),
+            DayPhase::Dawn(amount) if amount > 0.0 && amount < 1.0
+        ));
+        assert!(matches!(
+            day_phase(BERLIN, utc(6, 21, 19, 33)),
+            DayPhase::Dusk(amount) if amount > 0.0 && amount < 1.0
+        ));
+    }
+
+    #[test]
+    fn daylight_of_phases() {
+        assert_eq!(DayPhase::Night.daylight(), 0.0);
+        assert_eq!(DayPhase::Dawn(0.25).daylight(), 0.25);
+        assert_eq!(DayPhase::Dusk(0.75).daylight(), 0.75);
+        assert_eq!(DayPhase::Day.daylight(), 1.0);
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use std::time::Instant;
+
+use smithay::reexports::calloop::{
+    ping::{make_ping, Ping, PingSource},
+    timer::{TimeoutAction, Timer},
+    EventSource, Poll, PostAction, Readiness, Token, TokenFactory,
+};
+
+use crate::loader::Notify;
+
+/// The `WallpaperState` side of a `WallpaperSource`
+#[derive(Debug)]
+pub(crate) struct Waker {
+    ping: Ping,
+    deadline: Rc<Cell<Option<Instant>>>,
+}
+
+impl Waker {
+    /// Wakes the source from loader threads
+    pub fn notify(&self) -> Notify {
+        let ping = self.ping.clone();
+        Notify::new(move || ping.ping())
+    }
+
+    /// Updates when the wallpaper changes next, waking the source to re-arm its timer
+    pub fn set_deadline(&self, deadline: Option<Instant>) {
+        if self.deadline.replace(deadline) != deadline {
+            self.ping.ping();
+        }
+    }
+}
+
+/// Calloop event source of a `WallpaperState`, created by [`WallpaperState::event_source`](crate::WallpaperState::event_source)
+///
+/// The callback is called whenever the wallpaper has to be drawn again.
+#[derive(Debug)]
+pub struct WallpaperSource {
+    ping: PingSource,
+    timer: Option<Timer>,
+    deadline: Rc<Cell<Option<Instant>>>,
+}
+
+pub(crate) fn new(deadline: Option<Instant>) -> io::Result<(Waker, WallpaperSource)> {
+    let (ping, source) = make_ping()?;
+    let deadline = Rc::new(Cell::new(deadline));
+    Ok((
+        Waker {
+            ping,
+            deadline: deadline.clone(),
+        },
+        WallpaperSource {
+            ping: source,
+            timer: None,
+            deadline,
+        },
+    ))
+}
+
+impl EventSource for WallpaperSource {
+    type Event = ();
+    type Metadata = ();
+    type Ret = ();
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> io::Result<PostAction>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        let mut woken = false;
+        self.ping
+            .process_events(readiness, token, |_, _| woken = true)?;
+        if let Some(timer) = &mut self.timer {
+            timer.process_events(readiness, token, |_, _| {
+                woken = true;
+                TimeoutAction::Drop
+            })?;
+        }
+
+        if woken {
+            callback((), &mut ());
+            // re-arm the timer for the current deadline
+            Ok(PostAction::Reregister)
+        } else {
+            Ok(PostAction::Continue)
+        }
+    }
+
+    fn register(&mut self, poll: &mut Poll, token_factory: &mut TokenFactory) -> io::Result<()> {
+        self.ping.register(poll, token_factory)?;
+        self.timer = self.deadline.get().map(Timer::from_deadline);
+        if let Some(timer) = &mut self.timer {
+            timer.register(poll, token_factory)?;
+        }
+        Ok(())
+    }
+
+    fn reregister(&mut self, poll: &mut Poll, token_factory: &mut TokenFactory) -> io::Result<()> {
+        if let Some(mut timer) = self.timer.take() {
+            timer.unregister(poll)?;
+        }
+        self.ping.reregister(poll, token_factory)?;
+        self.timer = self.deadline.get().map(Timer::from_deadline);
+        if let Some(timer) = &mut self.timer {
+            timer.register(poll, token_factory)?;
+        }
+        Ok(())
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> io::Result<()> {
+        if let Some(mut timer) = self.timer.take() {
+            timer.unregister(poll)?;
+        }
+        self.ping.unregister(poll)
+    }
+}
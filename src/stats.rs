@@ -0,0 +1,124 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// Decodes done on this thread since the last [`take_decodes`]
+    static DECODES: Cell<Decodes> = Cell::new(Decodes::default());
+}
+
+/// What a wallpaper cost so far, e.g. to show in a debug overlay,
+/// see [`WallpaperState::stats`](crate::WallpaperState::stats) and [`WallpaperManager::stats`](crate::WallpaperManager::stats)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WallpaperStats {
+    /// Files and downloads decoded
+    pub decodes: u64,
+    /// Time spent decoding, on the loader threads unless loading is synchronous
+    pub decode_time: Duration,
+    /// Images and animation frames imported into textures
+    pub uploads: u64,
+    /// Time spent importing images into textures on the render thread
+    pub upload_time: Duration,
+    /// Bytes of the textures currently held, in their format and including mipmaps
+    pub texture_bytes: usize,
+    /// Frames drawn with damage
+    pub frames_drawn: u64,
+    /// Images shown with a texture the cache already held
+    pub cache_hits: u64,
+    /// Images the cache held no texture for, which had to be imported
+    pub cache_misses: u64,
+}
+
+impl WallpaperStats {
+    /// Returns the sum of both stats
+    pub(crate) fn add(self, other: Self) -> Self {
+        Self {
+            decodes: self.decodes + other.decodes,
+            decode_time: self.decode_time + other.decode_time,
+            uploads: self.uploads + other.uploads,
+            upload_time: self.upload_time + other.upload_time,
+            texture_bytes: self.texture_bytes + other.texture_bytes,
+            frames_drawn: self.frames_drawn + other.frames_drawn,
+            cache_hits: self.cache_hits + other.cache_hits,
+            cache_misses: self.cache_misses + other.cache_misses,
+        }
+    }
+}
+
+/// Number and duration of decodes, handed from the loader threads to the state
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Decodes {
+    count: u64,
+    time: Duration,
+}
+
+/// Adds the time since `started` to the decodes of this thread
+pub(crate) fn record_decode(started: Instant) {
+    DECODES.with(|decodes| {
+        let current = decodes.get();
+        decodes.set(Decodes {
+            count: current.count + 1,
+            time: current.time + started.elapsed(),
+        });
+    });
+}
+
+/// Returns the decodes of this thread since the last call
+pub(crate) fn take_decodes() -> Decodes {
+    DECODES.with(|decodes| decodes.take())
+}
+
+/// Counters of a single [`WallpaperState`](crate::WallpaperState), shared with its frames
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    decodes: Cell<u64>,
+    decode_time: Cell<Duration>,
+    uploads: Cell<u64>,
+    upload_time: Cell<Duration>,
+    frames_drawn: Cell<u64>,
+    cache_hits: Cell<u64>,
+    cache_misses: Cell<u64>,
+}
+
+impl Counters {
+    /// Adds decodes taken from a loader thread
+    pub fn record_decodes(&self, decodes: Decodes) {
+        self.decodes.set(self.decodes.get() + decodes.count);
+        self.decode_time.set(self.decode_time.get() + decodes.time);
+    }
+
+    /// Adds the time since `started` to the upload time
+    pub fn record_upload(&self, started: Instant) {
+        self.uploads.set(self.uploads.get() + 1);
+        self.upload_time
+            .set(self.upload_time.get() + started.elapsed());
+    }
+
+    /// Counts a frame drawn
+    pub fn record_frame(&self) {
+        self.frames_drawn.set(self.frames_drawn.get() + 1);
+    }
+
+    /// Counts a lookup of the texture cache
+    pub fn record_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.set(counter.get() + 1);
+    }
+
+    /// Returns the totals so far, with the `texture_bytes` currently held
+    pub fn snapshot(&self, texture_bytes: usize) -> WallpaperStats {
+        WallpaperStats {
+            decodes: self.decodes.get(),
+            decode_time: self.decode_time.get(),
+            uploads: self.uploads.get(),
+            upload_time: self.upload_time.get(),
+            texture_bytes,
+            frames_drawn: self.frames_drawn.get(),
+            cache_hits: self.cache_hits.get(),
+            cache_misses: self.cache_misses.get(),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    DynamicImage, ImageError, ImageResult, RgbaImage,
+};
+use smithay::utils::{Physical, Size};
+
+use crate::ScalingMode;
+
+/// Returns whether `path` looks like an SVG file
+pub(crate) fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            ext.eq_ignore_ascii_case("svg") || ext.eq_ignore_ascii_case("svgz")
+        })
+}
+
+fn decoding_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("SVG".into()), err))
+}
+
+/// Rasterizes the SVG at `path` so it is drawn 1:1 on an output of `size` with `mode`.
+///
+/// Without a `size` the SVG is rendered at its intrinsic size.
+pub(crate) fn render(
+    path: &Path,
+    size: Option<Size<i32, Physical>>,
+    mode: ScalingMode,
+) -> ImageResult<DynamicImage> {
+    let data = std::fs::read(path)?;
+    let tree =
+        usvg::Tree::from_data(&data, &usvg::Options::default().to_ref()).map_err(decoding_error)?;
+
+    let intrinsic = tree.svg_node().size;
+    let (w, h) = (intrinsic.width(), intrinsic.height());
+    let (sx, sy) = match size {
+        Some(size) => {
+            let (scale_x, scale_y) = (size.w as f64 / w, size.h as f64 / h);
+            match mode {
+                ScalingMode::Fill => (scale_x.max(scale_y), scale_x.max(scale_y)),
+                ScalingMode::Fit => (scale_x.min(scale_y), scale_x.min(scale_y)),
+                ScalingMode::Stretch => (scale_x, scale_y),
+                ScalingMode::Center | ScalingMode::Tile | ScalingMode::PixelArt => (1.0, 1.0),
+            }
+        }
+        None => (1.0, 1.0),
+    };
+
+    let (width, height) = (
+        ((w * sx).round() as u32).max(1),
+        ((h * sy).round() as u32).max(1),
+    );
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or_else(|| decoding_error("invalid size"))?;
+    resvg::render(
+        &tree,
+        usvg::FitTo::Original,
+        tiny_skia::Transform::from_scale(sx as f32, sy as f32),
+        pixmap.as_mut(),
+    )
+    .ok_or_else(|| decoding_error("failed to render"))?;
+
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(width, height, data)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| decoding_error("invalid pixmap"))
+}
+
+/// An SVG wallpaper and the output size and scaling it was last rasterized for
+#[derive(Debug)]
+pub(crate) struct Svg {
+    pub path: PathBuf,
+    pub rendered: Option<(Size<i32, Physical>, ScalingMode)>,
+}
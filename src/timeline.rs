@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDateTime, NaiveTime, Timelike};
+
+use crate::Transition;
+
+/// Checked again after this time if the next switch cannot be computed, e.g. around DST changes
+const RETRY: Duration = Duration::from_secs(60);
+/// Light variants of dynamic wallpapers are shown between these hours without a location
+const DAY_HOURS: (u32, u32) = (7, 19);
+const DAY_SECS: u32 = 24 * 60 * 60;
+
+/// Returns whether it is day and how long until that changes
+///
+/// Follows the sun if a location is known, otherwise day is between fixed hours of local time.
+pub(crate) fn daylight() -> (bool, Duration) {
+    if let Some(coordinates) = crate::solar::location() {
+        return crate::solar::daylight(coordinates);
+    }
+    let secs = Local::now().num_seconds_from_midnight();
+    let (start, end) = (DAY_HOURS.0 * 3600, DAY_HOURS.1 * 3600);
+    let (day, until) = if secs >= start && secs < end {
+        (true, end - secs)
+    } else if secs < start {
+        (false, start - secs)
+    } else {
+        (false, DAY_SECS - secs + start)
+    };
+    (day, Duration::from_secs(until.max(1) as u64))
+}
+
+/// Images shown at certain times of the day
+///
+/// Each image is shown from its time until the time of the next one, the last image is shown
+/// until the first one of the next day.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    entries: Vec<(NaiveTime, PathBuf)>,
+    transition: Transition,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            transition: Transition::Fade(Duration::from_secs(2)),
+        }
+    }
+}
+
+impl Timeline {
+    /// Creates an empty `Timeline` fading between its images over two seconds
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Morning at 6:00, noon at 12:00, evening at 18:00 and night at 21:00
+    pub fn day_phases<P: Into<PathBuf>>(morning: P, noon: P, evening: P, night: P) -> Self {
+        Self::new()
+            .at(6, 0, morning)
+            .at(12, 0, noon)
+            .at(18, 0, evening)
+            .at(21, 0, night)
+    }
+
+    /// Shows the image at `path` from `hour:minute` local time on
+    pub fn at<P: Into<PathBuf>>(mut self, hour: u32, minute: u32, path: P) -> Self {
+        let time = NaiveTime::from_hms_opt(hour % 24, minute % 60, 0).unwrap();
+        self.entries.push((time, path.into()));
+        self.entries.sort_by_key(|(time, _)| *time);
+        self
+    }
+
+    /// Sets the `Transition` used when the image changes
+    pub fn transition(mut self, transition: Transition) -> Self {
+        self.transition = transition;
+        self
+    }
+
+    /// Returns the index of the entry shown at `now` and when the following entry starts,
+    /// if that date can be represented
+    fn current(&self, now: NaiveDateTime) -> Option<(usize, Option<NaiveDateTime>)> {
+        let (first, _) = self.entries.first()?;
+        let time = now.time();
+        let index = self
+            .entries
+            .iter()
+            .rposition(|(start, _)| *start <= time)
+            .unwrap_or(self.entries.len() - 1);
+        let next = match self.entries.iter().find(|(start, _)| *start > time) {
+            Some((start, _)) => Some(now.date().and_time(*start)),
+            None => now.date().succ_opt().map(|date| date.and_time(*first)),
+        };
+        Some((index, next))
+    }
+}
+
+/// A `Timeline` set on a `WallpaperState` and the entry it currently shows
+#[derive(Debug)]
+pub(crate) struct Schedule {
+    timeline: Timeline,
+    shown: Option<usize>,
+    /// `None` until it was first polled
+    next: Option<Instant>,
+}
+
+impl Schedule {
+    pub fn new(timeline: Timeline) -> Self {
+        Self {
+            timeline,
+            shown: None,
+            next: None,
+        }
+    }
+
+    pub fn transition(&self) -> Transition {
+        self.timeline.transition
+    }
+
+    /// When the next entry starts, `now` if the schedule was not polled yet
+    pub fn deadline(&self, now: Instant) -> Instant {
+        self.next.unwrap_or(now)
+    }
+
+    /// Returns the image to show once the next entry started at `now`.
+    ///
+    /// The entry is picked by the local time, `now` only decides when it is looked up again.
+    pub fn poll(&mut self, now: Instant) -> Option<PathBuf> {
+        if self.next.map_or(false, |next| now < next) {
+            return None;
+        }
+
+        let local = Local::now().naive_local();
+        let (index, next) = self.timeline.current(local)?;
+        self.next = Some(
+            now + next
+                .and_then(|next| (next - local).to_std().ok())
+                .unwrap_or(RETRY),
+        );
+        if self.shown == Some(index) {
+            return None;
+        }
+        self.shown = Some(index);
+        Some(self.timeline.entries[index].1.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, 3, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .unwrap()
+    }
+
+    fn phases() -> Timeline {
+        Timeline::day_phases("morning", "noon", "evening", "night")
+    }
+
+    #[test]
+    fn shows_the_latest_entry_started() {
+        assert_eq!(
+            phases().current(at(10, 6, 0)),
+            Some((0, Some(at(10, 12, 0))))
+        );
+        assert_eq!(
+            phases().current(at(10, 13, 30)),
+            Some((1, Some(at(10, 18, 0))))
+        );
+        assert_eq!(
+            phases().current(at(10, 20, 59)),
+            Some((2, Some(at(10, 21, 0))))
+        );
+    }
+
+    #[test]
+    fn last_entry_lasts_until_the_first_of_the_next_day() {
+        assert_eq!(
+            phases().current(at(10, 22, 0)),
+            Some((3, Some(at(11, 6, 0))))
+        );
+        assert_eq!(
+            phases().current(at(10, 3, 0)),
+            Some((3, Some(at(10, 6, 0))))
+        );
+    }
+
+    #[test]
+    fn entries_are_sorted_by_time() {
+        let timeline = Timeline::new().at(18, 0, "evening").at(8, 30, "morning");
+        assert_eq!(timeline.entries[0].1, PathBuf::from("morning"));
+        assert_eq!(
+            timeline.current(at(10, 9, 0)),
+            Some((0, Some(at(10, 18, 0))))
+        );
+    }
+
+    #[test]
+    fn no_next_switch_after_the_last_date() {
+        let now = NaiveDateTime::MAX;
+        assert_eq!(phases().current(now), Some((3, None)));
+    }
+
+    #[test]
+    fn empty_timeline_shows_nothing() {
+        assert_eq!(Timeline::new().current(at(10, 12, 0)), None);
+    }
+}
@@ -1,40 +1,417 @@
-use image::{ImageBuffer, Rgba};
-use smithay::backend::renderer::gles2::{Gles2Error, Gles2Renderer, Gles2Texture};
+use image::{DynamicImage, ImageBuffer, Rgba, Rgba32FImage, RgbaImage};
+use std::any::Any;
+use std::borrow::Cow;
+use std::ffi::CStr;
 
-pub fn import_bitmap<C: std::ops::Deref<Target = [u8]>>(
-    renderer: &mut Gles2Renderer,
+use smithay::{
+    backend::allocator::{dmabuf::Dmabuf, Buffer as _, Fourcc},
+    backend::renderer::{
+        gles2::{ffi, Gles2Renderer, Gles2Texture},
+        ImportMem, Renderer, Texture,
+    },
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Size},
+};
+
+/// Texture formats of GLES 3, which the bindings of smithay do not include
+const RGBA16F: ffi::types::GLenum = 0x881A;
+pub const SRGB8_ALPHA8: ffi::types::GLenum = 0x8C43;
+
+fn buffer_size<C: std::ops::Deref<Target = [u8]>>(
     image: &ImageBuffer<Rgba<u8>, C>,
-    size: (i32, i32),
-) -> Result<Gles2Texture, Gles2Error> {
-    use smithay::backend::renderer::gles2::ffi;
-
-    renderer.with_context(|renderer, gl| unsafe {
-        let mut tex = 0;
-        gl.GenTextures(1, &mut tex);
-        gl.BindTexture(ffi::TEXTURE_2D, tex);
-        gl.TexParameteri(
-            ffi::TEXTURE_2D,
-            ffi::TEXTURE_WRAP_S,
-            ffi::CLAMP_TO_EDGE as i32,
-        );
-        gl.TexParameteri(
-            ffi::TEXTURE_2D,
-            ffi::TEXTURE_WRAP_T,
-            ffi::CLAMP_TO_EDGE as i32,
-        );
-        gl.TexImage2D(
-            ffi::TEXTURE_2D,
-            0,
-            ffi::RGBA as i32,
-            image.width() as i32,
-            image.height() as i32,
-            0,
-            ffi::RGBA,
-            ffi::UNSIGNED_BYTE as u32,
-            image.as_ptr() as *const _,
-        );
-        gl.BindTexture(ffi::TEXTURE_2D, 0);
-
-        Gles2Texture::from_raw(renderer, tex, size.into())
+) -> Size<i32, Buffer> {
+    (image.width() as i32, image.height() as i32).into()
+}
+
+pub fn import_bitmap<R: ImportMem, C: std::ops::Deref<Target = [u8]>>(
+    renderer: &mut R,
+    image: &ImageBuffer<Rgba<u8>, C>,
+) -> Result<<R as Renderer>::TextureId, <R as Renderer>::Error> {
+    renderer.import_memory(&**image, buffer_size(image), false)
+}
+
+/// Imports `image` into a half float texture, keeping colors brighter than white for tone mapping.
+///
+/// Returns `None` unless `renderer` is a `Gles2Renderer` on GLES 3, which is needed for float textures.
+pub fn import_float<R>(renderer: &mut R, image: &Rgba32FImage) -> Option<<R as Renderer>::TextureId>
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let size = (image.width() as i32, image.height() as i32).into();
+    import_gles3(
+        renderer,
+        size,
+        RGBA16F,
+        ffi::FLOAT,
+        image.as_ptr() as *const _,
+    )
+}
+
+/// Imports `image` into an sRGB texture, which is decoded to linear light when sampled,
+/// so it is also filtered in linear light.
+///
+/// Returns `None` unless `renderer` is a `Gles2Renderer` on GLES 3, which is needed for sRGB textures.
+pub fn import_srgb<R>(renderer: &mut R, image: &RgbaImage) -> Option<<R as Renderer>::TextureId>
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let size = buffer_size(image);
+    import_gles3(
+        renderer,
+        size,
+        SRGB8_ALPHA8,
+        ffi::UNSIGNED_BYTE,
+        image.as_ptr() as *const _,
+    )
+}
+
+/// Creates a texture of `size` in the `internal` format from RGBA `data` of type `kind`
+fn import_gles3<R>(
+    renderer: &mut R,
+    size: Size<i32, Buffer>,
+    internal: ffi::types::GLenum,
+    kind: ffi::types::GLenum,
+    data: *const std::ffi::c_void,
+) -> Option<<R as Renderer>::TextureId>
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let gles = (renderer as &mut dyn Any).downcast_mut::<Gles2Renderer>()?;
+    let texture = gles
+        .with_context(|renderer, gl| unsafe {
+            if gl_string(gl, ffi::VERSION).starts_with("OpenGL ES 2") {
+                return None;
+            }
+            let mut tex = 0;
+            gl.GenTextures(1, &mut tex);
+            gl.BindTexture(ffi::TEXTURE_2D, tex);
+            gl.TexImage2D(
+                ffi::TEXTURE_2D,
+                0,
+                internal as i32,
+                size.w,
+                size.h,
+                0,
+                ffi::RGBA,
+                kind,
+                data as *const _,
+            );
+            gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+            gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+            gl.TexParameteri(
+                ffi::TEXTURE_2D,
+                ffi::TEXTURE_WRAP_S,
+                ffi::CLAMP_TO_EDGE as i32,
+            );
+            gl.TexParameteri(
+                ffi::TEXTURE_2D,
+                ffi::TEXTURE_WRAP_T,
+                ffi::CLAMP_TO_EDGE as i32,
+            );
+            gl.BindTexture(ffi::TEXTURE_2D, 0);
+            Some(Gles2Texture::from_raw(renderer, tex, size))
+        })
+        .ok()??;
+    // `R` is a `Gles2Renderer`, so its textures are `Gles2Texture`s
+    (Box::new(texture) as Box<dyn Any>)
+        .downcast()
+        .ok()
+        .map(|texture| *texture)
+}
+
+/// Textures holding the parts of an image too large for a single one, with the part each holds
+pub type Tiles<T> = Vec<(Rectangle<i32, Buffer>, T)>;
+
+/// Imports `image` as a grid of textures of at most `max` pixels along each side
+pub fn import_tiled<R: ImportMem>(
+    renderer: &mut R,
+    image: &RgbaImage,
+    max: u32,
+) -> Result<Tiles<<R as Renderer>::TextureId>, <R as Renderer>::Error> {
+    let mut tiles = Vec::new();
+    for (part, tile) in split(image, max) {
+        tiles.push((part, import_bitmap(renderer, &tile)?));
+    }
+    Ok(tiles)
+}
+
+/// Replaces the contents of `tiles` with the parts of `image`, which must have the same size
+pub fn update_tiled<R: ImportMem>(
+    renderer: &mut R,
+    tiles: &Tiles<<R as Renderer>::TextureId>,
+    image: &RgbaImage,
+) -> Result<(), <R as Renderer>::Error> {
+    let max = tiles
+        .first()
+        .map_or(1, |(part, _)| part.size.w.max(part.size.h) as u32);
+    for ((_, texture), (_, tile)) in tiles.iter().zip(split(image, max)) {
+        update_bitmap(renderer, texture, &tile)?;
+    }
+    Ok(())
+}
+
+/// Cuts `image` into parts of at most `max` pixels along each side, row by row
+fn split(
+    image: &RgbaImage,
+    max: u32,
+) -> impl Iterator<Item = (Rectangle<i32, Buffer>, RgbaImage)> + '_ {
+    let max = max.max(1);
+    let (width, height) = image.dimensions();
+    (0..height).step_by(max as usize).flat_map(move |y| {
+        (0..width).step_by(max as usize).map(move |x| {
+            let (w, h) = (max.min(width - x), max.min(height - y));
+            (
+                Rectangle::from_loc_and_size((x as i32, y as i32), (w as i32, h as i32)),
+                image::imageops::crop_imm(image, x, y, w, h).to_image(),
+            )
+        })
     })
 }
+
+/// Returns the pixels of `image` in RGBA, only converting them if the loader did not already
+pub fn rgba8(image: &DynamicImage) -> Cow<'_, RgbaImage> {
+    match image.as_rgba8() {
+        Some(rgba) => Cow::Borrowed(rgba),
+        None => Cow::Owned(image.to_rgba8()),
+    }
+}
+
+/// Returns `true` if `image` has no transparent pixels, looking at every pixel if it has an alpha channel
+pub fn is_opaque(image: &DynamicImage) -> bool {
+    match image {
+        DynamicImage::ImageLumaA8(image) => image.pixels().all(|pixel| pixel[1] == u8::MAX),
+        DynamicImage::ImageLumaA16(image) => image.pixels().all(|pixel| pixel[1] == u16::MAX),
+        DynamicImage::ImageRgba8(image) => image.pixels().all(|pixel| pixel[3] == u8::MAX),
+        DynamicImage::ImageRgba16(image) => image.pixels().all(|pixel| pixel[3] == u16::MAX),
+        DynamicImage::ImageRgba32F(image) => image.pixels().all(|pixel| pixel[3] >= 1.0),
+        image => !image.color().has_alpha(),
+    }
+}
+
+/// Replaces the contents of `texture` with `image`, which must have the same size
+pub fn update_bitmap<R: ImportMem, C: std::ops::Deref<Target = [u8]>>(
+    renderer: &mut R,
+    texture: &<R as Renderer>::TextureId,
+    image: &ImageBuffer<Rgba<u8>, C>,
+) -> Result<(), <R as Renderer>::Error> {
+    renderer.update_memory(
+        texture,
+        &**image,
+        Rectangle::from_loc_and_size((0, 0), buffer_size(image)),
+    )
+}
+
+/// Replaces `region` of `texture` with the same region of `image`, which must have the size of `texture`
+pub fn update_region<R: ImportMem>(
+    renderer: &mut R,
+    texture: &<R as Renderer>::TextureId,
+    image: &RgbaImage,
+    region: Rectangle<i32, Buffer>,
+) -> Result<(), <R as Renderer>::Error> {
+    renderer.update_memory(texture, &**image, region)
+}
+
+/// Generates the mipmaps of `texture`, so it can be sampled trilinearly when drawn smaller.
+///
+/// Returns `false` if the renderer cannot, like GLES 2 without support for
+/// mipmaps of textures whose size is no power of two.
+pub fn generate_mipmaps<R>(renderer: &mut R, texture: &<R as Renderer>::TextureId) -> bool
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let renderer = match (renderer as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+        Some(renderer) => renderer,
+        None => return false,
+    };
+    let texture = match (texture as &dyn Any).downcast_ref::<Gles2Texture>() {
+        Some(texture) => texture,
+        None => return false,
+    };
+    let power_of_two = is_power_of_two(texture);
+    renderer
+        .with_context(|_renderer, gl| unsafe {
+            if !power_of_two && !supports_npot(gl) {
+                return false;
+            }
+            gl.BindTexture(ffi::TEXTURE_2D, texture.tex_id());
+            gl.GenerateMipmap(ffi::TEXTURE_2D);
+            gl.BindTexture(ffi::TEXTURE_2D, 0);
+            true
+        })
+        .unwrap_or(false)
+}
+
+/// Returns `true` if `texture` can be drawn repeatedly with `GL_REPEAT`
+pub fn can_repeat<R>(renderer: &mut R, texture: &<R as Renderer>::TextureId) -> bool
+where
+    R: Renderer + 'static,
+    <R as Renderer>::TextureId: 'static,
+{
+    let renderer = match (renderer as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+        Some(renderer) => renderer,
+        None => return false,
+    };
+    let power_of_two = match (texture as &dyn Any).downcast_ref::<Gles2Texture>() {
+        Some(texture) => is_power_of_two(texture),
+        None => return false,
+    };
+    power_of_two
+        || renderer
+            .with_context(|_renderer, gl| unsafe { supports_npot(gl) })
+            .unwrap_or(false)
+}
+
+fn is_power_of_two(texture: &Gles2Texture) -> bool {
+    let size = texture.size();
+    (size.w as u32).is_power_of_two() && (size.h as u32).is_power_of_two()
+}
+
+/// GLES 3 supports mipmaps and repeating of textures of any size,
+/// GLES 2 only with the `GL_OES_texture_npot` extension
+unsafe fn supports_npot(gl: &ffi::Gles2) -> bool {
+    !gl_string(gl, ffi::VERSION).starts_with("OpenGL ES 2")
+        || has_extension(gl, "GL_OES_texture_npot")
+}
+
+/// Returns the string `name` of the current context, empty if it has none
+pub unsafe fn gl_string(gl: &ffi::Gles2, name: ffi::types::GLenum) -> String {
+    let string = gl.GetString(name);
+    if string.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(string as *const _)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Returns `true` if the current context supports `extension`
+pub unsafe fn has_extension(gl: &ffi::Gles2, extension: &str) -> bool {
+    gl_string(gl, ffi::EXTENSIONS)
+        .split(' ')
+        .any(|supported| supported == extension)
+}
+
+/// Returns the maximum texture width and height, if the renderer exposes it
+pub fn max_texture_size<R: Renderer + 'static>(renderer: &mut R) -> Option<u32> {
+    let renderer = (renderer as &mut dyn Any).downcast_mut::<Gles2Renderer>()?;
+    renderer
+        .with_context(|_renderer, gl| unsafe {
+            let mut size = 0;
+            gl.GetIntegerv(ffi::MAX_TEXTURE_SIZE, &mut size);
+            size as u32
+        })
+        .ok()
+}
+
+/// Returns the bytes of a texture of `size`, a third more with mipmaps
+pub fn texture_bytes(size: Size<i32, Buffer>, bytes_per_pixel: usize, mipmaps: bool) -> usize {
+    let bytes = size.w.max(0) as usize * size.h.max(0) as usize * bytes_per_pixel;
+    if mipmaps {
+        bytes * 4 / 3
+    } else {
+        bytes
+    }
+}
+
+/// Returns the bytes of the planes of `dmabuf`, the chroma planes of NV12 have half its height
+pub fn dmabuf_bytes(dmabuf: &Dmabuf) -> usize {
+    let height = dmabuf.size().h.max(0) as usize;
+    let subsampled = dmabuf.format().code == Fourcc::Nv12;
+    dmabuf
+        .strides()
+        .enumerate()
+        .map(|(plane, stride)| match plane {
+            0 => stride as usize * height,
+            _ if subsampled => stride as usize * ((height + 1) / 2),
+            _ => stride as usize * height,
+        })
+        .sum()
+}
+
+/// Converts `area` to physical coordinates at a possibly fractional `scale`.
+///
+/// The edges are rounded instead of the size, so adjacent areas leave no gaps,
+/// and edges within a pixel of the buffer of `size` are moved onto it.
+pub fn to_physical(
+    area: Rectangle<i32, Logical>,
+    size: Size<i32, Physical>,
+    scale: f64,
+) -> Rectangle<i32, Physical> {
+    let snap = scale.ceil() as i32;
+    let edge = |logical: i32, end: i32| {
+        let physical = (logical as f64 * scale).round() as i32;
+        if (end - physical).abs() <= snap {
+            end
+        } else {
+            physical
+        }
+    };
+    let (x0, y0) = (edge(area.loc.x, 0), edge(area.loc.y, 0));
+    let (x1, y1) = (
+        edge(area.loc.x + area.size.w, size.w),
+        edge(area.loc.y + area.size.h, size.h),
+    );
+    Rectangle::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0))
+}
+
+/// Converts `damage` relative to `origin` to physical coordinates, growing it to whole pixels
+pub fn damage_to_physical(
+    damage: &[Rectangle<i32, Logical>],
+    origin: Point<i32, Logical>,
+    scale: f64,
+) -> Vec<Rectangle<i32, Physical>> {
+    damage
+        .iter()
+        .map(|rect| {
+            let (x0, y0) = (
+                ((origin.x + rect.loc.x) as f64 * scale).floor() as i32,
+                ((origin.y + rect.loc.y) as f64 * scale).floor() as i32,
+            );
+            let (x1, y1) = (
+                ((origin.x + rect.loc.x + rect.size.w) as f64 * scale).ceil() as i32,
+                ((origin.y + rect.loc.y + rect.size.h) as f64 * scale).ceil() as i32,
+            );
+            Rectangle::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0))
+        })
+        .collect()
+}
+
+/// Returns the parts of `damage` within `dst`, relative to it, as the renderer clips textures drawn into `dst`
+pub fn damage_in(
+    damage: &[Rectangle<i32, Physical>],
+    dst: Rectangle<f64, Physical>,
+) -> Vec<Rectangle<i32, Buffer>> {
+    damage
+        .iter()
+        .filter_map(|rect| {
+            let x0 = (rect.loc.x as f64).max(dst.loc.x) - dst.loc.x;
+            let y0 = (rect.loc.y as f64).max(dst.loc.y) - dst.loc.y;
+            let x1 = ((rect.loc.x + rect.size.w) as f64).min(dst.loc.x + dst.size.w) - dst.loc.x;
+            let y1 = ((rect.loc.y + rect.size.h) as f64).min(dst.loc.y + dst.size.h) - dst.loc.y;
+            let (x0, y0, x1, y1) = (
+                x0.floor() as i32,
+                y0.floor() as i32,
+                x1.ceil() as i32,
+                y1.ceil() as i32,
+            );
+            (x1 > x0 && y1 > y0).then(|| Rectangle::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0)))
+        })
+        .collect()
+}
+
+/// Converts `area` back to logical coordinates, growing it to whole logical pixels
+pub fn to_logical(area: Rectangle<i32, Physical>, scale: f64) -> Rectangle<i32, Logical> {
+    let (x0, y0) = (
+        (area.loc.x as f64 / scale).floor() as i32,
+        (area.loc.y as f64 / scale).floor() as i32,
+    );
+    let (x1, y1) = (
+        ((area.loc.x + area.size.w) as f64 / scale).ceil() as i32,
+        ((area.loc.y + area.size.h) as f64 / scale).ceil() as i32,
+    );
+    Rectangle::from_loc_and_size((x0, y0), (x1 - x0, y1 - y0))
+}
@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::CachedTexture;
+
+/// How the wallpaper changes from one image to the next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    /// Swap the image instantly
+    None,
+    /// Linearly blend the old image into the new one over the given duration
+    Fade(Duration),
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Transition::None
+    }
+}
+
+/// The previous image of a running transition
+#[derive(Debug, Clone)]
+pub(crate) struct Fading {
+    pub image: Rc<Option<DynamicImage>>,
+    pub texture: Rc<RefCell<Option<CachedTexture>>>,
+    pub frame: usize,
+    pub progress: f32,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Fading {
+    pub fn new(
+        image: Rc<Option<DynamicImage>>,
+        texture: Rc<RefCell<Option<CachedTexture>>>,
+        frame: usize,
+        duration: Duration,
+        now: Instant,
+    ) -> Self {
+        Self {
+            image,
+            texture,
+            frame,
+            progress: 0.0,
+            started: now,
+            duration,
+        }
+    }
+
+    /// Updates the progress to `now`, returns `false` once the transition is finished
+    pub fn update(&mut self, now: Instant) -> bool {
+        self.progress = if self.duration.is_zero() {
+            1.0
+        } else {
+            let elapsed = now.saturating_duration_since(self.started);
+            (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+        };
+        self.progress < 1.0
+    }
+}
+
+/// A value following its target linearly over a duration, like the dimming of the wallpaper
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Animated {
+    from: f32,
+    to: f32,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Default for Animated {
+    fn default() -> Self {
+        Self {
+            from: 0.0,
+            to: 0.0,
+            started: Instant::now(),
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+impl Animated {
+    /// Returns the value at `now`
+    pub fn value(&self, now: Instant) -> f32 {
+        if self.duration.is_zero() {
+            return self.to;
+        }
+        let elapsed = now.saturating_duration_since(self.started);
+        let progress = (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        self.from + (self.to - self.from) * progress
+    }
+
+    /// Returns the value the animation ends at
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    /// Returns `true` while the value is still changing at `now`
+    pub fn animating(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) < self.duration
+    }
+
+    /// Starts moving from the value at `now` to `to` over `duration`
+    pub fn animate(&mut self, to: f32, duration: Duration, now: Instant) {
+        *self = Self {
+            from: self.value(now),
+            to,
+            started: now,
+            duration,
+        };
+    }
+}
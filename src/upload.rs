@@ -0,0 +1,271 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::ptr;
+use std::rc::{Rc, Weak};
+
+use image::DynamicImage;
+use smithay::{
+    backend::renderer::{
+        gles2::{ffi, Gles2Renderer, Gles2Texture},
+        Renderer,
+    },
+    utils::{Buffer, Size},
+};
+
+use crate::{cache, context, tools, CachedTexture, WallpaperError};
+
+/// Images of fewer bytes are imported at once, streaming them is not worth the frames it takes
+const MIN_BYTES: usize = 4 * 1024 * 1024;
+/// Bytes copied into the pixel buffer per frame, a 4K image streams in over four frames
+const CHUNK: usize = 8 * 1024 * 1024;
+
+/// An image copied into a pixel buffer object a chunk per frame,
+/// then transferred into its texture by the GPU
+#[derive(Debug)]
+struct Streaming {
+    target: Weak<RefCell<Option<CachedTexture>>>,
+    /// EGL context the pixel buffer and texture belong to
+    context: usize,
+    frame: usize,
+    linear: bool,
+    size: Size<i32, Buffer>,
+    pbo: ffi::types::GLuint,
+    tex: ffi::types::GLuint,
+    copied: usize,
+    /// Signaled once the GPU copied the pixel buffer into the texture
+    fence: Option<ffi::types::GLsync>,
+}
+
+impl Streaming {
+    /// Deletes the pixel buffer, fence and texture, which was not handed out yet
+    unsafe fn delete(&self, gl: &ffi::Gles2) {
+        if let Some(fence) = self.fence {
+            gl.DeleteSync(fence);
+        }
+        gl.DeleteBuffers(1, &self.pbo);
+        gl.DeleteTextures(1, &self.tex);
+    }
+}
+
+/// Streams large images into textures without stalling a frame, see
+/// [`WallpaperState::set_streaming_upload`](crate::WallpaperState::set_streaming_upload)
+#[derive(Debug, Default)]
+pub(crate) struct Uploader {
+    streaming: RefCell<Option<Streaming>>,
+}
+
+impl Uploader {
+    /// Streams frame `index` of `image` into `texture` over the next frames.
+    ///
+    /// Returns `true` while the upload is in progress and `texture` stays empty.
+    /// Images the renderer cannot stream, because it is no `Gles2Renderer` on GLES 3
+    /// or another renderer is streaming, and small, HDR or oversized images are left
+    /// to the regular import and return `false`.
+    pub fn stream<R>(
+        &self,
+        r: &mut R,
+        image: &DynamicImage,
+        texture: &Rc<RefCell<Option<CachedTexture>>>,
+        index: usize,
+        linear: bool,
+    ) -> Result<bool, WallpaperError>
+    where
+        R: Renderer + 'static,
+        <R as Renderer>::TextureId: 'static,
+    {
+        if texture.borrow().is_some() {
+            return Ok(false);
+        }
+        let rgba = match image.as_rgba8() {
+            Some(rgba) if rgba.len() >= MIN_BYTES => rgba,
+            _ => return Ok(false),
+        };
+        let max = tools::max_texture_size(r).unwrap_or(0);
+        if image.width() > max || image.height() > max {
+            return Ok(false);
+        }
+        let r = match (r as &mut dyn Any).downcast_mut::<Gles2Renderer>() {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+
+        let size = Size::from((image.width() as i32, image.height() as i32));
+        let context = context::id(&*r);
+        let mut streaming = self.streaming.borrow_mut();
+        // the objects of an upload cannot be deleted in the context of another GPU
+        if streaming
+            .as_ref()
+            .map_or(false, |streaming| streaming.context != context)
+        {
+            return Ok(false);
+        }
+        let finished = r
+            .with_context(|renderer, gl| unsafe {
+                let current = streaming.as_ref().map_or(false, |streaming| {
+                    streaming.frame == index
+                        && streaming.linear == linear
+                        && ptr::eq(streaming.target.as_ptr(), Rc::as_ptr(texture))
+                });
+                if !current {
+                    if let Some(abandoned) = streaming.take() {
+                        abandoned.delete(gl);
+                    }
+                    // pixel buffer objects need GLES 3
+                    if tools::gl_string(gl, ffi::VERSION).starts_with("OpenGL ES 2") {
+                        return None;
+                    }
+                    *streaming = Some(start(gl, texture, context, index, linear, size, rgba.len()));
+                }
+                let current = streaming.as_mut().unwrap();
+
+                let fence = match current.fence {
+                    Some(fence) => fence,
+                    None => {
+                        copy_chunk(gl, current, rgba);
+                        return Some(None);
+                    }
+                };
+                let status = gl.ClientWaitSync(fence, 0, 0);
+                if status != ffi::ALREADY_SIGNALED && status != ffi::CONDITION_SATISFIED {
+                    return Some(None);
+                }
+                let finished = streaming.take().unwrap();
+                gl.DeleteSync(fence);
+                gl.DeleteBuffers(1, &finished.pbo);
+                Some(Some(Gles2Texture::from_raw(renderer, finished.tex, size)))
+            })
+            .map_err(|err| WallpaperError::Render(Box::new(err)))?;
+
+        let imported = match finished {
+            // not streamable, imported like any other image
+            None => return Ok(false),
+            Some(None) => return Ok(true),
+            Some(Some(imported)) => imported,
+        };
+        let mipmaps = tools::generate_mipmaps(r, &imported);
+        *texture.borrow_mut() = Some(CachedTexture {
+            texture: Box::new(imported),
+            frame: index,
+            size,
+            mipmaps,
+            hdr: false,
+            srgb: linear,
+            linear,
+            external: false,
+            compressed: false,
+            context,
+            bytes: tools::texture_bytes(size, 4, mipmaps),
+        });
+        cache::trim();
+        Ok(false)
+    }
+
+    /// Forgets an unfinished upload without deleting it, after the GL context was lost
+    pub fn forget(&self) {
+        self.streaming.borrow_mut().take();
+    }
+
+    /// Deletes the pixel buffer and texture of an unfinished upload of `r`
+    pub fn destroy(&self, r: &mut Gles2Renderer) {
+        let mut streaming = self.streaming.borrow_mut();
+        if streaming
+            .as_ref()
+            .map_or(false, |streaming| streaming.context == context::id(&*r))
+        {
+            let streaming = streaming.take().unwrap();
+            let _ = r.with_context(|_renderer, gl| unsafe { streaming.delete(gl) });
+        }
+    }
+}
+
+/// Creates the pixel buffer of `len` bytes and the texture of a new upload
+unsafe fn start(
+    gl: &ffi::Gles2,
+    texture: &Rc<RefCell<Option<CachedTexture>>>,
+    context: usize,
+    index: usize,
+    linear: bool,
+    size: Size<i32, Buffer>,
+    len: usize,
+) -> Streaming {
+    let mut pbo = 0;
+    gl.GenBuffers(1, &mut pbo);
+    gl.BindBuffer(ffi::PIXEL_UNPACK_BUFFER, pbo);
+    gl.BufferData(
+        ffi::PIXEL_UNPACK_BUFFER,
+        len as isize,
+        ptr::null(),
+        ffi::STREAM_DRAW,
+    );
+    gl.BindBuffer(ffi::PIXEL_UNPACK_BUFFER, 0);
+    let mut tex = 0;
+    gl.GenTextures(1, &mut tex);
+    Streaming {
+        target: Rc::downgrade(texture),
+        context,
+        frame: index,
+        linear,
+        size,
+        pbo,
+        tex,
+        copied: 0,
+        fence: None,
+    }
+}
+
+/// Copies the next chunk of `image` into the pixel buffer of `streaming`, and once all is copied
+/// has the GPU transfer it into the texture
+unsafe fn copy_chunk(gl: &ffi::Gles2, streaming: &mut Streaming, image: &[u8]) {
+    let len = CHUNK.min(image.len() - streaming.copied);
+    gl.BindBuffer(ffi::PIXEL_UNPACK_BUFFER, streaming.pbo);
+    // the buffer is only read once all is written, so there is nothing to wait for
+    let mapped = gl.MapBufferRange(
+        ffi::PIXEL_UNPACK_BUFFER,
+        streaming.copied as isize,
+        len as isize,
+        ffi::MAP_WRITE_BIT | ffi::MAP_UNSYNCHRONIZED_BIT,
+    );
+    if !mapped.is_null() {
+        ptr::copy_nonoverlapping(image[streaming.copied..].as_ptr(), mapped as *mut u8, len);
+        gl.UnmapBuffer(ffi::PIXEL_UNPACK_BUFFER);
+    }
+    streaming.copied += len;
+
+    if streaming.copied == image.len() {
+        let internal = if streaming.linear {
+            tools::SRGB8_ALPHA8
+        } else {
+            ffi::RGBA8
+        };
+        gl.BindTexture(ffi::TEXTURE_2D, streaming.tex);
+        // with the pixel buffer bound the data pointer is an offset into it
+        gl.TexImage2D(
+            ffi::TEXTURE_2D,
+            0,
+            internal as i32,
+            streaming.size.w,
+            streaming.size.h,
+            0,
+            ffi::RGBA,
+            ffi::UNSIGNED_BYTE,
+            ptr::null(),
+        );
+        gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+        gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+        gl.TexParameteri(
+            ffi::TEXTURE_2D,
+            ffi::TEXTURE_WRAP_S,
+            ffi::CLAMP_TO_EDGE as i32,
+        );
+        gl.TexParameteri(
+            ffi::TEXTURE_2D,
+            ffi::TEXTURE_WRAP_T,
+            ffi::CLAMP_TO_EDGE as i32,
+        );
+        gl.BindTexture(ffi::TEXTURE_2D, 0);
+        streaming.fence = Some(gl.FenceSync(ffi::SYNC_GPU_COMMANDS_COMPLETE, 0));
+        gl.Flush();
+    }
+    // the renderer imports from client memory, which a bound pixel buffer would replace
+    gl.BindBuffer(ffi::PIXEL_UNPACK_BUFFER, 0);
+}
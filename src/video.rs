@@ -0,0 +1,242 @@
+#[cfg(feature = "vaapi")]
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+use image::{DynamicImage, RgbaImage};
+#[cfg(feature = "vaapi")]
+use smithay::backend::allocator::{
+    dmabuf::{Dmabuf, DmabufFlags},
+    Fourcc, Modifier,
+};
+
+use crate::WallpaperError;
+
+/// Converts the frames of the VA decoders on the GPU and keeps them there as dmabufs
+#[cfg(feature = "vaapi")]
+const VAAPI_SINK: &str = "vapostproc ! video/x-raw(memory:DMABuf),format={ BGRx, BGRA, RGBx, RGBA, NV12 } ! appsink name=sink max-buffers=1 drop=true";
+/// Converts the frames on the CPU
+const SOFTWARE_SINK: &str =
+    "videoconvert ! video/x-raw,format=RGBA ! appsink name=sink max-buffers=1 drop=true";
+/// Dmabuf frames kept alive, the shown one and the one before it may still be drawn
+#[cfg(feature = "vaapi")]
+const HELD_SAMPLES: usize = 2;
+
+/// A decoded frame of a [`Video`]
+pub(crate) enum Frame {
+    Image(DynamicImage),
+    /// Decoded on the GPU, imported without copying it
+    #[cfg(feature = "vaapi")]
+    Dmabuf(Dmabuf),
+}
+
+/// A looping video decoded by GStreamer into RGBA frames,
+/// or into dmabufs on the GPU with the `vaapi` feature
+#[derive(Debug)]
+pub(crate) struct Video {
+    path: PathBuf,
+    pipeline: gst::Element,
+    sink: gst_app::AppSink,
+    /// Samples of the dmabufs handed out, their memory is reused by the decoder once dropped
+    #[cfg(feature = "vaapi")]
+    held: VecDeque<gst::Sample>,
+}
+
+impl Video {
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        gst::init()?;
+
+        let path = path.canonicalize()?;
+        let uri = gst::glib::filename_to_uri(&path, None)?;
+
+        // without the VA plugin the frames are converted on the CPU
+        #[cfg(feature = "vaapi")]
+        let bin = gst::parse_bin_from_description(VAAPI_SINK, true)
+            .or_else(|_| gst::parse_bin_from_description(SOFTWARE_SINK, true))?;
+        #[cfg(not(feature = "vaapi"))]
+        let bin = gst::parse_bin_from_description(SOFTWARE_SINK, true)?;
+        let sink = bin
+            .by_name("sink")
+            .ok_or("missing appsink")?
+            .downcast::<gst_app::AppSink>()
+            .map_err(|_| "sink is not an appsink")?;
+
+        let pipeline = gst::ElementFactory::make("playbin", None)?;
+        pipeline.set_property("uri", uri.as_str());
+        pipeline.set_property("video-sink", &bin);
+        pipeline.set_property("audio-sink", &gst::ElementFactory::make("fakesink", None)?);
+        pipeline.set_state(gst::State::Playing)?;
+
+        Ok(Self {
+            path,
+            pipeline,
+            sink,
+            #[cfg(feature = "vaapi")]
+            held: VecDeque::new(),
+        })
+    }
+
+    /// Returns the latest decoded frame, if a new one is available,
+    /// or the error of the pipeline or of importing the frame
+    pub fn poll(&mut self) -> Result<Option<Frame>, WallpaperError> {
+        self.handle_messages().map_err(|err| self.error(err))?;
+
+        let sample = match self.sink.try_pull_sample(gst::ClockTime::ZERO) {
+            Some(sample) => sample,
+            None => return Ok(None),
+        };
+        self.frame(sample).map_err(|err| self.error(err))
+    }
+
+    /// Converts the frame in `sample`, `None` if it has no video
+    fn frame(
+        &mut self,
+        sample: gst::Sample,
+    ) -> Result<Option<Frame>, Box<dyn Error + Send + Sync>> {
+        let caps = match sample.caps() {
+            Some(caps) => caps,
+            None => return Ok(None),
+        };
+        let info = gst_video::VideoInfo::from_caps(caps)?;
+
+        #[cfg(feature = "vaapi")]
+        if caps
+            .features(0)
+            .map_or(false, |features| features.contains("memory:DMABuf"))
+        {
+            let dmabuf = to_dmabuf(&sample, &info)?;
+            self.held.push_back(sample);
+            while self.held.len() > HELD_SAMPLES {
+                self.held.pop_front();
+            }
+            return Ok(Some(Frame::Dmabuf(dmabuf)));
+        }
+
+        let buffer = sample.buffer().ok_or("missing buffer")?.map_readable()?;
+
+        let (width, height) = (info.width(), info.height());
+        let stride = info.stride()[0] as usize;
+        let row = width as usize * 4;
+        let data = if stride == row {
+            buffer
+                .as_slice()
+                .get(..row * height as usize)
+                .ok_or("frame smaller than its size")?
+                .to_vec()
+        } else {
+            buffer
+                .as_slice()
+                .chunks(stride)
+                .take(height as usize)
+                .flat_map(|line| &line[..row])
+                .copied()
+                .collect()
+        };
+
+        Ok(RgbaImage::from_raw(width, height, data)
+            .map(DynamicImage::ImageRgba8)
+            .map(Frame::Image))
+    }
+
+    /// Pauses or resumes decoding
+    pub fn set_playing(&self, playing: bool) -> Result<(), WallpaperError> {
+        let state = if playing {
+            gst::State::Playing
+        } else {
+            gst::State::Paused
+        };
+        self.pipeline
+            .set_state(state)
+            .map(|_| ())
+            .map_err(|err| self.error(err.into()))
+    }
+
+    /// Restarts the video at its end, returns the first error of the pipeline
+    fn handle_messages(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let bus = match self.pipeline.bus() {
+            Some(bus) => bus,
+            None => return Ok(()),
+        };
+
+        while let Some(msg) = bus.pop() {
+            match msg.view() {
+                gst::MessageView::Eos(..) => {
+                    self.pipeline.seek_simple(
+                        gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+                        gst::ClockTime::ZERO,
+                    )?;
+                }
+                gst::MessageView::Error(err) => return Err(err.error().into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Wraps `err` of playing this video
+    fn error(&self, err: Box<dyn Error + Send + Sync>) -> WallpaperError {
+        WallpaperError::Video(self.path.clone(), err)
+    }
+}
+
+/// Describes the planes of the dmabuf frame in `sample`, duplicating their file descriptors
+#[cfg(feature = "vaapi")]
+fn to_dmabuf(
+    sample: &gst::Sample,
+    info: &gst_video::VideoInfo,
+) -> Result<Dmabuf, Box<dyn Error + Send + Sync>> {
+    use gstreamer_allocators::DmaBufMemory;
+    use gstreamer_video::VideoFormat;
+
+    let format = match info.format() {
+        // DRM names formats by their bits in a little endian word, GStreamer by their bytes
+        VideoFormat::Bgrx => Fourcc::Xrgb8888,
+        VideoFormat::Bgra => Fourcc::Argb8888,
+        VideoFormat::Rgbx => Fourcc::Xbgr8888,
+        VideoFormat::Rgba => Fourcc::Abgr8888,
+        VideoFormat::Nv12 => Fourcc::Nv12,
+        format => return Err(format!("unsupported format {:?}", format).into()),
+    };
+    let buffer = sample.buffer().ok_or("missing buffer")?;
+    // the decoder may pad planes, which the video meta describes
+    let (offsets, strides) = match buffer.meta::<gst_video::VideoMeta>() {
+        Some(meta) => (meta.offset().to_vec(), meta.stride().to_vec()),
+        None => (info.offset().to_vec(), info.stride().to_vec()),
+    };
+
+    let mut builder = Dmabuf::builder(
+        (info.width() as i32, info.height() as i32),
+        format,
+        DmabufFlags::empty(),
+    );
+    for (index, (offset, stride)) in offsets.into_iter().zip(strides).enumerate() {
+        let (memory, _, skip) = buffer
+            .find_memory(offset, None)
+            .ok_or("plane outside of the buffer")?;
+        let memory = buffer.peek_memory(memory);
+        let fd = memory
+            .downcast_memory_ref::<DmaBufMemory>()
+            .ok_or("frame is not a dmabuf")?
+            .fd();
+        // the dmabuf closes its descriptors, the buffer keeps its own
+        let fd = nix::unistd::dup(fd)?;
+        builder.add_plane(
+            fd,
+            index as u32,
+            (memory.offset() + skip) as u32,
+            stride as u32,
+            Modifier::Invalid,
+        );
+    }
+    Ok(builder.build().ok_or("invalid dmabuf")?)
+}
+
+impl Drop for Video {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Events within this delay are merged, so a file is reloaded once after it was written
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches a wallpaper file for changes
+pub(crate) struct Watch {
+    path: PathBuf,
+    events: Receiver<DebouncedEvent>,
+    _watcher: RecommendedWatcher,
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watch").field("path", &self.path).finish()
+    }
+}
+
+impl Watch {
+    pub fn new(path: &Path) -> notify::Result<Self> {
+        // watch the directory, as many programs replace files instead of writing them
+        let path = path.canonicalize()?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+        let (tx, events) = mpsc::channel();
+        let mut watcher = watcher(tx, DEBOUNCE)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            path,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// The watched file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns `true` if the file was written or replaced since the last poll,
+    /// or the first error of the watcher, the events after it are looked at by the next poll
+    pub fn poll(&self) -> notify::Result<bool> {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            changed |= match event {
+                DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => path == self.path,
+                DebouncedEvent::Rename(_, to) => to == self.path,
+                DebouncedEvent::Error(err, _) => return Err(err),
+                _ => false,
+            };
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use smithay::{
+    backend::renderer::{ImportMem, Renderer},
+    utils::{Logical, Physical, Rectangle, Size, Transform},
+};
+
+use crate::{WallpaperError, WallpaperFrame, WallpaperState};
+
+/// One `WallpaperState` per workspace of an output, switched by the workspace index
+///
+/// Every workspace keeps its texture, so switching between them does not import anything.
+#[derive(Debug, Default)]
+pub struct WorkspaceWallpapers {
+    workspaces: HashMap<usize, WallpaperState>,
+}
+
+impl WorkspaceWallpapers {
+    /// Creates a new `WorkspaceWallpapers` without any wallpapers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the `WallpaperState` of `workspace`, creating it if it does not exist yet
+    pub fn workspace_mut(&mut self, workspace: usize) -> &mut WallpaperState {
+        self.workspaces.entry(workspace).or_default()
+    }
+
+    /// Returns the `WallpaperState` of `workspace`, if it has one
+    pub fn workspace(&self, workspace: usize) -> Option<&WallpaperState> {
+        self.workspaces.get(&workspace)
+    }
+
+    /// Drops the wallpaper of `workspace`
+    pub fn remove(&mut self, workspace: usize) -> Option<WallpaperState> {
+        self.workspaces.remove(&workspace)
+    }
+
+    /// Imports the textures of all workspaces, so switching to them later does not stall
+    pub fn upload<R>(&mut self, r: &mut R) -> Result<(), WallpaperError>
+    where
+        R: Renderer + ImportMem + 'static,
+        <R as Renderer>::TextureId: 'static,
+        <R as Renderer>::Error: Send + Sync,
+    {
+        for state in self.workspaces.values_mut() {
+            state.upload(r)?;
+        }
+        Ok(())
+    }
+
+    /// Produce the frame of the wallpaper of `workspace`, see [`WallpaperState::run`].
+    ///
+    /// Returns `None` if `workspace` has no wallpaper.
+    pub fn run(
+        &mut self,
+        workspace: usize,
+        area: Rectangle<i32, Logical>,
+        size: Size<i32, Physical>,
+        scale: f64,
+        transform: Transform,
+    ) -> Option<WallpaperFrame> {
+        self.workspaces
+            .get_mut(&workspace)
+            .map(|state| state.run(area, size, scale, transform))
+    }
+}